@@ -0,0 +1,50 @@
+use builder::FunctionBuilder;
+use criterion::{criterion_group, criterion_main, Criterion};
+use interpreter::{Interpreter, Program};
+use new_interp::*;
+use value::VariableType;
+
+pub fn benchmark_fibonacci(c: &mut Criterion) {
+    c.bench_function("Recursive fibonacci(20)", |b| {
+        b.iter(|| {
+            let mut main = FunctionBuilder::new(&[], None);
+            let n = main.var::<u64>();
+            let result = main.var::<u64>();
+            main.set_i(n, 20);
+            main.push_parameter(n);
+            main.call(1, result); // fibonacci(n)
+            let main = main.build().unwrap();
+
+            let mut fibonacci = FunctionBuilder::new(&[VariableType::U64], Some(VariableType::U64));
+            let n = fibonacci.parameter::<u64>(0).unwrap();
+            let condition = fibonacci.var::<bool>();
+            let argument = fibonacci.var::<u64>();
+            let fib_1 = fibonacci.var::<u64>();
+            let fib_2 = fibonacci.var::<u64>();
+            let return_n = fibonacci.new_label();
+
+            fibonacci.less_than_i(condition, n, 2);
+            fibonacci.goto_if_true(return_n, condition);
+            fibonacci.set(argument, n);
+            fibonacci.sub_i(argument, 1);
+            fibonacci.push_parameter(argument);
+            fibonacci.call(1, fib_1); // fibonacci(n - 1)
+            fibonacci.sub_i(argument, 1);
+            fibonacci.push_parameter(argument);
+            fibonacci.call(1, fib_2); // fibonacci(n - 2)
+            fibonacci.add(fib_1, fib_2);
+            fibonacci.ret(fib_1);
+            fibonacci.bind(return_n);
+            fibonacci.ret(n);
+            let fibonacci = fibonacci.build().unwrap();
+
+            let program = Program::new(&[main, fibonacci]);
+            let mut interpreter = Interpreter::new(program).unwrap();
+
+            interpreter.execute().unwrap();
+        });
+    });
+}
+
+criterion_group!(benches, benchmark_fibonacci);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use new_interp::fuzzing::{program_from_bytes, run_program};
+
+fuzz_target!(|data: &[u8]| {
+    // malformed definitions are rejected like any other invalid program
+    if let Ok(program) = program_from_bytes(data) {
+        run_program(program);
+    }
+});
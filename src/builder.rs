@@ -0,0 +1,469 @@
+use std::{fmt, marker::PhantomData};
+
+use crate::{
+    instructions::Instruction,
+    interpreter::Function,
+    value::{ArrayValue, FunctionIdType, Value, VariableIdType, VariableType},
+};
+
+/// A position in a function's instructions that jumps can target before it is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+/// A variable allocated by a `FunctionBuilder`, whose type is only checked when the program runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Variable(VariableIdType);
+
+impl Variable {
+    pub fn id(self) -> VariableIdType {
+        self.0
+    }
+}
+
+/// A Rust type standing in for a `VariableType`, so that `Var` handles are checked at compile time.
+pub trait VarType {
+    fn variable_type() -> VariableType;
+    fn into_value(self) -> Value;
+    /// Packs values of this type into the matching array.
+    fn into_array(values: Vec<Self>) -> ArrayValue
+    where
+        Self: Sized;
+}
+
+/// Types accepted by arithmetic and ordering instructions.
+pub trait Numeric: VarType {}
+
+macro_rules! scalar_var_type {
+    ($rust_type:ty, $variable_type:ident, $array:ident) => {
+        impl VarType for $rust_type {
+            fn variable_type() -> VariableType {
+                VariableType::$variable_type
+            }
+            fn into_value(self) -> Value {
+                Value::$variable_type(self)
+            }
+            fn into_array(values: Vec<Self>) -> ArrayValue {
+                ArrayValue::$array(values)
+            }
+        }
+    };
+}
+
+scalar_var_type!(u8, U8, U8Array);
+scalar_var_type!(u16, U16, U16Array);
+scalar_var_type!(u32, U32, U32Array);
+scalar_var_type!(u64, U64, U64Array);
+scalar_var_type!(bool, Bool, BoolArray);
+scalar_var_type!(String, String, StringArray);
+scalar_var_type!(char, Char, CharArray);
+
+impl Numeric for u8 {}
+impl Numeric for u16 {}
+impl Numeric for u32 {}
+impl Numeric for u64 {}
+
+impl<T: VarType> VarType for Vec<T> {
+    fn variable_type() -> VariableType {
+        VariableType::Array(Box::new(T::variable_type()))
+    }
+    fn into_value(self) -> Value {
+        Value::Array(T::into_array(self))
+    }
+    fn into_array(values: Vec<Self>) -> ArrayValue {
+        ArrayValue::ArrayArray(T::variable_type(), values.into_iter().map(T::into_array).collect())
+    }
+}
+
+/// A variable of type `T`, allocated by `FunctionBuilder::var`.
+pub struct Var<T> {
+    id: VariableIdType,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Var<T> {
+    fn new(id: VariableIdType) -> Self {
+        Var { id, _type: PhantomData }
+    }
+    pub fn id(self) -> VariableIdType {
+        self.id
+    }
+}
+
+// derived impls would require `T` itself to implement each trait
+impl<T> Clone for Var<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Var<T> {}
+impl<T> PartialEq for Var<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+impl<T> Eq for Var<T> {}
+impl<T: VarType> fmt::Debug for Var<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Var<{}>({})", T::variable_type(), self.id)
+    }
+}
+
+impl<T> From<Var<T>> for Variable {
+    fn from(var: Var<T>) -> Self {
+        Variable(var.id)
+    }
+}
+
+/// A variable a conditional jump can test: a `Var<bool>`, or a `Variable` that is checked when the jump runs.
+pub trait Condition {
+    fn condition_id(&self) -> VariableIdType;
+}
+
+impl Condition for Var<bool> {
+    fn condition_id(&self) -> VariableIdType {
+        self.id
+    }
+}
+
+impl Condition for Variable {
+    fn condition_id(&self) -> VariableIdType {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// A jump targets a label that was never bound.
+    UnboundLabel(Label),
+    LabelBoundTwice(Label),
+    /// More variables were allocated than there are variable ids.
+    TooManyVariables,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::UnboundLabel(Label(label)) => write!(f, "label {label} is jumped to but never bound"),
+            BuildError::LabelBoundTwice(Label(label)) => write!(f, "label {label} is bound more than once"),
+            BuildError::TooManyVariables => write!(f, "function has more than {} variables", VariableIdType::MAX as usize + 1),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builds a `Function`, resolving jumps to labels once every instruction is in place.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionBuilder {
+    name: Option<String>,
+    parameters: Vec<VariableType>,
+    return_type: Option<VariableType>,
+    // variables after the parameters
+    locals: Vec<VariableType>,
+    instructions: Vec<Instruction>,
+    labels: Vec<Option<usize>>,
+    // jump instructions, the entry of a switch's jump table, and the label each one targets
+    jumps: Vec<(usize, usize, Label)>,
+    // the first misuse, reported by `build`
+    error: Option<BuildError>,
+}
+
+impl FunctionBuilder {
+    pub fn new(parameters: &[VariableType], return_type: Option<VariableType>) -> Self {
+        FunctionBuilder {
+            parameters: parameters.to_vec(),
+            return_type,
+            ..Default::default()
+        }
+    }
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+    /// The variables holding the parameters, in order.
+    pub fn parameters(&self) -> Vec<Variable> {
+        (0..self.parameters.len()).map(|id| Variable(id as VariableIdType)).collect()
+    }
+    /// The parameter at `index`, if it exists and has type `T`.
+    pub fn parameter<T: VarType>(&self, index: usize) -> Option<Var<T>> {
+        match self.parameters.get(index) {
+            Some(var_type) if *var_type == T::variable_type() => Some(Var::new(index as VariableIdType)),
+            _ => None,
+        }
+    }
+    pub fn variable(&mut self, var_type: VariableType) -> Variable {
+        let Ok(id) = VariableIdType::try_from(self.parameters.len() + self.locals.len()) else {
+            self.error.get_or_insert(BuildError::TooManyVariables);
+            return Variable(VariableIdType::MAX);
+        };
+        self.locals.push(var_type);
+        Variable(id)
+    }
+    pub fn var<T: VarType>(&mut self) -> Var<T> {
+        Var::new(self.variable(T::variable_type()).0)
+    }
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+    /// Makes `label` refer to the next instruction pushed.
+    pub fn bind(&mut self, label: Label) {
+        match self.labels[label.0] {
+            Some(_) => {
+                self.error.get_or_insert(BuildError::LabelBoundTwice(label));
+            }
+            None => self.labels[label.0] = Some(self.instructions.len()),
+        }
+    }
+    pub fn push(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+    pub fn goto(&mut self, label: Label) {
+        self.jumps.push((self.instructions.len(), 0, label));
+        self.instructions.push(Instruction::Goto(0));
+    }
+    pub fn goto_if_true(&mut self, label: Label, condition: impl Condition) {
+        self.jumps.push((self.instructions.len(), 0, label));
+        self.instructions.push(Instruction::GotoIfTrue(0, condition.condition_id()));
+    }
+    /// Jumps to `targets[i]` for an enum with tag `i` or an integer equal to `i`, otherwise continues.
+    pub fn switch(&mut self, var: impl Into<Variable>, targets: &[Label]) {
+        for (entry, label) in targets.iter().enumerate() {
+            self.jumps.push((self.instructions.len(), entry, *label));
+        }
+        self.instructions.push(Instruction::Switch(var.into().id(), vec![0; targets.len()]));
+    }
+
+    //
+    // TYPED INSTRUCTIONS
+    //
+    pub fn set<T>(&mut self, to: Var<T>, from: Var<T>) {
+        self.push(Instruction::Set(to.id, from.id));
+    }
+    pub fn set_i<T: VarType>(&mut self, var: Var<T>, value: T) {
+        self.push(Instruction::SetI(var.id, value.into_value()));
+    }
+    pub fn get_array_index<T, I: Numeric>(&mut self, array: Var<Vec<T>>, dest: Var<T>, index: Var<I>) {
+        self.push(Instruction::GetArrayIndex(array.id, dest.id, index.id));
+    }
+    pub fn set_array_index<T, I: Numeric>(&mut self, array: Var<Vec<T>>, index: Var<I>, value: Var<T>) {
+        self.push(Instruction::SetArrayIndex(array.id, index.id, value.id));
+    }
+    pub fn add<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::Add(lvalue.id, rvalue.id));
+    }
+    pub fn add_i<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::AddI(lvalue.id, rvalue.into_value()));
+    }
+    pub fn sub<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::Sub(lvalue.id, rvalue.id));
+    }
+    pub fn sub_i<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::SubI(lvalue.id, rvalue.into_value()));
+    }
+    pub fn mul<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::Mul(lvalue.id, rvalue.id));
+    }
+    pub fn mul_i<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::MulI(lvalue.id, rvalue.into_value()));
+    }
+    pub fn div<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::Div(lvalue.id, rvalue.id));
+    }
+    pub fn div_i<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::DivI(lvalue.id, rvalue.into_value()));
+    }
+    pub fn rem<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::Rem(lvalue.id, rvalue.id));
+    }
+    pub fn rem_i<T: Numeric>(&mut self, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::RemI(lvalue.id, rvalue.into_value()));
+    }
+    pub fn less_than<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::LessThan(dest.id, lvalue.id, rvalue.id));
+    }
+    pub fn less_than_i<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::LessThanI(dest.id, lvalue.id, rvalue.into_value()));
+    }
+    pub fn greater_than<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::GreaterThan(dest.id, lvalue.id, rvalue.id));
+    }
+    pub fn greater_than_i<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::GreaterThanI(dest.id, lvalue.id, rvalue.into_value()));
+    }
+    pub fn less_than_or_equal<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::LessThanOrEqual(dest.id, lvalue.id, rvalue.id));
+    }
+    pub fn less_than_or_equal_i<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::LessThanOrEqualI(dest.id, lvalue.id, rvalue.into_value()));
+    }
+    pub fn greater_than_or_equal<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::GreaterThanOrEqual(dest.id, lvalue.id, rvalue.id));
+    }
+    pub fn greater_than_or_equal_i<T: Numeric>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::GreaterThanOrEqualI(dest.id, lvalue.id, rvalue.into_value()));
+    }
+    pub fn equals<T>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::Equals(dest.id, lvalue.id, rvalue.id));
+    }
+    pub fn equals_i<T: VarType>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::EqualsI(dest.id, lvalue.id, rvalue.into_value()));
+    }
+    pub fn not_equals<T>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: Var<T>) {
+        self.push(Instruction::NotEquals(dest.id, lvalue.id, rvalue.id));
+    }
+    pub fn not_equals_i<T: VarType>(&mut self, dest: Var<bool>, lvalue: Var<T>, rvalue: T) {
+        self.push(Instruction::NotEqualsI(dest.id, lvalue.id, rvalue.into_value()));
+    }
+    pub fn and(&mut self, lvalue: Var<bool>, rvalue: Var<bool>) {
+        self.push(Instruction::And(lvalue.id, rvalue.id));
+    }
+    pub fn or(&mut self, lvalue: Var<bool>, rvalue: Var<bool>) {
+        self.push(Instruction::Or(lvalue.id, rvalue.id));
+    }
+    pub fn xor(&mut self, lvalue: Var<bool>, rvalue: Var<bool>) {
+        self.push(Instruction::Xor(lvalue.id, rvalue.id));
+    }
+    pub fn not(&mut self, var: Var<bool>) {
+        self.push(Instruction::Not(var.id));
+    }
+    pub fn push_parameter<T>(&mut self, var: Var<T>) {
+        self.push(Instruction::PushFunctionParameter(var.id));
+    }
+    pub fn call<T>(&mut self, function_id: FunctionIdType, dest: Var<T>) {
+        self.push(Instruction::CallFunction(function_id, dest.id));
+    }
+    pub fn call_void(&mut self, function_id: FunctionIdType) {
+        self.push(Instruction::CallVoidFunction(function_id));
+    }
+    pub fn ret<T>(&mut self, var: Var<T>) {
+        self.push(Instruction::Return(var.id));
+    }
+
+    pub fn build(mut self) -> Result<Function, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        for (index, entry, label) in self.jumps.iter() {
+            let Some(target) = self.labels[label.0] else {
+                return Err(BuildError::UnboundLabel(*label));
+            };
+            match &mut self.instructions[*index] {
+                Instruction::Goto(instruction_number) | Instruction::GotoIfTrue(instruction_number, _) => *instruction_number = target,
+                Instruction::Switch(_, jump_table) => jump_table[*entry] = target,
+                _ => unreachable!("only jumps are recorded"),
+            }
+        }
+
+        let mut function = Function::new(&self.parameters, self.return_type);
+        function
+            .register_variables(&self.locals)
+            .map_err(|_| BuildError::TooManyVariables)?;
+        if let Some(name) = self.name {
+            function.set_name(&name);
+        }
+        function.set_instructions(self.instructions);
+        Ok(function)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        builder::*,
+        interpreter::{ExecutionStatus, Interpreter, Program},
+    };
+
+    #[test]
+    fn test_labels_resolve() {
+        let mut builder = FunctionBuilder::new(&[], Some(VariableType::U64));
+        let counter = builder.variable(VariableType::U64);
+        let condition = builder.variable(VariableType::Bool);
+        let top = builder.new_label();
+        let done = builder.new_label();
+        builder.bind(top);
+        builder.push(Instruction::AddI(counter.id(), Value::U64(1)));
+        builder.push(Instruction::EqualsI(condition.id(), counter.id(), Value::U64(10)));
+        builder.goto_if_true(done, condition);
+        builder.goto(top);
+        builder.bind(done);
+        builder.push(Instruction::Return(counter.id()));
+        let function = builder.build().unwrap();
+        assert!(matches!(function.instructions()[2], Instruction::GotoIfTrue(4, _)));
+        assert!(matches!(function.instructions()[3], Instruction::Goto(0)));
+
+        let mut interpreter = Interpreter::new(Program::new(&[function])).unwrap();
+        assert!(matches!(
+            interpreter.run_for(u64::MAX),
+            ExecutionStatus::Finished(Some(Value::U64(10)))
+        ));
+    }
+
+    #[test]
+    fn test_switch_labels() {
+        let mut builder = FunctionBuilder::new(&[VariableType::U8], Some(VariableType::U8));
+        let value = builder.parameter::<u8>(0).unwrap();
+        let (zero, one) = (builder.new_label(), builder.new_label());
+        builder.switch(value, &[zero, one, zero]);
+        builder.add_i(value, 10);
+        builder.bind(one);
+        builder.add_i(value, 100);
+        builder.bind(zero);
+        builder.ret(value);
+        let function = builder.build().unwrap();
+        assert!(matches!(&function.instructions()[0], Instruction::Switch(0, table) if *table == vec![3, 2, 3]));
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let mut builder = FunctionBuilder::new(&[], None);
+        let label = builder.new_label();
+        builder.goto(label);
+        assert_eq!(builder.build().unwrap_err(), BuildError::UnboundLabel(label));
+
+        let mut builder = FunctionBuilder::new(&[], None);
+        let label = builder.new_label();
+        builder.bind(label);
+        builder.bind(label);
+        assert_eq!(builder.build().unwrap_err(), BuildError::LabelBoundTwice(label));
+    }
+
+    #[test]
+    fn test_typed_variables() {
+        let mut builder = FunctionBuilder::new(&[VariableType::U64], Some(VariableType::Array(Box::new(VariableType::U64))));
+        let limit = builder.parameter::<u64>(0).unwrap();
+        assert!(builder.parameter::<bool>(0).is_none());
+        let squares = builder.var::<Vec<u64>>();
+        let index = builder.var::<u64>();
+        let square = builder.var::<u64>();
+        let done = builder.var::<bool>();
+        assert_eq!(squares.id(), 1);
+        assert_eq!(done.id(), 4);
+
+        let top = builder.new_label();
+        builder.set_i(squares, vec![0u64; 4]);
+        builder.bind(top);
+        builder.set(square, index);
+        builder.mul(square, index);
+        builder.set_array_index(squares, index, square);
+        builder.add_i(index, 1);
+        builder.less_than(done, index, limit);
+        builder.goto_if_true(top, done);
+        builder.ret(squares);
+        let function = builder.build().unwrap();
+
+        let mut main = FunctionBuilder::new(&[], Some(VariableType::Array(Box::new(VariableType::U64))));
+        let limit = main.var::<u64>();
+        let result = main.var::<Vec<u64>>();
+        main.set_i(limit, 4);
+        main.push_parameter(limit);
+        main.call(1, result);
+        main.ret(result);
+        let main = main.build().unwrap();
+
+        let mut interpreter = Interpreter::new(Program::new(&[main, function])).unwrap();
+        let ExecutionStatus::Finished(Some(result)) = interpreter.run_for(u64::MAX) else {
+            panic!("program did not finish");
+        };
+        assert_eq!(result, Value::Array(ArrayValue::U64Array(vec![0, 1, 4, 9])));
+    }
+}
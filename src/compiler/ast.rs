@@ -0,0 +1,103 @@
+use crate::value::VariableType;
+
+/// A line and column in the source, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFunction {
+    pub name: String,
+    pub parameters: Vec<(String, VariableType)>,
+    pub return_type: Option<VariableType>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Let {
+        name: String,
+        var_type: Option<VariableType>,
+        value: Expr,
+    },
+    Assign {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    AssignIndex {
+        name: String,
+        index: Expr,
+        value: Expr,
+        span: Span,
+    },
+    If {
+        condition: Expr,
+        then_body: Vec<Stmt>,
+        else_body: Vec<Stmt>,
+    },
+    While {
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    For {
+        name: String,
+        start: Expr,
+        end: Expr,
+        body: Vec<Stmt>,
+    },
+    Return {
+        value: Option<Expr>,
+        span: Span,
+    },
+    Break(Span),
+    Continue(Span),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Equals,
+    NotEquals,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    /// Filled in by type checking. `None` for calls to functions that return nothing.
+    pub ty: Option<VariableType>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Integer(u64),
+    Bool(bool),
+    String(String),
+    Array(Vec<Expr>),
+    Variable(String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span, ty: None }
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+
+use crate::{
+    builder::{FunctionBuilder, Label, Variable},
+    compiler::{
+        ast::{BinaryOp, Expr, ExprKind, SourceFunction, Span, Stmt},
+        typeck::Signature,
+        CompileError,
+    },
+    instructions::Instruction,
+    interpreter::Function,
+    native,
+    value::{ArrayValue, Value, VariableType},
+};
+
+/// An expression's result. Temporaries may be modified in place, named variables must be copied first.
+#[derive(Debug, Clone, Copy)]
+struct Operand {
+    variable: Variable,
+    temporary: bool,
+}
+
+struct Loop {
+    continue_label: Label,
+    break_label: Label,
+}
+
+struct Generator<'a> {
+    builder: FunctionBuilder,
+    signatures: &'a HashMap<String, Signature>,
+    scopes: Vec<HashMap<String, Variable>>,
+    loops: Vec<Loop>,
+    end: Label,
+}
+
+/// The value of a literal that needs no instructions to compute.
+fn constant(expr: &Expr) -> Option<Value> {
+    Some(match &expr.kind {
+        ExprKind::Integer(value) => match expr.ty.as_ref()? {
+            VariableType::U8 => Value::U8(*value as u8),
+            VariableType::U16 => Value::U16(*value as u16),
+            VariableType::U32 => Value::U32(*value as u32),
+            _ => Value::U64(*value),
+        },
+        ExprKind::Bool(value) => Value::Bool(*value),
+        ExprKind::String(value) => Value::String(value.clone()),
+        ExprKind::Array(items) => {
+            let Some(VariableType::Array(inner)) = &expr.ty else {
+                return None;
+            };
+            let mut array = ArrayValue::new(inner.as_ref().clone());
+            for item in items {
+                array.push(constant(item)?).ok()?;
+            }
+            Value::Array(array)
+        }
+        _ => return None,
+    })
+}
+
+fn typed(expr: &Expr) -> VariableType {
+    expr.ty.clone().expect("expressions producing values are typed")
+}
+
+impl Generator<'_> {
+    fn lookup(&self, name: &str) -> Variable {
+        *self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .expect("variables are resolved by the type checker")
+    }
+    fn declare(&mut self, name: &str, variable: Variable) {
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope")
+            .insert(name.to_string(), variable);
+    }
+    fn temporary(&mut self, var_type: VariableType) -> Operand {
+        Operand {
+            variable: self.builder.variable(var_type),
+            temporary: true,
+        }
+    }
+    /// A temporary holding the value of `expr`, safe to modify.
+    fn owned(&mut self, expr: &Expr) -> Variable {
+        let operand = self.value(expr);
+        self.own(operand, typed(expr))
+    }
+    /// `operand` itself when it is a temporary, otherwise a copy of it.
+    fn own(&mut self, operand: Operand, var_type: VariableType) -> Variable {
+        if operand.temporary {
+            return operand.variable;
+        }
+        let copy = self.builder.variable(var_type);
+        self.builder.push(Instruction::Set(copy.id(), operand.variable.id()));
+        copy
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Let { name, value, .. } => {
+                // a temporary can take the name, anything else is copied so the two can change independently
+                let variable = self.owned(value);
+                self.declare(name, variable);
+            }
+            Stmt::Assign { name, value, .. } => {
+                let target = self.lookup(name);
+                match constant(value) {
+                    Some(value) => self.builder.push(Instruction::SetI(target.id(), value)),
+                    None => {
+                        let value = self.value(value);
+                        self.builder.push(Instruction::Set(target.id(), value.variable.id()));
+                    }
+                }
+            }
+            Stmt::AssignIndex { name, index, value, .. } => {
+                let array = self.lookup(name).id();
+                match (constant(index), constant(value)) {
+                    (Some(index), _) => {
+                        let value = self.value(value).variable.id();
+                        self.builder.push(Instruction::SetArrayIIndex(array, index, value));
+                    }
+                    (None, Some(value)) => {
+                        let index = self.value(index).variable.id();
+                        self.builder.push(Instruction::SetArrayIndexI(array, index, value));
+                    }
+                    (None, None) => {
+                        let index = self.value(index).variable.id();
+                        let value = self.value(value).variable.id();
+                        self.builder.push(Instruction::SetArrayIndex(array, index, value));
+                    }
+                }
+            }
+            Stmt::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                let then_label = self.builder.new_label();
+                let end_label = self.builder.new_label();
+                let condition = self.value(condition).variable;
+                self.builder.goto_if_true(then_label, condition);
+                self.block(else_body);
+                self.builder.goto(end_label);
+                self.builder.bind(then_label);
+                self.block(then_body);
+                self.builder.bind(end_label);
+            }
+            Stmt::While { condition, body } => {
+                let body_label = self.builder.new_label();
+                let check_label = self.builder.new_label();
+                let break_label = self.builder.new_label();
+                self.builder.goto(check_label);
+                self.builder.bind(body_label);
+                self.loop_body(body, check_label, break_label);
+                self.builder.bind(check_label);
+                let condition = self.value(condition).variable;
+                self.builder.goto_if_true(body_label, condition);
+                self.builder.bind(break_label);
+            }
+            Stmt::For { name, start, end, body } => {
+                let var_type = typed(start);
+                let counter = self.owned(start);
+                // the end is evaluated once, before the first iteration
+                let end = self.owned(end);
+                let condition = self.builder.variable(VariableType::Bool);
+                let body_label = self.builder.new_label();
+                let continue_label = self.builder.new_label();
+                let check_label = self.builder.new_label();
+                let break_label = self.builder.new_label();
+                self.builder.goto(check_label);
+                self.builder.bind(body_label);
+                self.scopes.push(HashMap::from([(name.clone(), counter)]));
+                self.loop_body(body, continue_label, break_label);
+                self.scopes.pop();
+                self.builder.bind(continue_label);
+                self.builder.push(Instruction::AddI(counter.id(), one(&var_type)));
+                self.builder.bind(check_label);
+                self.builder.push(Instruction::LessThan(condition.id(), counter.id(), end.id()));
+                self.builder.goto_if_true(body_label, condition);
+                self.builder.bind(break_label);
+            }
+            Stmt::Return { value: Some(value), .. } => {
+                let value = self.value(value).variable;
+                self.builder.push(Instruction::Return(value.id()));
+            }
+            Stmt::Return { value: None, .. } => self.builder.goto(self.end),
+            Stmt::Break(_) => {
+                let label = self.loops.last().expect("checked to be inside a loop").break_label;
+                self.builder.goto(label);
+            }
+            Stmt::Continue(_) => {
+                let label = self.loops.last().expect("checked to be inside a loop").continue_label;
+                self.builder.goto(label);
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+            }
+        }
+    }
+
+    fn loop_body(&mut self, body: &[Stmt], continue_label: Label, break_label: Label) {
+        self.loops.push(Loop {
+            continue_label,
+            break_label,
+        });
+        self.block(body);
+        self.loops.pop();
+    }
+
+    fn value(&mut self, expr: &Expr) -> Operand {
+        self.expr(expr).expect("void calls are rejected by the type checker")
+    }
+
+    /// Emits the instructions computing `expr`, returning where the result is stored.
+    fn expr(&mut self, expr: &Expr) -> Option<Operand> {
+        if let Some(value) = constant(expr) {
+            let operand = self.temporary(typed(expr));
+            self.builder.push(Instruction::SetI(operand.variable.id(), value));
+            return Some(operand);
+        }
+        Some(match &expr.kind {
+            ExprKind::Integer(_) | ExprKind::Bool(_) | ExprKind::String(_) => unreachable!("literals are constant"),
+            ExprKind::Array(items) => {
+                let array_type = typed(expr);
+                let array = self.temporary(array_type.clone());
+                // start empty, the same instructions can run again in a loop
+                self.builder
+                    .push(Instruction::SetI(array.variable.id(), array_type.default_value(&[], &[])));
+                for item in items {
+                    let item = self.value(item).variable;
+                    self.builder.push(Instruction::PushFunctionParameter(item.id()));
+                    self.builder
+                        .push(Instruction::CallNativeVoidMethod(array.variable.id(), native::ARRAY_PUSH));
+                }
+                array
+            }
+            ExprKind::Variable(name) => Operand {
+                variable: self.lookup(name),
+                temporary: false,
+            },
+            ExprKind::Index(base, index) => {
+                let base = self.value(base).variable.id();
+                let dest = self.temporary(typed(expr));
+                match constant(index) {
+                    Some(index) => self.builder.push(Instruction::GetArrayIndexI(base, dest.variable.id(), index)),
+                    None => {
+                        let index = self.value(index).variable.id();
+                        self.builder.push(Instruction::GetArrayIndex(base, dest.variable.id(), index));
+                    }
+                }
+                dest
+            }
+            ExprKind::Call(name, arguments) => return self.call(name, arguments, expr.ty.clone()),
+            ExprKind::Not(inner) => {
+                let variable = self.owned(inner);
+                self.builder.push(Instruction::Not(variable.id()));
+                Operand { variable, temporary: true }
+            }
+            ExprKind::Binary(..) => self.binary_chain(expr),
+        })
+    }
+
+    /// Emits a binary expression. Operators are left associative, so a long chain such as `a + b + c + ...` is walked
+    /// down its left operands and emitted innermost first, instead of recursing once per operator.
+    fn binary_chain(&mut self, expr: &Expr) -> Operand {
+        let mut chain = Vec::new();
+        let mut innermost = expr;
+        while let ExprKind::Binary(_, lhs, _) = &innermost.kind {
+            chain.push(innermost);
+            innermost = lhs;
+        }
+        let mut operand = self.value(innermost);
+        for node in chain.into_iter().rev() {
+            let ExprKind::Binary(op, lhs, rhs) = &node.kind else {
+                unreachable!("the chain holds binary expressions");
+            };
+            operand = self.binary(*op, operand, typed(lhs), rhs);
+        }
+        operand
+    }
+
+    /// Emits `op` applied to the already computed `lhs`, of type `lhs_type`, and `rhs`.
+    fn binary(&mut self, op: BinaryOp, lhs: Operand, lhs_type: VariableType, rhs: &Expr) -> Operand {
+        if let BinaryOp::And | BinaryOp::Or = op {
+            let result = self.own(lhs, lhs_type);
+            let end_label = self.builder.new_label();
+            if op == BinaryOp::And {
+                let skip = self.builder.variable(VariableType::Bool);
+                self.builder.push(Instruction::Set(skip.id(), result.id()));
+                self.builder.push(Instruction::Not(skip.id()));
+                self.builder.goto_if_true(end_label, skip);
+            } else {
+                self.builder.goto_if_true(end_label, result);
+            }
+            let rhs = self.value(rhs).variable;
+            self.builder.push(Instruction::Set(result.id(), rhs.id()));
+            self.builder.bind(end_label);
+            return Operand {
+                variable: result,
+                temporary: true,
+            };
+        }
+        let in_place = |l, r| match op {
+            BinaryOp::Add => Instruction::Add(l, r),
+            BinaryOp::Sub => Instruction::Sub(l, r),
+            BinaryOp::Mul => Instruction::Mul(l, r),
+            BinaryOp::Div => Instruction::Div(l, r),
+            _ => Instruction::Rem(l, r),
+        };
+        let in_place_i = |l, r| match op {
+            BinaryOp::Add => Instruction::AddI(l, r),
+            BinaryOp::Sub => Instruction::SubI(l, r),
+            BinaryOp::Mul => Instruction::MulI(l, r),
+            BinaryOp::Div => Instruction::DivI(l, r),
+            _ => Instruction::RemI(l, r),
+        };
+        let compare = |d, l, r| match op {
+            BinaryOp::Less => Instruction::LessThan(d, l, r),
+            BinaryOp::Greater => Instruction::GreaterThan(d, l, r),
+            BinaryOp::LessOrEqual => Instruction::LessThanOrEqual(d, l, r),
+            BinaryOp::GreaterOrEqual => Instruction::GreaterThanOrEqual(d, l, r),
+            BinaryOp::Equals => Instruction::Equals(d, l, r),
+            _ => Instruction::NotEquals(d, l, r),
+        };
+        let compare_i = |d, l, r| match op {
+            BinaryOp::Less => Instruction::LessThanI(d, l, r),
+            BinaryOp::Greater => Instruction::GreaterThanI(d, l, r),
+            BinaryOp::LessOrEqual => Instruction::LessThanOrEqualI(d, l, r),
+            BinaryOp::GreaterOrEqual => Instruction::GreaterThanOrEqualI(d, l, r),
+            BinaryOp::Equals => Instruction::EqualsI(d, l, r),
+            _ => Instruction::NotEqualsI(d, l, r),
+        };
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                let result = self.own(lhs, lhs_type);
+                match constant(rhs) {
+                    Some(value) => self.builder.push(in_place_i(result.id(), value)),
+                    None => {
+                        let rhs = self.value(rhs).variable.id();
+                        self.builder.push(in_place(result.id(), rhs));
+                    }
+                }
+                Operand {
+                    variable: result,
+                    temporary: true,
+                }
+            }
+            _ => {
+                let lhs = lhs.variable.id();
+                let dest = self.temporary(VariableType::Bool);
+                match constant(rhs) {
+                    Some(value) => self.builder.push(compare_i(dest.variable.id(), lhs, value)),
+                    None => {
+                        let rhs = self.value(rhs).variable.id();
+                        self.builder.push(compare(dest.variable.id(), lhs, rhs));
+                    }
+                }
+                dest
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expr], return_type: Option<VariableType>) -> Option<Operand> {
+        // every argument is computed before any is pushed, so calls inside arguments see an empty parameter stack
+        let arguments: Vec<Variable> = arguments.iter().map(|argument| self.value(argument).variable).collect();
+        match name {
+            "len" => {
+                let dest = self.temporary(VariableType::U64);
+                self.builder
+                    .push(Instruction::CallNativeMethod(arguments[0].id(), dest.variable.id(), native::LEN));
+                return Some(dest);
+            }
+            "push" => {
+                self.builder.push(Instruction::PushFunctionParameter(arguments[1].id()));
+                self.builder
+                    .push(Instruction::CallNativeVoidMethod(arguments[0].id(), native::ARRAY_PUSH));
+                return None;
+            }
+            "println" => {
+                self.builder.push(Instruction::PushFunctionParameter(arguments[0].id()));
+                self.builder.push(Instruction::CallNativeVoidFunction(native::PRINTLN));
+                return None;
+            }
+            _ => {}
+        }
+        let function_id = self.signatures[name].id;
+        for argument in arguments {
+            self.builder.push(Instruction::PushFunctionParameter(argument.id()));
+        }
+        match return_type {
+            Some(return_type) => {
+                let dest = self.temporary(return_type);
+                self.builder.push(Instruction::CallFunction(function_id, dest.variable.id()));
+                Some(dest)
+            }
+            None => {
+                self.builder.push(Instruction::CallVoidFunction(function_id));
+                None
+            }
+        }
+    }
+}
+
+fn one(var_type: &VariableType) -> Value {
+    match var_type {
+        VariableType::U8 => Value::U8(1),
+        VariableType::U16 => Value::U16(1),
+        VariableType::U32 => Value::U32(1),
+        _ => Value::U64(1),
+    }
+}
+
+/// Generates the instructions for a type checked function.
+pub fn generate(function: &SourceFunction, signatures: &HashMap<String, Signature>) -> Result<Function, CompileError> {
+    let parameter_types: Vec<VariableType> = function.parameters.iter().map(|(_, var_type)| var_type.clone()).collect();
+    let mut builder = FunctionBuilder::new(&parameter_types, function.return_type.clone());
+    builder.set_name(&function.name);
+    let parameters = function
+        .parameters
+        .iter()
+        .zip(builder.parameters())
+        .map(|((name, _), variable)| (name.clone(), variable))
+        .collect();
+    let end = builder.new_label();
+    let mut generator = Generator {
+        builder,
+        signatures,
+        scopes: vec![parameters],
+        loops: Vec::new(),
+        end,
+    };
+    generator.block(&function.body);
+    // `return;` jumps past the last instruction, which ends the function
+    generator.builder.bind(end);
+    generator
+        .builder
+        .build()
+        .map_err(|error| CompileError::new(format!("in function `{}`: {error}", function.name), Span::default()))
+}
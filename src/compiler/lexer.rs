@@ -0,0 +1,250 @@
+use crate::compiler::{ast::Span, CompileError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Ident(String),
+    Integer(u64),
+    String(String),
+    // keywords
+    Fn,
+    Let,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    Break,
+    Continue,
+    True,
+    False,
+    // punctuation
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Colon,
+    Semicolon,
+    Arrow,
+    DotDot,
+    Assign,
+    Equals,
+    NotEquals,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Bang,
+    AndAnd,
+    OrOr,
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            return true;
+        }
+        false
+    }
+    fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.next() != Some('/') {
+                        return;
+                    }
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn string(&mut self, span: Span) -> Result<TokenKind, CompileError> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(CompileError::new("unterminated string", span)),
+                Some('"') => return Ok(TokenKind::String(value)),
+                Some('\\') => match self.bump() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('\\') => value.push('\\'),
+                    Some('"') => value.push('"'),
+                    _ => return Err(CompileError::new("unknown escape in string", span)),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Token, CompileError> {
+        self.skip_whitespace_and_comments();
+        let span = self.span();
+        let Some(c) = self.bump() else {
+            return Ok(Token {
+                kind: TokenKind::Eof,
+                span,
+            });
+        };
+        let kind = match c {
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            '{' => TokenKind::LeftBrace,
+            '}' => TokenKind::RightBrace,
+            '[' => TokenKind::LeftBracket,
+            ']' => TokenKind::RightBracket,
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            ';' => TokenKind::Semicolon,
+            '+' => TokenKind::Plus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
+            '-' if self.eat('>') => TokenKind::Arrow,
+            '-' => TokenKind::Minus,
+            '.' if self.eat('.') => TokenKind::DotDot,
+            '=' if self.eat('=') => TokenKind::Equals,
+            '=' => TokenKind::Assign,
+            '!' if self.eat('=') => TokenKind::NotEquals,
+            '!' => TokenKind::Bang,
+            '<' if self.eat('=') => TokenKind::LessOrEqual,
+            '<' => TokenKind::Less,
+            '>' if self.eat('=') => TokenKind::GreaterOrEqual,
+            '>' => TokenKind::Greater,
+            '&' if self.eat('&') => TokenKind::AndAnd,
+            '|' if self.eat('|') => TokenKind::OrOr,
+            '"' => self.string(span)?,
+            c if c.is_ascii_digit() => {
+                let mut digits = String::from(c);
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_digit() || *c == '_') {
+                    digits.push(c);
+                    self.bump();
+                }
+                match digits.replace('_', "").parse() {
+                    Ok(value) => TokenKind::Integer(value),
+                    Err(_) => return Err(CompileError::new("integer literal is too large", span)),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+                    word.push(c);
+                    self.bump();
+                }
+                match word.as_str() {
+                    "fn" => TokenKind::Fn,
+                    "let" => TokenKind::Let,
+                    "if" => TokenKind::If,
+                    "else" => TokenKind::Else,
+                    "while" => TokenKind::While,
+                    "for" => TokenKind::For,
+                    "in" => TokenKind::In,
+                    "return" => TokenKind::Return,
+                    "break" => TokenKind::Break,
+                    "continue" => TokenKind::Continue,
+                    "true" => TokenKind::True,
+                    "false" => TokenKind::False,
+                    _ => TokenKind::Ident(word),
+                }
+            }
+            c => return Err(CompileError::new(format!("unexpected character `{c}`"), span)),
+        };
+        Ok(Token { kind, span })
+    }
+}
+
+/// Splits `source` into tokens, ending with `TokenKind::Eof`.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut lexer = Lexer {
+        chars: source.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.token()?;
+        let done = token.kind == TokenKind::Eof;
+        tokens.push(token);
+        if done {
+            return Ok(tokens);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compiler::lexer::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("let x = 1_000; // comment\nx <= \"a\\n\"..").unwrap();
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Let,
+                TokenKind::Ident("x".to_string()),
+                TokenKind::Assign,
+                TokenKind::Integer(1000),
+                TokenKind::Semicolon,
+                TokenKind::Ident("x".to_string()),
+                TokenKind::LessOrEqual,
+                TokenKind::String("a\n".to_string()),
+                TokenKind::DotDot,
+                TokenKind::Eof,
+            ]
+        );
+        let error = tokenize("let x = 1;\n  #").unwrap_err();
+        assert_eq!(error.span, Span { line: 2, column: 3 });
+    }
+}
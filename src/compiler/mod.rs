@@ -0,0 +1,212 @@
+//! A small statically typed language compiled to instructions.
+//!
+//! ```text
+//! fn main() -> u64 {
+//!     let primes: [u64] = [];
+//!     for n in 2..50 {
+//!         if is_prime(n) { push(primes, n); }
+//!     }
+//!     return len(primes);
+//! }
+//!
+//! fn is_prime(n: u64) -> bool {
+//!     let i = 2;
+//!     while i * i <= n {
+//!         if n % i == 0 { return false; }
+//!         i = i + 1;
+//!     }
+//!     return true;
+//! }
+//! ```
+//!
+//! Types are `u8`, `u16`, `u32`, `u64`, `bool`, `string` and arrays like `[u64]`. Integer literals take the type the
+//! context expects, `u64` otherwise. `len(array)`, `push(array, value)` and `println(value)` are builtin. Execution
+//! starts at `main`, which takes no parameters.
+
+mod ast;
+mod codegen;
+mod lexer;
+mod parser;
+mod typeck;
+
+use std::fmt;
+
+pub use ast::Span;
+
+use crate::interpreter::{Function, Program};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl CompileError {
+    pub(crate) fn new(message: impl Into<String>, span: Span) -> Self {
+        CompileError {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Compiles `source` into a program whose function 0 is `main`.
+pub fn compile(source: &str) -> Result<Program, CompileError> {
+    let functions = parser::parse(lexer::tokenize(source)?)?;
+    let (mut functions, signatures) = typeck::signatures(functions)?;
+    for function in functions.iter_mut() {
+        typeck::check_function(function, &signatures)?;
+    }
+    let compiled = functions
+        .iter()
+        .map(|function| codegen::generate(function, &signatures))
+        .collect::<Result<Vec<Function>, CompileError>>()?;
+    Ok(Program::new(&compiled))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compiler::*,
+        interpreter::{ExecutionStatus, Interpreter},
+        value::Value,
+    };
+
+    fn run(source: &str) -> Option<Value> {
+        let program = compile(source).unwrap_or_else(|error| panic!("{error}"));
+        let mut interpreter = Interpreter::new(program).unwrap();
+        match interpreter.run_for(1_000_000) {
+            ExecutionStatus::Finished(value) => value,
+            status => panic!("unexpected status {status:?}"),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_primes() {
+        let source = "
+            fn main() -> u64 {
+                let primes: [u64] = [];
+                for n in 2..50 {
+                    if is_prime(n) { push(primes, n); }
+                }
+                return len(primes);
+            }
+            fn is_prime(n: u64) -> bool {
+                let i = 2;
+                while i * i <= n {
+                    if n % i == 0 { return false; }
+                    i = i + 1;
+                }
+                return true;
+            }";
+        assert_eq!(run(source), Some(Value::U64(15)));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "
+            fn main() -> u32 {
+                let total: u32 = 0;
+                let limit: u32 = 100;
+                for i in 0..limit {
+                    if i % 2 == 1 { continue; } else if i > 10 { break; }
+                    total = total + i;
+                }
+                let x = 5;
+                if !(x > 3 && total == 30) || false { return 0; }
+                let x: [[u32]] = [[1, 2], [3]];
+                let inner = x[1];
+                push(inner, 4);
+                x[0] = inner;
+                add_to(x, 10);
+                let pair = [total, x[0][0]];
+                return total + x[0][1] + fib(10) + pair[1];
+            }
+            fn add_to(values: [[u32]], extra: u32) {
+                let first = values[0];
+                first[0] = extra;
+                values[0] = first;
+                return;
+            }
+            fn fib(n: u32) -> u32 {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }";
+        assert_eq!(run(source), Some(Value::U32(30 + 4 + 55 + 3)));
+    }
+
+    #[test]
+    fn test_strings_and_void_main() {
+        let source = r#"
+            fn main() {
+                let name = "wor" + "ld";
+                if name != "world" { let fail: u8 = 255 - 1; }
+                greet(name);
+            }
+            fn greet(name: string) { println("hello " + name); }"#;
+        assert_eq!(run(source), None);
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(error("fn main() { let x = 1; x = true; }"), "1:28: expected u64, found bool");
+        assert_eq!(error("fn main() { let x: u8 = 256; }"), "1:25: literal 256 does not fit in u8");
+        assert_eq!(
+            error("fn main() { let x = []; }"),
+            "1:21: cannot infer the type of an empty array, add a type annotation"
+        );
+        assert_eq!(
+            error("fn main() { let x = f(); }\nfn f() {}"),
+            "1:21: this call does not return a value"
+        );
+        assert_eq!(
+            error("fn main() { break; }"),
+            "1:13: `break` and `continue` can only be used inside a loop"
+        );
+        assert_eq!(error("fn main() { y = 1; }"), "1:13: unknown variable `y`");
+        assert_eq!(
+            error("fn main() {}\nfn f(x: u64) -> u64 { if x > 1 { return x; } }"),
+            "2:1: function `f` can end without returning a value"
+        );
+        assert_eq!(error("fn f() {}"), "0:0: no `main` function");
+        assert_eq!(
+            error("fn main() { f(1); }\nfn f() {}"),
+            "1:13: `f` takes 0 arguments but 1 were given"
+        );
+        // errors inside long operator chains are still found
+        let chain = vec!["1"; 5000].join(" + ");
+        assert_eq!(
+            error(&format!("fn main() {{ let x = {chain} + true; }}")),
+            "1:20021: expected u64, found bool"
+        );
+    }
+
+    #[test]
+    fn test_long_operator_chains() {
+        let sum = vec!["1"; 5000].join(" + ");
+        assert_eq!(run(&format!("fn main() -> u64 {{ return {sum}; }}")), Some(Value::U64(5000)));
+        let all = vec!["x < 2"; 5000].join(" && ");
+        let source = format!("fn main() -> bool {{ let x = 1; return {all} && x == 1 == true; }}");
+        assert_eq!(run(&source), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        // as deep as the parser allows, the function body being the first block
+        let nested = |depth: usize| format!("fn main() {{ let x = true; {}{} }}", "if x { ".repeat(depth), "}".repeat(depth));
+        assert!(compile(&nested(127)).is_ok());
+        assert!(error(&nested(128)).ends_with("block nests too deeply"));
+    }
+}
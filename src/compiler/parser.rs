@@ -0,0 +1,410 @@
+use crate::{
+    compiler::{
+        ast::{BinaryOp, Expr, ExprKind, SourceFunction, Span, Stmt},
+        lexer::{Token, TokenKind},
+        CompileError,
+    },
+    value::VariableType,
+};
+
+// The parser and later passes recurse into nested expressions and blocks, so deeper sources could overflow the stack.
+// Chains of binary operators are walked without recursing and do not count.
+const MAX_NESTING: usize = 128;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    // Parentheses, brackets, calls, `!` and blocks currently being parsed, each inside the previous.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &TokenKind {
+        &self.tokens[self.position].kind
+    }
+    fn span(&self) -> Span {
+        self.tokens[self.position].span
+    }
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+        // the last token is always Eof, stay on it
+        if self.position + 1 < self.tokens.len() {
+            self.position += 1;
+        }
+        token
+    }
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == kind {
+            self.bump();
+            return true;
+        }
+        false
+    }
+    fn expect(&mut self, kind: &TokenKind, what: &str) -> Result<Span, CompileError> {
+        let span = self.span();
+        if !self.eat(kind) {
+            return Err(self.unexpected(what));
+        }
+        Ok(span)
+    }
+    fn expect_ident(&mut self) -> Result<(String, Span), CompileError> {
+        let span = self.span();
+        match self.peek().clone() {
+            TokenKind::Ident(name) => {
+                self.bump();
+                Ok((name, span))
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+    fn unexpected(&self, what: &str) -> CompileError {
+        let found = match self.peek() {
+            TokenKind::Eof => "end of input".to_string(),
+            kind => format!("{kind:?}"),
+        };
+        CompileError::new(format!("expected {what}, found {found}"), self.span())
+    }
+    /// Enters one more level of nesting, failing past `MAX_NESTING`. Callers step back out by decrementing `depth`.
+    fn nest(&mut self, what: &str, span: Span) -> Result<(), CompileError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(CompileError::new(format!("{what} nests too deeply"), span));
+        }
+        Ok(())
+    }
+
+    fn function(&mut self) -> Result<SourceFunction, CompileError> {
+        let span = self.expect(&TokenKind::Fn, "`fn`")?;
+        let (name, _) = self.expect_ident()?;
+        self.expect(&TokenKind::LeftParen, "`(`")?;
+        let mut parameters = Vec::new();
+        while !self.eat(&TokenKind::RightParen) {
+            let (parameter, _) = self.expect_ident()?;
+            self.expect(&TokenKind::Colon, "`:`")?;
+            parameters.push((parameter, self.parse_type()?));
+            if !self.eat(&TokenKind::Comma) {
+                self.expect(&TokenKind::RightParen, "`,` or `)`")?;
+                break;
+            }
+        }
+        let return_type = match self.eat(&TokenKind::Arrow) {
+            true => Some(self.parse_type()?),
+            false => None,
+        };
+        let body = self.block()?;
+        Ok(SourceFunction {
+            name,
+            parameters,
+            return_type,
+            body,
+            span,
+        })
+    }
+
+    fn parse_type(&mut self) -> Result<VariableType, CompileError> {
+        if self.eat(&TokenKind::LeftBracket) {
+            let inner = self.parse_type()?;
+            self.expect(&TokenKind::RightBracket, "`]`")?;
+            return Ok(VariableType::Array(Box::new(inner)));
+        }
+        let span = self.span();
+        let (name, _) = self.expect_ident()?;
+        Ok(match name.as_str() {
+            "u8" => VariableType::U8,
+            "u16" => VariableType::U16,
+            "u32" => VariableType::U32,
+            "u64" => VariableType::U64,
+            "bool" => VariableType::Bool,
+            "string" => VariableType::String,
+            _ => return Err(CompileError::new(format!("unknown type `{name}`"), span)),
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        let span = self.expect(&TokenKind::LeftBrace, "`{`")?;
+        self.nest("block", span)?;
+        let mut statements = Vec::new();
+        while !self.eat(&TokenKind::RightBrace) {
+            statements.push(self.statement()?);
+        }
+        self.depth -= 1;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let span = self.span();
+        match self.peek() {
+            TokenKind::Let => {
+                self.bump();
+                let (name, _) = self.expect_ident()?;
+                let var_type = match self.eat(&TokenKind::Colon) {
+                    true => Some(self.parse_type()?),
+                    false => None,
+                };
+                self.expect(&TokenKind::Assign, "`=`")?;
+                let value = self.expression()?;
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                Ok(Stmt::Let { name, var_type, value })
+            }
+            TokenKind::If => self.if_statement(),
+            TokenKind::While => {
+                self.bump();
+                let condition = self.expression()?;
+                let body = self.block()?;
+                Ok(Stmt::While { condition, body })
+            }
+            TokenKind::For => {
+                self.bump();
+                let (name, _) = self.expect_ident()?;
+                self.expect(&TokenKind::In, "`in`")?;
+                let start = self.expression()?;
+                self.expect(&TokenKind::DotDot, "`..`")?;
+                let end = self.expression()?;
+                let body = self.block()?;
+                Ok(Stmt::For { name, start, end, body })
+            }
+            TokenKind::Return => {
+                self.bump();
+                let value = match self.peek() {
+                    TokenKind::Semicolon => None,
+                    _ => Some(self.expression()?),
+                };
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                Ok(Stmt::Return { value, span })
+            }
+            TokenKind::Break => {
+                self.bump();
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                Ok(Stmt::Break(span))
+            }
+            TokenKind::Continue => {
+                self.bump();
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                Ok(Stmt::Continue(span))
+            }
+            _ => {
+                let target = self.expression()?;
+                if !self.eat(&TokenKind::Assign) {
+                    self.expect(&TokenKind::Semicolon, "`;`")?;
+                    return Ok(Stmt::Expr(target));
+                }
+                let value = self.expression()?;
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                match target.kind {
+                    ExprKind::Variable(name) => Ok(Stmt::Assign { name, value, span }),
+                    ExprKind::Index(base, index) => match base.kind {
+                        ExprKind::Variable(name) => Ok(Stmt::AssignIndex {
+                            name,
+                            index: *index,
+                            value,
+                            span,
+                        }),
+                        _ => Err(CompileError::new("only elements of a variable can be assigned", base.span)),
+                    },
+                    _ => Err(CompileError::new("cannot assign to this expression", target.span)),
+                }
+            }
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, CompileError> {
+        self.expect(&TokenKind::If, "`if`")?;
+        let condition = self.expression()?;
+        let then_body = self.block()?;
+        let else_body = match self.eat(&TokenKind::Else) {
+            true if *self.peek() == TokenKind::If => {
+                // an `else if` is a block holding the next `if`
+                self.nest("block", self.span())?;
+                let else_if = self.if_statement()?;
+                self.depth -= 1;
+                vec![else_if]
+            }
+            true => self.block()?,
+            false => Vec::new(),
+        };
+        Ok(Stmt::If {
+            condition,
+            then_body,
+            else_body,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    /// Parses binary operators binding at least as tightly as `min_precedence`. Chains of operators are not nesting,
+    /// the type checker and code generator walk them without recursing.
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, CompileError> {
+        let mut lhs = self.unary()?;
+        while let Some((op, precedence)) = binary_operator(self.peek()).filter(|(_, precedence)| *precedence >= min_precedence) {
+            let span = self.bump().span;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let span = self.span();
+        if self.eat(&TokenKind::Bang) {
+            self.nest("expression", span)?;
+            let inner = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::new(ExprKind::Not(Box::new(inner)), span));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, CompileError> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            let span = self.span();
+            if self.eat(&TokenKind::LeftBracket) {
+                // each index nests the expression before it a level deeper
+                self.nest("expression", span)?;
+                let index = self.expression()?;
+                self.expect(&TokenKind::RightBracket, "`]`")?;
+                expr = Expr::new(ExprKind::Index(Box::new(expr), Box::new(index)), span);
+            } else if *self.peek() == TokenKind::LeftParen {
+                let ExprKind::Variable(name) = expr.kind else {
+                    return Err(CompileError::new("only named functions can be called", expr.span));
+                };
+                self.bump();
+                self.nest("expression", span)?;
+                let arguments = self.list(TokenKind::RightParen)?;
+                expr = Expr::new(ExprKind::Call(name, arguments), expr.span);
+            } else {
+                self.depth = depth;
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let span = self.span();
+        let kind = match self.peek().clone() {
+            TokenKind::Integer(value) => ExprKind::Integer(value),
+            TokenKind::String(value) => ExprKind::String(value),
+            TokenKind::True => ExprKind::Bool(true),
+            TokenKind::False => ExprKind::Bool(false),
+            TokenKind::Ident(name) => ExprKind::Variable(name),
+            TokenKind::LeftParen => {
+                self.bump();
+                self.nest("expression", span)?;
+                let expr = self.expression()?;
+                self.depth -= 1;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                return Ok(expr);
+            }
+            TokenKind::LeftBracket => {
+                self.bump();
+                self.nest("expression", span)?;
+                let items = self.list(TokenKind::RightBracket)?;
+                self.depth -= 1;
+                ExprKind::Array(items)
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        if !matches!(kind, ExprKind::Array(_)) {
+            self.bump();
+        }
+        Ok(Expr::new(kind, span))
+    }
+
+    /// Comma separated expressions up to and including `end`.
+    fn list(&mut self, end: TokenKind) -> Result<Vec<Expr>, CompileError> {
+        let mut items = Vec::new();
+        while !self.eat(&end) {
+            items.push(self.expression()?);
+            if !self.eat(&TokenKind::Comma) {
+                self.expect(&end, "`,` or closing bracket")?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+fn binary_operator(kind: &TokenKind) -> Option<(BinaryOp, u8)> {
+    Some(match kind {
+        TokenKind::OrOr => (BinaryOp::Or, 0),
+        TokenKind::AndAnd => (BinaryOp::And, 1),
+        TokenKind::Equals => (BinaryOp::Equals, 2),
+        TokenKind::NotEquals => (BinaryOp::NotEquals, 2),
+        TokenKind::Less => (BinaryOp::Less, 2),
+        TokenKind::Greater => (BinaryOp::Greater, 2),
+        TokenKind::LessOrEqual => (BinaryOp::LessOrEqual, 2),
+        TokenKind::GreaterOrEqual => (BinaryOp::GreaterOrEqual, 2),
+        TokenKind::Plus => (BinaryOp::Add, 3),
+        TokenKind::Minus => (BinaryOp::Sub, 3),
+        TokenKind::Star => (BinaryOp::Mul, 4),
+        TokenKind::Slash => (BinaryOp::Div, 4),
+        TokenKind::Percent => (BinaryOp::Rem, 4),
+        _ => return None,
+    })
+}
+
+/// Parses every function in the token stream.
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<SourceFunction>, CompileError> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        depth: 0,
+    };
+    let mut functions = Vec::new();
+    while *parser.peek() != TokenKind::Eof {
+        functions.push(parser.function()?);
+    }
+    Ok(functions)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compiler::{lexer::tokenize, parser::*};
+
+    #[test]
+    fn test_precedence() {
+        let functions = parse(tokenize("fn main() { x = 1 + 2 * 3 < 4 && !y || z; }").unwrap()).unwrap();
+        let Stmt::Assign { value, .. } = &functions[0].body[0] else {
+            panic!("expected an assignment");
+        };
+        // ((1 + (2 * 3)) < 4 && !y) || z
+        let ExprKind::Binary(BinaryOp::Or, lhs, _) = &value.kind else {
+            panic!("expected || at the top");
+        };
+        let ExprKind::Binary(BinaryOp::And, lhs, _) = &lhs.kind else {
+            panic!("expected && under ||");
+        };
+        let ExprKind::Binary(BinaryOp::Less, lhs, _) = &lhs.kind else {
+            panic!("expected < under &&");
+        };
+        assert!(matches!(&lhs.kind, ExprKind::Binary(BinaryOp::Add, _, rhs) if matches!(rhs.kind, ExprKind::Binary(BinaryOp::Mul, _, _))));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse(tokenize("fn main() {\n  let x = ;\n}").unwrap()).unwrap_err();
+        assert_eq!(error.span, Span { line: 2, column: 11 });
+        assert!(parse(tokenize("fn main() { f()[0] = 1; }").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let too_deep = |expression: String| {
+            let error = parse(tokenize(&format!("fn main() {{ x = {expression}; }}")).unwrap()).unwrap_err();
+            error.message == "expression nests too deeply"
+        };
+        assert!(too_deep(format!("{}1{}", "(".repeat(5000), ")".repeat(5000))));
+        assert!(too_deep(format!("{}y", "!".repeat(5000))));
+        assert!(too_deep(format!("a{}", "[0]".repeat(5000))));
+        assert!(too_deep(format!("{}1{}", "f(".repeat(5000), ")".repeat(5000))));
+        assert!(parse(tokenize(&format!("fn main() {{ x = {}1{}; }}", "(".repeat(100), ")".repeat(100))).unwrap()).is_ok());
+        // a flat chain of operators does not nest
+        assert!(parse(tokenize(&format!("fn main() {{ x = {}; }}", vec!["1"; 5000].join(" + "))).unwrap()).is_ok());
+        // conditions do not count towards the nesting of the blocks around them
+        let ifs = format!("fn main() {{ {}{} }}", "if x { ".repeat(5000), "}".repeat(5000));
+        assert_eq!(parse(tokenize(&ifs).unwrap()).unwrap_err().message, "block nests too deeply");
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+
+use crate::{
+    compiler::{
+        ast::{BinaryOp, Expr, ExprKind, SourceFunction, Span, Stmt},
+        CompileError,
+    },
+    value::{FunctionIdType, VariableType},
+};
+
+/// Names that call native functions instead of functions in the program.
+pub const BUILTINS: [&str; 3] = ["len", "push", "println"];
+
+/// What a call needs to know about a function.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub id: FunctionIdType,
+    pub parameters: Vec<VariableType>,
+    pub return_type: Option<VariableType>,
+}
+
+struct Checker<'a> {
+    signatures: &'a HashMap<String, Signature>,
+    return_type: Option<VariableType>,
+    scopes: Vec<HashMap<String, VariableType>>,
+    loop_depth: usize,
+}
+
+fn is_numeric(var_type: &VariableType) -> bool {
+    matches!(
+        var_type,
+        VariableType::U8 | VariableType::U16 | VariableType::U32 | VariableType::U64
+    )
+}
+
+fn mismatch(expected: &VariableType, found: &VariableType, span: Span) -> CompileError {
+    CompileError::new(format!("expected {expected}, found {found}"), span)
+}
+
+/// The type `op` produces from operands of `var_type`, which both operands were checked to have.
+fn operator_type(op: BinaryOp, var_type: VariableType, span: Span) -> Result<VariableType, CompileError> {
+    match op {
+        BinaryOp::And | BinaryOp::Or => Ok(VariableType::Bool),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            let concatenation = op == BinaryOp::Add && var_type == VariableType::String;
+            if !is_numeric(&var_type) && !concatenation {
+                return Err(CompileError::new(format!("cannot use {op:?} on {var_type}"), span));
+            }
+            Ok(var_type)
+        }
+        BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessOrEqual | BinaryOp::GreaterOrEqual => {
+            if !is_numeric(&var_type) {
+                return Err(CompileError::new(format!("cannot compare {var_type}"), span));
+            }
+            Ok(VariableType::Bool)
+        }
+        BinaryOp::Equals | BinaryOp::NotEquals => {
+            if matches!(var_type, VariableType::Array(_)) {
+                return Err(CompileError::new("arrays cannot be compared", span));
+            }
+            Ok(VariableType::Bool)
+        }
+    }
+}
+
+impl Checker<'_> {
+    fn lookup(&self, name: &str, span: Span) -> Result<VariableType, CompileError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .ok_or_else(|| CompileError::new(format!("unknown variable `{name}`"), span))
+    }
+    fn declare(&mut self, name: &str, var_type: VariableType) {
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope")
+            .insert(name.to_string(), var_type);
+    }
+
+    fn block(&mut self, statements: &mut [Stmt]) -> Result<(), CompileError> {
+        self.scopes.push(HashMap::new());
+        let result = statements.iter_mut().try_for_each(|statement| self.statement(statement));
+        self.scopes.pop();
+        result
+    }
+
+    fn statement(&mut self, statement: &mut Stmt) -> Result<(), CompileError> {
+        match statement {
+            Stmt::Let { name, var_type, value, .. } => {
+                let value_type = self.value(value, var_type.as_ref())?;
+                if let Some(var_type) = var_type {
+                    if *var_type != value_type {
+                        return Err(mismatch(var_type, &value_type, value.span));
+                    }
+                }
+                self.declare(name, value_type.clone());
+                *var_type = Some(value_type);
+            }
+            Stmt::Assign { name, value, span } => {
+                let var_type = self.lookup(name, *span)?;
+                self.expect(value, &var_type)?;
+            }
+            Stmt::AssignIndex { name, index, value, span } => {
+                let VariableType::Array(inner) = self.lookup(name, *span)? else {
+                    return Err(CompileError::new(format!("`{name}` is not an array"), *span));
+                };
+                self.index(index)?;
+                self.expect(value, &inner)?;
+            }
+            Stmt::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                self.expect(condition, &VariableType::Bool)?;
+                self.block(then_body)?;
+                self.block(else_body)?;
+            }
+            Stmt::While { condition, body } => {
+                self.expect(condition, &VariableType::Bool)?;
+                self.loop_body(body)?;
+            }
+            Stmt::For { name, start, end, body } => {
+                let var_type = self.pair(start, end, None)?;
+                if !is_numeric(&var_type) {
+                    return Err(CompileError::new(format!("cannot loop over a range of {var_type}"), start.span));
+                }
+                self.scopes.push(HashMap::from([(name.clone(), var_type)]));
+                let result = self.loop_body(body);
+                self.scopes.pop();
+                result?;
+            }
+            Stmt::Return { value, span } => match (value, self.return_type.clone()) {
+                (Some(value), Some(return_type)) => self.expect(value, &return_type)?,
+                (None, None) => {}
+                (Some(value), None) => return Err(CompileError::new("this function does not return a value", value.span)),
+                (None, Some(return_type)) => return Err(CompileError::new(format!("expected a return value of {return_type}"), *span)),
+            },
+            Stmt::Break(span) | Stmt::Continue(span) => {
+                if self.loop_depth == 0 {
+                    return Err(CompileError::new("`break` and `continue` can only be used inside a loop", *span));
+                }
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_body(&mut self, body: &mut [Stmt]) -> Result<(), CompileError> {
+        self.loop_depth += 1;
+        let result = self.block(body);
+        self.loop_depth -= 1;
+        result
+    }
+
+    fn expect(&mut self, expr: &mut Expr, expected: &VariableType) -> Result<(), CompileError> {
+        let found = self.value(expr, Some(expected))?;
+        if found != *expected {
+            return Err(mismatch(expected, &found, expr.span));
+        }
+        Ok(())
+    }
+
+    fn index(&mut self, index: &mut Expr) -> Result<(), CompileError> {
+        let index_type = self.value(index, None)?;
+        if !is_numeric(&index_type) {
+            return Err(CompileError::new(
+                format!("array index must be an integer, found {index_type}"),
+                index.span,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks an expression that must produce a value.
+    fn value(&mut self, expr: &mut Expr, expected: Option<&VariableType>) -> Result<VariableType, CompileError> {
+        self.expr(expr, expected)?
+            .ok_or_else(|| CompileError::new("this call does not return a value", expr.span))
+    }
+
+    /// Checks two operands that must have the same type. An integer literal takes the type of the other side.
+    fn pair(&mut self, lhs: &mut Expr, rhs: &mut Expr, expected: Option<&VariableType>) -> Result<VariableType, CompileError> {
+        let (first, second) = match lhs.kind {
+            ExprKind::Integer(_) => (rhs, lhs),
+            _ => (lhs, rhs),
+        };
+        let var_type = self.value(first, expected)?;
+        self.expect(second, &var_type)?;
+        Ok(var_type)
+    }
+
+    fn expr(&mut self, expr: &mut Expr, expected: Option<&VariableType>) -> Result<Option<VariableType>, CompileError> {
+        if let ExprKind::Binary(..) = expr.kind {
+            return self.binary_chain(expr, expected).map(Some);
+        }
+        let span = expr.span;
+        let var_type = match &mut expr.kind {
+            ExprKind::Integer(value) => {
+                let var_type = match expected {
+                    Some(var_type) if is_numeric(var_type) => var_type.clone(),
+                    _ => VariableType::U64,
+                };
+                let max = match var_type {
+                    VariableType::U8 => u8::MAX as u64,
+                    VariableType::U16 => u16::MAX as u64,
+                    VariableType::U32 => u32::MAX as u64,
+                    _ => u64::MAX,
+                };
+                if *value > max {
+                    return Err(CompileError::new(format!("literal {value} does not fit in {var_type}"), span));
+                }
+                Some(var_type)
+            }
+            ExprKind::Bool(_) => Some(VariableType::Bool),
+            ExprKind::String(_) => Some(VariableType::String),
+            ExprKind::Array(items) => {
+                let inner = match expected {
+                    Some(VariableType::Array(inner)) => Some(inner.as_ref().clone()),
+                    _ => None,
+                };
+                let Some((first, rest)) = items.split_first_mut() else {
+                    let Some(inner) = inner else {
+                        return Err(CompileError::new(
+                            "cannot infer the type of an empty array, add a type annotation",
+                            span,
+                        ));
+                    };
+                    expr.ty = Some(VariableType::Array(Box::new(inner)));
+                    return Ok(expr.ty.clone());
+                };
+                let inner = self.value(first, inner.as_ref())?;
+                for item in rest {
+                    self.expect(item, &inner)?;
+                }
+                Some(VariableType::Array(Box::new(inner)))
+            }
+            ExprKind::Variable(name) => Some(self.lookup(name, span)?),
+            ExprKind::Index(base, index) => {
+                let base_type = self.value(base, None)?;
+                let VariableType::Array(inner) = base_type else {
+                    return Err(CompileError::new(format!("cannot index into {base_type}"), base.span));
+                };
+                self.index(index)?;
+                Some(*inner)
+            }
+            ExprKind::Call(name, arguments) => self.call(name, arguments, span)?,
+            ExprKind::Not(inner) => {
+                self.expect(inner, &VariableType::Bool)?;
+                Some(VariableType::Bool)
+            }
+            ExprKind::Binary(..) => unreachable!("checked by `binary_chain`"),
+        };
+        expr.ty = var_type.clone();
+        Ok(var_type)
+    }
+
+    /// Checks a binary expression. Operators are left associative, so a long chain such as `a + b + c + ...` nests
+    /// to the left: the left operands are unlinked and checked innermost first, instead of recursing once per operator.
+    fn binary_chain(&mut self, expr: &mut Expr, expected: Option<&VariableType>) -> Result<VariableType, CompileError> {
+        let placeholder = |span| Expr::new(ExprKind::Bool(false), span);
+        let mut node = std::mem::replace(expr, placeholder(expr.span));
+        let mut expected = expected.cloned();
+        let mut outer = Vec::new();
+        while let ExprKind::Binary(op, lhs, _) = &mut node.kind {
+            if !matches!(lhs.kind, ExprKind::Binary(..)) {
+                break;
+            }
+            // what the left operand is expected to be, as in `binary`
+            expected = match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => expected,
+                BinaryOp::And | BinaryOp::Or => Some(VariableType::Bool),
+                _ => None,
+            };
+            let span = lhs.span;
+            let inner = std::mem::replace(&mut **lhs, placeholder(span));
+            outer.push(node);
+            node = inner;
+        }
+
+        let ExprKind::Binary(op, lhs, rhs) = &mut node.kind else {
+            unreachable!("only called on binary expressions");
+        };
+        let mut result = self.binary(*op, lhs, rhs, expected.as_ref(), node.span);
+        node.ty = result.as_ref().ok().cloned();
+        // the tree is put back together even after an error
+        while let Some(mut parent) = outer.pop() {
+            let span = parent.span;
+            let ExprKind::Binary(op, lhs, rhs) = &mut parent.kind else {
+                unreachable!("only binary expressions are unlinked");
+            };
+            **lhs = node;
+            if let Ok(lhs_type) = result {
+                result = self.chained(*op, lhs, lhs_type, rhs, span);
+            }
+            parent.ty = result.as_ref().ok().cloned();
+            node = parent;
+        }
+        *expr = node;
+        result
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        lhs: &mut Expr,
+        rhs: &mut Expr,
+        expected: Option<&VariableType>,
+        span: Span,
+    ) -> Result<VariableType, CompileError> {
+        let var_type = match op {
+            BinaryOp::And | BinaryOp::Or => {
+                self.expect(lhs, &VariableType::Bool)?;
+                self.expect(rhs, &VariableType::Bool)?;
+                VariableType::Bool
+            }
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => self.pair(lhs, rhs, expected)?,
+            _ => self.pair(lhs, rhs, None)?,
+        };
+        operator_type(op, var_type, span)
+    }
+
+    /// Checks a binary expression whose left operand, itself a binary expression, was already checked as `lhs_type`.
+    fn chained(
+        &mut self,
+        op: BinaryOp,
+        lhs: &Expr,
+        lhs_type: VariableType,
+        rhs: &mut Expr,
+        span: Span,
+    ) -> Result<VariableType, CompileError> {
+        if matches!(op, BinaryOp::And | BinaryOp::Or) && lhs_type != VariableType::Bool {
+            return Err(mismatch(&VariableType::Bool, &lhs_type, lhs.span));
+        }
+        self.expect(rhs, &lhs_type)?;
+        operator_type(op, lhs_type, span)
+    }
+
+    fn call(&mut self, name: &str, arguments: &mut [Expr], span: Span) -> Result<Option<VariableType>, CompileError> {
+        let given = arguments.len();
+        let argument_count = |count: usize| match given == count {
+            true => Ok(()),
+            false => Err(CompileError::new(
+                format!("`{name}` takes {count} arguments but {given} were given"),
+                span,
+            )),
+        };
+        match name {
+            "len" => {
+                argument_count(1)?;
+                let var_type = self.value(&mut arguments[0], None)?;
+                if !matches!(var_type, VariableType::Array(_)) {
+                    return Err(CompileError::new(
+                        format!("`len` expects an array, found {var_type}"),
+                        arguments[0].span,
+                    ));
+                }
+                Ok(Some(VariableType::U64))
+            }
+            "push" => {
+                argument_count(2)?;
+                let [array, value] = arguments else { unreachable!() };
+                if !matches!(array.kind, ExprKind::Variable(_)) {
+                    return Err(CompileError::new("`push` needs a variable holding an array", array.span));
+                }
+                let VariableType::Array(inner) = self.value(array, None)? else {
+                    return Err(CompileError::new("`push` expects an array", array.span));
+                };
+                self.expect(value, &inner)?;
+                Ok(None)
+            }
+            "println" => {
+                argument_count(1)?;
+                self.value(&mut arguments[0], None)?;
+                Ok(None)
+            }
+            _ => {
+                let Some(signature) = self.signatures.get(name) else {
+                    return Err(CompileError::new(format!("unknown function `{name}`"), span));
+                };
+                argument_count(signature.parameters.len())?;
+                for (argument, parameter) in arguments.iter_mut().zip(signature.parameters.iter()) {
+                    self.expect(argument, parameter)?;
+                }
+                Ok(signature.return_type.clone())
+            }
+        }
+    }
+}
+
+/// Whether every path through `statements` ends in a `return`.
+fn always_returns(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Stmt::Return { .. } => true,
+        Stmt::If { then_body, else_body, .. } => always_returns(then_body) && always_returns(else_body),
+        _ => false,
+    })
+}
+
+/// Assigns function ids, with `main` as function 0, and returns the functions in id order.
+pub fn signatures(functions: Vec<SourceFunction>) -> Result<(Vec<SourceFunction>, HashMap<String, Signature>), CompileError> {
+    let Some(main) = functions.iter().position(|function| function.name == "main") else {
+        return Err(CompileError::new("no `main` function", Span::default()));
+    };
+    let mut functions = functions;
+    let main = functions.remove(main);
+    if !main.parameters.is_empty() {
+        return Err(CompileError::new("`main` cannot take parameters", main.span));
+    }
+    functions.insert(0, main);
+
+    let mut signatures = HashMap::new();
+    for (id, function) in functions.iter().enumerate() {
+        let Ok(id) = FunctionIdType::try_from(id) else {
+            return Err(CompileError::new("too many functions", function.span));
+        };
+        if BUILTINS.contains(&function.name.as_str()) {
+            return Err(CompileError::new(
+                format!("`{}` is a builtin function", function.name),
+                function.span,
+            ));
+        }
+        let signature = Signature {
+            id,
+            parameters: function.parameters.iter().map(|(_, var_type)| var_type.clone()).collect(),
+            return_type: function.return_type.clone(),
+        };
+        if signatures.insert(function.name.clone(), signature).is_some() {
+            return Err(CompileError::new(
+                format!("function `{}` is defined twice", function.name),
+                function.span,
+            ));
+        }
+    }
+    Ok((functions, signatures))
+}
+
+/// Checks the types in `function` and records them in its expressions.
+pub fn check_function(function: &mut SourceFunction, signatures: &HashMap<String, Signature>) -> Result<(), CompileError> {
+    let mut parameters = HashMap::new();
+    for (name, var_type) in function.parameters.iter() {
+        if parameters.insert(name.clone(), var_type.clone()).is_some() {
+            return Err(CompileError::new(format!("parameter `{name}` is declared twice"), function.span));
+        }
+    }
+    let mut checker = Checker {
+        signatures,
+        return_type: function.return_type.clone(),
+        scopes: vec![parameters],
+        loop_depth: 0,
+    };
+    checker.block(&mut function.body)?;
+    if function.return_type.is_some() && !always_returns(&function.body) {
+        return Err(CompileError::new(
+            format!("function `{}` can end without returning a value", function.name),
+            function.span,
+        ));
+    }
+    Ok(())
+}
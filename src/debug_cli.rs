@@ -0,0 +1,155 @@
+use std::io::{self, BufRead, Write};
+
+use new_interp::{
+    debugger::{Breakpoint, Watchpoint},
+    interpreter::{ExecutionStatus, Interpreter},
+};
+
+const HELP: &str = "\
+Commands:
+  s, step                  execute one instruction
+  n, next                  execute one instruction, running calls to completion
+  o, out                   run until the current function returns
+  c, continue              run until a breakpoint, watchpoint, yield or the end of the program
+  b <function> <index>     set a breakpoint before an instruction
+  d <function> <index>     delete a breakpoint
+  w <function> <variable>  watch a variable for changes
+  bt, frames               show every frame on the callstack
+  l, list                  list the instructions of the current function
+  q, quit                  exit the debugger";
+
+/// Runs `interpreter` under an interactive debugger reading commands from stdin.
+pub fn run(mut interpreter: Interpreter) {
+    println!("{HELP}");
+    let stdin = io::stdin();
+    loop {
+        print!("(debug) ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let status = match words.as_slice() {
+            ["s"] | ["step"] => interpreter.step(),
+            ["n"] | ["next"] => interpreter.step_over(),
+            ["o"] | ["out"] => interpreter.step_out(),
+            ["c"] | ["continue"] => interpreter.run_for(u64::MAX),
+            ["b", function_id, instruction] | ["d", function_id, instruction] => {
+                let (Ok(function_id), Ok(instruction)) = (function_id.parse(), instruction.parse()) else {
+                    println!("Expected a function id and an instruction index");
+                    continue;
+                };
+                let breakpoint = Breakpoint { function_id, instruction };
+                if words[0] == "b" {
+                    interpreter.debugger_mut().add_breakpoint(breakpoint);
+                } else if !interpreter.debugger_mut().remove_breakpoint(breakpoint) {
+                    println!("No breakpoint at function {function_id} instruction {instruction}");
+                }
+                continue;
+            }
+            ["w", function_id, variable_id] => {
+                let (Ok(function_id), Ok(variable_id)) = (function_id.parse(), variable_id.parse()) else {
+                    println!("Expected a function id and a variable id");
+                    continue;
+                };
+                interpreter.debugger_mut().add_watchpoint(Watchpoint { function_id, variable_id });
+                continue;
+            }
+            ["bt"] | ["frames"] => {
+                print_frames(&interpreter);
+                continue;
+            }
+            ["l"] | ["list"] => {
+                print_listing(&interpreter);
+                continue;
+            }
+            ["q"] | ["quit"] => return,
+            _ => {
+                println!("{HELP}");
+                continue;
+            }
+        };
+
+        match status {
+            ExecutionStatus::Running => print_location(&interpreter),
+            ExecutionStatus::Yielded => {
+                println!("Yielded");
+                print_location(&interpreter);
+            }
+            ExecutionStatus::Breakpoint(breakpoint) => {
+                println!(
+                    "Breakpoint at function {} instruction {}",
+                    breakpoint.function_id, breakpoint.instruction
+                );
+                print_location(&interpreter);
+            }
+            ExecutionStatus::Watchpoint(hit) => {
+                println!(
+                    "Watchpoint on function {} variable {}: {:?} -> {:?}",
+                    hit.watchpoint.function_id, hit.watchpoint.variable_id, hit.old_value, hit.new_value
+                );
+                print_location(&interpreter);
+            }
+            ExecutionStatus::Error(error) => {
+                println!("{error}");
+                print_location(&interpreter);
+            }
+            ExecutionStatus::Finished(value) => {
+                println!("Finished, returned {:?}", value);
+                return;
+            }
+        }
+    }
+}
+
+fn print_location(interpreter: &Interpreter) {
+    let frames = interpreter.frames();
+    let Some(frame) = frames.last() else {
+        return;
+    };
+    let instruction = interpreter
+        .program()
+        .get_function(frame.function_id)
+        .ok()
+        .and_then(|function| function.instructions().get(frame.instruction_counter));
+    match instruction {
+        Some(instruction) => println!("{:0>3}:{:0>3} ==> {:?}", frame.function_id, frame.instruction_counter, instruction),
+        None => println!("{:0>3}:{:0>3} ==> end of function", frame.function_id, frame.instruction_counter),
+    }
+}
+
+fn print_frames(interpreter: &Interpreter) {
+    for (depth, frame) in interpreter.frames().iter().enumerate().rev() {
+        println!(
+            "#{depth} function {} at instruction {}",
+            frame.function_id, frame.instruction_counter
+        );
+        for (id, variable) in frame.variables.iter().enumerate() {
+            println!("    Variable ID {id: >4} Variable value: {:?}", variable);
+        }
+        for value in frame.parameter_stack {
+            println!("    Pushed parameter: {:?}", value);
+        }
+    }
+}
+
+fn print_listing(interpreter: &Interpreter) {
+    let frames = interpreter.frames();
+    let Some(frame) = frames.last() else {
+        return;
+    };
+    let Ok(function) = interpreter.program().get_function(frame.function_id) else {
+        return;
+    };
+    let breakpoints: Vec<&Breakpoint> = interpreter.debugger().breakpoints().collect();
+    for (no, instruction) in function.instructions().iter().enumerate() {
+        let marker = if no == frame.instruction_counter { "==>" } else { "-" };
+        let breakpoint = breakpoints
+            .iter()
+            .any(|b| b.function_id == frame.function_id && b.instruction == no);
+        println!("{}{no:0>3} {marker} {:?}", if breakpoint { "*" } else { " " }, instruction);
+    }
+}
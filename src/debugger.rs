@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+
+use crate::{
+    interpreter::{ExecutionStatus, Interpreter},
+    value::{FunctionIdType, Value, VariableIdType},
+};
+
+/// An instruction location. Execution stops before running the instruction at a breakpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Breakpoint {
+    pub function_id: FunctionIdType,
+    pub instruction: usize,
+}
+
+/// A variable of a function. Execution stops after an instruction changes its value in any frame of that function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub function_id: FunctionIdType,
+    pub variable_id: VariableIdType,
+}
+
+/// A watched variable that just changed.
+#[derive(Debug, Clone)]
+pub struct WatchpointHit {
+    pub watchpoint: Watchpoint,
+    pub old_value: Value,
+    pub new_value: Value,
+}
+
+/// A read-only view of one frame on the callstack.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameView<'a> {
+    pub function_id: FunctionIdType,
+    /// The next instruction the frame will run.
+    pub instruction_counter: usize,
+    pub variables: &'a [Value],
+    pub parameter_stack: &'a [Value],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: HashSet<Breakpoint>,
+    watchpoints: HashSet<Watchpoint>,
+    // The breakpoint execution last stopped at, and the call depth it was hit at, so resuming runs past it.
+    stopped_at: Option<(Breakpoint, usize)>,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.insert(breakpoint);
+    }
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.remove(&breakpoint)
+    }
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.iter()
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.insert(watchpoint);
+    }
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.watchpoints.remove(&watchpoint)
+    }
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter()
+    }
+    pub(crate) fn has_breakpoints(&self) -> bool {
+        !self.breakpoints.is_empty()
+    }
+    pub(crate) fn has_watchpoints(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+    pub(crate) fn is_watched(&self, watchpoint: &Watchpoint) -> bool {
+        self.watchpoints.contains(watchpoint)
+    }
+    /// Whether execution should stop before `location`. A breakpoint that was just reported is skipped once so
+    /// that running again makes progress.
+    pub(crate) fn should_break(&mut self, location: Breakpoint, depth: usize) -> bool {
+        if self.stopped_at.take() == Some((location, depth)) {
+            return false;
+        }
+        if self.breakpoints.contains(&location) {
+            self.stopped_at = Some((location, depth));
+            return true;
+        }
+        false
+    }
+}
+
+impl Interpreter {
+    /// Executes the next instruction, running any function it calls to completion.
+    pub fn step_over(&mut self) -> ExecutionStatus {
+        let depth = self.call_depth();
+        let mut status = self.step();
+        while matches!(status, ExecutionStatus::Running) && self.call_depth() > depth {
+            status = self.step();
+        }
+        status
+    }
+    /// Runs until the current function returns to its caller.
+    pub fn step_out(&mut self) -> ExecutionStatus {
+        let depth = self.call_depth();
+        loop {
+            let status = self.step();
+            if !matches!(status, ExecutionStatus::Running) || self.call_depth() < depth {
+                return status;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::debugger::*;
+    use crate::{
+        instructions::Instruction,
+        interpreter::{Function, Program},
+        value::VariableType,
+    };
+
+    // main calls `double(21)` and stores the result in var 1
+    fn program() -> Program {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::U64, VariableType::U64]).unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::U64(21)),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 1),
+            Instruction::AddI(1, Value::U64(1)),
+        ]);
+        let mut double = Function::new(&[VariableType::U64], Some(VariableType::U64));
+        double.set_instructions(vec![Instruction::Add(0, 0), Instruction::Return(0)]);
+        Program::new(&[main, double])
+    }
+
+    #[test]
+    fn test_breakpoint_stops_and_resumes() {
+        let mut interpreter = Interpreter::new(program()).unwrap();
+        let breakpoint = Breakpoint {
+            function_id: 1,
+            instruction: 1,
+        };
+        interpreter.debugger_mut().add_breakpoint(breakpoint);
+
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Breakpoint(b) if b == breakpoint));
+        let frames = interpreter.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].instruction_counter, 3);
+        assert_eq!(frames[1].function_id, 1);
+        assert_eq!(frames[1].variables, &[Value::U64(42)]);
+
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(None)));
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut interpreter = Interpreter::new(program()).unwrap();
+        interpreter.step();
+        interpreter.step();
+        // the call runs to completion
+        assert!(matches!(interpreter.step_over(), ExecutionStatus::Running));
+        assert_eq!(interpreter.call_depth(), 1);
+        assert_eq!(interpreter.frames()[0].instruction_counter, 3);
+
+        let mut interpreter = Interpreter::new(program()).unwrap();
+        interpreter.run_for(3);
+        assert_eq!(interpreter.call_depth(), 2);
+        assert!(matches!(interpreter.step_out(), ExecutionStatus::Running));
+        assert_eq!(interpreter.call_depth(), 1);
+        assert_eq!(interpreter.frames()[0].variables[1], Value::U64(42));
+    }
+
+    #[test]
+    fn test_watchpoint_reports_changes() {
+        let mut interpreter = Interpreter::new(program()).unwrap();
+        interpreter.debugger_mut().add_watchpoint(Watchpoint {
+            function_id: 0,
+            variable_id: 1,
+        });
+
+        let ExecutionStatus::Watchpoint(hit) = interpreter.run_for(100) else {
+            panic!("expected the return value to trigger the watchpoint");
+        };
+        assert_eq!((hit.old_value, hit.new_value), (Value::U64(0), Value::U64(42)));
+
+        let ExecutionStatus::Watchpoint(hit) = interpreter.run_for(100) else {
+            panic!("expected AddI to trigger the watchpoint");
+        };
+        assert_eq!(hit.new_value, Value::U64(43));
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(None)));
+    }
+    #[test]
+    fn test_watchpoint_sees_writes_through_references() {
+        // main passes a reference to var 0 to `set`, which stores 7 through it, then stores 9 through it itself
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::U64, VariableType::Ref(Box::new(VariableType::U64)), VariableType::U64])
+            .unwrap();
+        main.set_instructions(vec![
+            Instruction::MakeRef(1, 0),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallVoidFunction(1),
+            Instruction::SetI(2, Value::U64(9)),
+            Instruction::StoreRef(1, 2),
+        ]);
+        let mut set = Function::new(&[VariableType::Ref(Box::new(VariableType::U64))], None);
+        set.register_variable(VariableType::U64).unwrap();
+        set.set_instructions(vec![Instruction::SetI(1, Value::U64(7)), Instruction::StoreRef(0, 1)]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, set])).unwrap();
+        interpreter.debugger_mut().add_watchpoint(Watchpoint {
+            function_id: 0,
+            variable_id: 0,
+        });
+
+        let ExecutionStatus::Watchpoint(hit) = interpreter.run_for(100) else {
+            panic!("expected the callee's write to trigger the watchpoint");
+        };
+        assert_eq!((hit.old_value, hit.new_value), (Value::U64(0), Value::U64(7)));
+        assert_eq!(interpreter.call_depth(), 2);
+        let ExecutionStatus::Watchpoint(hit) = interpreter.run_for(100) else {
+            panic!("expected main's own write to trigger the watchpoint");
+        };
+        assert_eq!((hit.old_value, hit.new_value), (Value::U64(7), Value::U64(9)));
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(None)));
+    }
+}
@@ -0,0 +1,319 @@
+//! Builds arbitrary programs from raw bytes, for fuzzing the interpreter against panics.
+
+use crate::{
+    instructions::{Capture, Instruction},
+    interpreter::{Function, Interpreter, Program},
+    interpreter_error::InterpreterError,
+    value::{
+        ArrayValue, EnumDefinition, EnumIdType, FunctionValue, MapValue, ObjectValue, OptionValue, RefValue, Signature, StructDefinition,
+        StructIdType, Value, VariableIdType, VariableType,
+    },
+};
+
+// every program defines this many structs and enums, so generated struct and enum types exist unless a definition
+// is deliberately malformed
+const STRUCT_COUNT: StructIdType = 3;
+const ENUM_COUNT: EnumIdType = 2;
+
+/// Reads bytes in order, producing zeroes once they run out so every input decodes to some program.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl ByteReader<'_> {
+    fn u8(&mut self) -> u8 {
+        let byte = self.bytes.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        byte
+    }
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+    fn below(&mut self, bound: u8) -> u8 {
+        self.u8() % bound
+    }
+    /// Mostly small variable ids, occasionally out of range ones.
+    fn var_id(&mut self) -> VariableIdType {
+        match self.below(16) {
+            0 => self.u16(),
+            n => (n - 1) as VariableIdType,
+        }
+    }
+    /// Mostly existing function ids, occasionally out of range ones.
+    fn function_id(&mut self) -> u16 {
+        match self.below(8) {
+            0 => self.u16(),
+            n => (n % 4) as u16,
+        }
+    }
+    /// Mostly existing native ids, occasionally out of range ones.
+    fn native_id(&mut self) -> u16 {
+        match self.below(16) {
+            0 => self.u16(),
+            n => (n % 13) as u16,
+        }
+    }
+    fn target(&mut self) -> usize {
+        self.below(40) as usize
+    }
+    fn var_type(&mut self, depth: u8) -> VariableType {
+        match self.below(if depth == 0 { 10 } else { 16 }) {
+            0 => VariableType::U8,
+            1 => VariableType::U16,
+            2 => VariableType::U32,
+            3 => VariableType::U64,
+            4 => VariableType::Bool,
+            5 => VariableType::String,
+            6 => VariableType::Char,
+            7 => VariableType::Bytes,
+            8 => VariableType::Struct(self.below(STRUCT_COUNT as u8) as StructIdType),
+            9 => VariableType::Enum(self.below(ENUM_COUNT as u8) as EnumIdType),
+            10 => VariableType::Array(Box::new(self.var_type(depth - 1))),
+            11 => VariableType::Map(Box::new(self.key_type()), Box::new(self.var_type(depth - 1))),
+            12 => VariableType::Option(Box::new(self.var_type(depth - 1))),
+            13 => VariableType::Ref(Box::new(self.var_type(depth - 1))),
+            14 => VariableType::Object(Box::new(self.var_type(depth - 1))),
+            _ => VariableType::Function(Box::new(self.signature(depth - 1))),
+        }
+    }
+    fn signature(&mut self, depth: u8) -> Signature {
+        let parameters: Vec<VariableType> = (0..self.below(3)).map(|_| self.var_type(depth)).collect();
+        let return_type = match self.below(2) {
+            0 => None,
+            _ => Some(self.var_type(depth)),
+        };
+        Signature::new(&parameters, return_type)
+    }
+    fn key_type(&mut self) -> VariableType {
+        match self.var_type(0) {
+            VariableType::Struct(_) | VariableType::Enum(_) => VariableType::U8,
+            key_type => key_type,
+        }
+    }
+    /// A type `Program::with_types` rejects in a definition: an undefined struct or enum, the definition's own type,
+    /// or a map with an unhashable key.
+    fn malformed_type(&mut self, own_type: VariableType) -> VariableType {
+        match self.below(4) {
+            0 => VariableType::Struct(STRUCT_COUNT + self.below(4) as StructIdType),
+            1 => VariableType::Enum(ENUM_COUNT + self.below(4) as EnumIdType),
+            2 => own_type,
+            _ => VariableType::Map(
+                Box::new(VariableType::Array(Box::new(VariableType::U8))),
+                Box::new(VariableType::U8),
+            ),
+        }
+    }
+    /// Fields hold only earlier structs and no enums directly, so definitions never contain themselves, unless a
+    /// malformed field is added.
+    fn struct_definition(&mut self, struct_id: StructIdType) -> StructDefinition {
+        let mut fields: Vec<VariableType> = (0..self.below(4))
+            .map(|_| match self.var_type(1) {
+                VariableType::Struct(_) if struct_id > 0 => VariableType::Struct(struct_id - 1),
+                VariableType::Struct(_) | VariableType::Enum(_) => VariableType::U64,
+                var_type => var_type,
+            })
+            .collect();
+        if self.below(16) == 0 {
+            fields.push(self.malformed_type(VariableType::Struct(struct_id)));
+        }
+        StructDefinition::new(&fields)
+    }
+    /// The first variant holds only earlier enums directly, so default values never contain themselves.
+    fn enum_definition(&mut self, enum_id: EnumIdType) -> EnumDefinition {
+        let mut variants: Vec<Option<VariableType>> = (0..=self.below(3))
+            .map(|_| match self.below(3) {
+                0 => None,
+                _ => Some(self.var_type(1)),
+            })
+            .collect();
+        if let Some(VariableType::Enum(_)) = variants[0] {
+            variants[0] = enum_id.checked_sub(1).map(VariableType::Enum);
+        }
+        if self.below(16) == 0 {
+            let variant = self.below(variants.len() as u8) as usize;
+            variants[variant] = Some(self.malformed_type(VariableType::Enum(enum_id)));
+        }
+        EnumDefinition::new(&variants)
+    }
+    fn value(&mut self) -> Value {
+        match self.below(17) {
+            0 => Value::U8(self.u8()),
+            1 => Value::U16(self.u16()),
+            2 => Value::U32(self.u16() as u32),
+            3 => Value::U64(self.below(8) as u64),
+            4 => Value::Bool(self.u8() & 1 == 1),
+            5 => Value::String((0..self.below(4)).map(|_| (b'a' + self.below(26)) as char).collect()),
+            6 => Value::Array(ArrayValue::U64Array((0..self.below(4)).map(|_| self.u8() as u64).collect())),
+            7 => Value::Array(ArrayValue::new(self.var_type(1))),
+            8 => Value::Option(OptionValue::none(self.var_type(1))),
+            9 => Value::Option(OptionValue::some(Value::U64(self.u8() as u64))),
+            10 => Value::Char(char::from_u32(self.u16() as u32).unwrap_or(char::REPLACEMENT_CHARACTER)),
+            11 => Value::Bytes((0..self.below(4)).map(|_| self.u8()).collect()),
+            12 => Value::Ref(RefValue::null(self.var_type(1))),
+            13 => Value::Object(ObjectValue::null(self.var_type(1))),
+            14 => Value::Function(FunctionValue::null(self.signature(1))),
+            15 => Value::Function(FunctionValue::new(self.signature(1), self.function_id())),
+            _ => {
+                let mut map = MapValue::new(VariableType::U8, VariableType::U64);
+                for _ in 0..self.below(4) {
+                    map.insert(Value::U8(self.below(8)), Value::U64(self.u8() as u64))
+                        .expect("entries match the map types");
+                }
+                Value::Map(map)
+            }
+        }
+    }
+    fn instruction(&mut self) -> Instruction {
+        match self.below(70) {
+            0 => Instruction::Set(self.var_id(), self.var_id()),
+            1 => Instruction::SetI(self.var_id(), self.value()),
+            2 => Instruction::SetArrayIndex(self.var_id(), self.var_id(), self.var_id()),
+            3 => Instruction::SetArrayIndexI(self.var_id(), self.var_id(), self.value()),
+            4 => Instruction::SetArrayIIndex(self.var_id(), self.value(), self.var_id()),
+            5 => Instruction::GetArrayIndex(self.var_id(), self.var_id(), self.var_id()),
+            6 => Instruction::GetArrayIndexI(self.var_id(), self.var_id(), self.value()),
+            7 => Instruction::Add(self.var_id(), self.var_id()),
+            8 => Instruction::Sub(self.var_id(), self.var_id()),
+            9 => Instruction::Mul(self.var_id(), self.var_id()),
+            10 => Instruction::Div(self.var_id(), self.var_id()),
+            11 => Instruction::Rem(self.var_id(), self.var_id()),
+            12 => Instruction::AddI(self.var_id(), self.value()),
+            13 => Instruction::SubI(self.var_id(), self.value()),
+            14 => Instruction::MulI(self.var_id(), self.value()),
+            15 => Instruction::DivI(self.var_id(), self.value()),
+            16 => Instruction::RemI(self.var_id(), self.value()),
+            17 => Instruction::LessThan(self.var_id(), self.var_id(), self.var_id()),
+            18 => Instruction::LessThanI(self.var_id(), self.var_id(), self.value()),
+            19 => Instruction::GreaterThan(self.var_id(), self.var_id(), self.var_id()),
+            20 => Instruction::GreaterThanI(self.var_id(), self.var_id(), self.value()),
+            21 => Instruction::LessThanOrEqual(self.var_id(), self.var_id(), self.var_id()),
+            22 => Instruction::LessThanOrEqualI(self.var_id(), self.var_id(), self.value()),
+            23 => Instruction::GreaterThanOrEqual(self.var_id(), self.var_id(), self.var_id()),
+            24 => Instruction::GreaterThanOrEqualI(self.var_id(), self.var_id(), self.value()),
+            25 => Instruction::Equals(self.var_id(), self.var_id(), self.var_id()),
+            26 => Instruction::EqualsI(self.var_id(), self.var_id(), self.value()),
+            27 => Instruction::NotEquals(self.var_id(), self.var_id(), self.var_id()),
+            28 => Instruction::NotEqualsI(self.var_id(), self.var_id(), self.value()),
+            29 => Instruction::Or(self.var_id(), self.var_id()),
+            30 => Instruction::And(self.var_id(), self.var_id()),
+            31 => Instruction::Xor(self.var_id(), self.var_id()),
+            32 => Instruction::Not(self.var_id()),
+            33 => Instruction::Goto(self.target()),
+            34 => Instruction::GotoIfTrue(self.target(), self.var_id()),
+            35 | 36 => Instruction::PushFunctionParameter(self.var_id()),
+            37 => Instruction::CallVoidFunction(self.function_id()),
+            38 => Instruction::CallFunction(self.function_id(), self.var_id()),
+            39 => Instruction::CallNativeVoidFunction(self.native_id()),
+            40 => Instruction::CallNativeVoidMethod(self.var_id(), self.native_id()),
+            41 => Instruction::CallNativeMethod(self.var_id(), self.var_id(), self.native_id()),
+            42 => Instruction::GetField(self.var_id(), self.var_id(), self.below(4) as u16),
+            43 => Instruction::SetField(self.var_id(), self.below(4) as u16, self.var_id()),
+            44 => Instruction::SetFieldI(self.var_id(), self.below(4) as u16, self.value()),
+            45 => Instruction::WrapSome(self.var_id(), self.var_id()),
+            46 => Instruction::SetNone(self.var_id()),
+            47 => Instruction::IsSome(self.var_id(), self.var_id()),
+            48 => Instruction::Unwrap(self.var_id(), self.var_id()),
+            49 => Instruction::SetVariant(self.var_id(), self.below(4) as u16, self.var_id()),
+            50 => Instruction::SetUnitVariant(self.var_id(), self.below(4) as u16),
+            51 => Instruction::GetTag(self.var_id(), self.var_id()),
+            52 => Instruction::GetPayload(self.var_id(), self.var_id(), self.below(4) as u16),
+            53 => Instruction::Switch(self.var_id(), (0..self.below(4)).map(|_| self.target()).collect()),
+            54 => Instruction::MakeRef(self.var_id(), self.var_id()),
+            55 => Instruction::AllocRef(self.var_id(), self.var_id()),
+            56 => Instruction::LoadRef(self.var_id(), self.var_id()),
+            57 => Instruction::StoreRef(self.var_id(), self.var_id()),
+            58 => Instruction::GetRefIndex(self.var_id(), self.var_id(), self.var_id()),
+            59 => Instruction::SetRefIndex(self.var_id(), self.var_id(), self.var_id()),
+            60 => Instruction::NewObject(self.var_id(), self.var_id()),
+            61 => Instruction::GetObject(self.var_id(), self.var_id()),
+            62 => Instruction::SetObject(self.var_id(), self.var_id()),
+            63 => Instruction::ObjectRef(self.var_id(), self.var_id()),
+            64 => Instruction::CallVoidIndirect(self.var_id()),
+            65 => Instruction::CallIndirect(self.var_id(), self.var_id()),
+            66 => {
+                let captures = (0..self.below(3))
+                    .map(|_| match self.below(2) {
+                        0 => Capture::Value(self.var_id()),
+                        _ => Capture::Ref(self.var_id()),
+                    })
+                    .collect();
+                Instruction::MakeClosure(self.var_id(), self.function_id(), captures)
+            }
+            67 | 68 => Instruction::Return(self.var_id()),
+            _ => Instruction::Yield,
+        }
+    }
+    fn function(&mut self) -> Function {
+        let parameters: Vec<VariableType> = (0..self.below(3)).map(|_| self.var_type(2)).collect();
+        let return_type = match self.below(2) {
+            0 => None,
+            _ => Some(self.var_type(2)),
+        };
+        let mut function = Function::new(&parameters, return_type);
+        let variables: Vec<VariableType> = (0..self.below(10)).map(|_| self.var_type(2)).collect();
+        // a dozen variables at most, far below the id limit
+        let _ = function.register_variables(&variables);
+        function.set_instructions((0..self.below(32)).map(|_| self.instruction()).collect());
+        function
+    }
+}
+
+/// Decodes `bytes` into a program of up to four functions. Some inputs decode to malformed struct or enum
+/// definitions, which fail the same way as in `Program::with_types`.
+pub fn program_from_bytes(bytes: &[u8]) -> Result<Program, InterpreterError> {
+    let mut reader = ByteReader { bytes, position: 0 };
+    let structs: Vec<StructDefinition> = (0..STRUCT_COUNT).map(|struct_id| reader.struct_definition(struct_id)).collect();
+    let enums: Vec<EnumDefinition> = (0..ENUM_COUNT).map(|enum_id| reader.enum_definition(enum_id)).collect();
+    let functions: Vec<Function> = (0..reader.below(5)).map(|_| reader.function()).collect();
+    Program::with_types(&functions, &structs, &enums)
+}
+
+/// Runs `program` with limits that keep arbitrary programs short, returning without panicking whatever it does.
+pub fn run_program(program: Program) {
+    let Ok(mut interpreter) = Interpreter::new(program) else {
+        return;
+    };
+    interpreter.set_fuel(Some(10_000));
+    interpreter.set_max_call_depth(64);
+    interpreter.set_max_parameter_stack_size(64);
+    interpreter.set_memory_limit(Some(1 << 20));
+    // collect often, so collections happen while frames and objects are in every state
+    interpreter.set_gc_threshold(1 << 10);
+    let _ = interpreter.execute();
+    interpreter.collect_garbage();
+
+    // whatever state execution stopped in must survive a snapshot
+    let snapshot = interpreter.snapshot();
+    interpreter
+        .restore(&snapshot)
+        .expect("restoring a snapshot of the same interpreter failed");
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fuzzing::*;
+
+    #[test]
+    fn test_arbitrary_programs_do_not_panic() {
+        // xorshift, so the same inputs are checked on every run
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut malformed = 0;
+        for _ in 0..2000 {
+            let bytes: Vec<u8> = (0..1024)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            match program_from_bytes(&bytes) {
+                Ok(program) => run_program(program),
+                Err(_) => malformed += 1,
+            }
+        }
+        // both outcomes are exercised
+        assert!(malformed > 0 && malformed < 2000);
+    }
+}
@@ -0,0 +1,175 @@
+//! Objects that live outside any frame, reached through references and object handles, and the garbage collector
+//! that frees them once nothing reaches them.
+
+use crate::{
+    interpreter_error::InterpreterError,
+    value::{HeapIdType, RefTarget, Value},
+};
+
+/// Heap bytes allocated before the first automatic collection.
+pub const DEFAULT_GC_THRESHOLD: usize = 1 << 20;
+
+/// Counters kept by the garbage collector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Collections run, automatic or forced.
+    pub collections: u64,
+    pub objects_freed: u64,
+    pub bytes_freed: u64,
+    /// Objects on the heap, including unreachable ones not collected yet.
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+/// Slots holding heap objects, addressed by id. Slots freed by a collection are reused by later allocations.
+#[derive(Debug)]
+pub(crate) struct Heap {
+    objects: Vec<Option<Value>>,
+    free: Vec<HeapIdType>,
+    // Approximate bytes held by the objects, counted the same way as frame variables.
+    allocated_bytes: usize,
+    // An automatic collection runs once `allocated_bytes` reaches this.
+    next_collection: usize,
+    threshold: usize,
+    collections: u64,
+    objects_freed: u64,
+    bytes_freed: u64,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::with_objects(Vec::new())
+    }
+}
+
+impl Heap {
+    /// A heap holding `objects`, where empty slots are free.
+    pub(crate) fn with_objects(objects: Vec<Option<Value>>) -> Self {
+        let allocated_bytes = objects.iter().flatten().map(object_size).sum();
+        let free = (0..objects.len() as HeapIdType)
+            .rev()
+            .filter(|object_id| objects[*object_id as usize].is_none())
+            .collect();
+        Heap {
+            objects,
+            free,
+            allocated_bytes,
+            next_collection: DEFAULT_GC_THRESHOLD.max(allocated_bytes * 2),
+            threshold: DEFAULT_GC_THRESHOLD,
+            collections: 0,
+            objects_freed: 0,
+            bytes_freed: 0,
+        }
+    }
+    /// Moves `value` onto the heap, returning the id of its slot.
+    pub(crate) fn allocate(&mut self, value: Value) -> Result<HeapIdType, InterpreterError> {
+        self.allocated_bytes += object_size(&value);
+        if let Some(object_id) = self.free.pop() {
+            self.objects[object_id as usize] = Some(value);
+            return Ok(object_id);
+        }
+        let Ok(object_id) = HeapIdType::try_from(self.objects.len()) else {
+            self.allocated_bytes -= object_size(&value);
+            return Err(InterpreterError::HeapExhausted);
+        };
+        self.objects.push(Some(value));
+        Ok(object_id)
+    }
+    pub(crate) fn get(&self, object_id: HeapIdType) -> Result<&Value, InterpreterError> {
+        match self.objects.get(object_id as usize) {
+            Some(Some(value)) => Ok(value),
+            _ => Err(InterpreterError::DanglingReference),
+        }
+    }
+    /// Changes the object `object_id` in place. `change` returns the heap size of what it removed and what it added,
+    /// so the heap's memory use can follow.
+    pub(crate) fn change(
+        &mut self,
+        object_id: HeapIdType,
+        change: impl FnOnce(&mut Value) -> Result<(usize, usize), InterpreterError>,
+    ) -> Result<(), InterpreterError> {
+        let Some(Some(value)) = self.objects.get_mut(object_id as usize) else {
+            return Err(InterpreterError::DanglingReference);
+        };
+        let (removed, added) = change(value)?;
+        self.allocated_bytes = self.allocated_bytes - removed + added;
+        Ok(())
+    }
+    pub(crate) fn objects(&self) -> &[Option<Value>] {
+        &self.objects
+    }
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes
+    }
+    /// Whether the heap has grown enough since the last collection to collect again.
+    pub(crate) fn should_collect(&self) -> bool {
+        self.allocated_bytes >= self.next_collection
+    }
+    /// Heap bytes allocated before an automatic collection, grown to twice what survives each collection.
+    pub(crate) fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+        self.next_collection = threshold.max(self.allocated_bytes * 2);
+    }
+    pub(crate) fn threshold(&self) -> usize {
+        self.threshold
+    }
+    /// Frees every object that no value in `roots` reaches, directly or through other objects.
+    pub(crate) fn collect<'a>(&mut self, roots: impl Iterator<Item = &'a Value>) {
+        let mut pending = Vec::new();
+        let mut push_object = |target: RefTarget| {
+            if let RefTarget::Heap(object_id) = target {
+                pending.push(object_id);
+            }
+        };
+        roots.for_each(|root| root.visit_targets(&mut push_object));
+
+        let mut reachable = vec![false; self.objects.len()];
+        while let Some(object_id) = pending.pop() {
+            match reachable.get_mut(object_id as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => continue,
+            }
+            if let Some(Some(object)) = self.objects.get(object_id as usize) {
+                object.visit_targets(&mut |target| {
+                    if let RefTarget::Heap(object_id) = target {
+                        pending.push(object_id);
+                    }
+                });
+            }
+        }
+
+        for (object_id, slot) in self.objects.iter_mut().enumerate() {
+            if reachable[object_id] {
+                continue;
+            }
+            if let Some(object) = slot.take() {
+                let size = object_size(&object);
+                self.allocated_bytes -= size;
+                self.bytes_freed += size as u64;
+                self.objects_freed += 1;
+                self.free.push(object_id as HeapIdType);
+            }
+        }
+        self.collections += 1;
+        self.next_collection = self.threshold.max(self.allocated_bytes * 2);
+    }
+    pub(crate) fn stats(&self) -> GcStats {
+        GcStats {
+            collections: self.collections,
+            objects_freed: self.objects_freed,
+            bytes_freed: self.bytes_freed,
+            objects: self.objects.len() - self.free.len(),
+            bytes: self.allocated_bytes,
+        }
+    }
+    /// Restores counters saved in a snapshot.
+    pub(crate) fn set_stats(&mut self, stats: GcStats) {
+        self.collections = stats.collections;
+        self.objects_freed = stats.objects_freed;
+        self.bytes_freed = stats.bytes_freed;
+    }
+}
+
+fn object_size(value: &Value) -> usize {
+    size_of::<Value>() + value.heap_size()
+}
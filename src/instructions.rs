@@ -1,4 +1,4 @@
-use crate::value::{FunctionIdType, Value, VariableIdType};
+use crate::value::{FieldIdType, FunctionIdType, Value, VariableIdType, VariantIdType};
 
 #[derive(Debug, Clone)]
 #[repr(align(64))]
@@ -11,6 +11,31 @@ pub enum Instruction {
     // SetArrayIIndexI(VariableIdType, Value, Value),                 // array[I] = J
     GetArrayIndex(VariableIdType, VariableIdType, VariableIdType),
     GetArrayIndexI(VariableIdType, VariableIdType, Value),
+    GetField(VariableIdType, VariableIdType, FieldIdType), // a = struct.field
+    SetField(VariableIdType, FieldIdType, VariableIdType), // struct.field = a
+    SetFieldI(VariableIdType, FieldIdType, Value),         // struct.field = I
+    // Options
+    WrapSome(VariableIdType, VariableIdType), // option = Some(a)
+    SetNone(VariableIdType),                  // option = None
+    IsSome(VariableIdType, VariableIdType),   // a = option.is_some()
+    Unwrap(VariableIdType, VariableIdType),   // a = option.unwrap()
+    // Enums
+    SetVariant(VariableIdType, VariantIdType, VariableIdType), // enum = Variant(a)
+    SetUnitVariant(VariableIdType, VariantIdType),             // enum = Variant
+    GetTag(VariableIdType, VariableIdType),                    // a = enum.tag as u16
+    GetPayload(VariableIdType, VariableIdType, VariantIdType), // a = payload of enum, which must be Variant
+    // References
+    MakeRef(VariableIdType, VariableIdType),                     // ref = &a
+    AllocRef(VariableIdType, VariableIdType),                    // ref = a copy of a on the heap
+    LoadRef(VariableIdType, VariableIdType),                     // a = *ref
+    StoreRef(VariableIdType, VariableIdType),                    // *ref = a
+    GetRefIndex(VariableIdType, VariableIdType, VariableIdType), // a = (*ref)[b]
+    SetRefIndex(VariableIdType, VariableIdType, VariableIdType), // (*ref)[a] = b
+    // Objects
+    NewObject(VariableIdType, VariableIdType), // object = a new heap object holding a copy of a
+    GetObject(VariableIdType, VariableIdType), // a = *object
+    SetObject(VariableIdType, VariableIdType), // *object = a
+    ObjectRef(VariableIdType, VariableIdType), // ref = &*object
     // Arithmetic
     Add(VariableIdType, VariableIdType),
     Sub(VariableIdType, VariableIdType),
@@ -45,14 +70,219 @@ pub enum Instruction {
     // Control
     Goto(usize),                       // used for loop breaks and continues
     GotoIfTrue(usize, VariableIdType), // used for
+    /// Jumps to the target at the index given by an enum's tag or an integer, continuing with the next instruction
+    /// when the index is past the end of the table.
+    Switch(VariableIdType, Vec<usize>),
 
     PushFunctionParameter(VariableIdType),
     CallVoidFunction(FunctionIdType),
     CallFunction(FunctionIdType, VariableIdType),
+    CallVoidIndirect(VariableIdType),             // a(), a function value
+    CallIndirect(VariableIdType, VariableIdType), // b = a(), a function value
+    /// Stores a closure over the function in the variable, capturing variables of the running frame. The function
+    /// receives the captures, in order, in the variables following its parameters.
+    MakeClosure(VariableIdType, FunctionIdType, Vec<Capture>),
     CallNativeVoidFunction(FunctionIdType),
 
     CallNativeVoidMethod(VariableIdType, FunctionIdType),
     CallNativeMethod(VariableIdType, VariableIdType, FunctionIdType),
 
     Return(VariableIdType),
+    Yield, // pause execution, handing control back to the host
+}
+
+/// How a closure captures a variable of the frame creating it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    /// A copy of the variable's value at the time the closure is made.
+    Value(VariableIdType),
+    /// A reference to the variable, so the closure sees later writes and can write to it. The closure may not
+    /// outlive the frame, as for any reference.
+    Ref(VariableIdType),
+}
+
+impl Instruction {
+    /// The value embedded in the instruction, if it has one.
+    pub fn immediate(&self) -> Option<&Value> {
+        match self {
+            Instruction::SetI(_, value)
+            | Instruction::SetArrayIndexI(_, _, value)
+            | Instruction::SetArrayIIndex(_, value, _)
+            | Instruction::GetArrayIndexI(_, _, value)
+            | Instruction::SetFieldI(_, _, value)
+            | Instruction::AddI(_, value)
+            | Instruction::SubI(_, value)
+            | Instruction::MulI(_, value)
+            | Instruction::DivI(_, value)
+            | Instruction::RemI(_, value)
+            | Instruction::LessThanI(_, _, value)
+            | Instruction::GreaterThanI(_, _, value)
+            | Instruction::LessThanOrEqualI(_, _, value)
+            | Instruction::GreaterThanOrEqualI(_, _, value)
+            | Instruction::EqualsI(_, _, value)
+            | Instruction::NotEqualsI(_, _, value) => Some(value),
+            _ => None,
+        }
+    }
+    /// Variables in the running frame whose values this instruction uses.
+    pub fn read_variables(&self) -> Vec<VariableIdType> {
+        match self {
+            Instruction::SetI(_, _)
+            | Instruction::Goto(_)
+            | Instruction::CallVoidFunction(_)
+            | Instruction::CallFunction(_, _)
+            | Instruction::CallNativeVoidFunction(_)
+            | Instruction::MakeRef(_, _)
+            | Instruction::Yield => Vec::new(),
+            Instruction::Set(_, var_id)
+            | Instruction::GetArrayIndexI(var_id, _, _)
+            | Instruction::GetField(var_id, _, _)
+            | Instruction::SetFieldI(var_id, _, _)
+            | Instruction::SetNone(var_id)
+            | Instruction::IsSome(var_id, _)
+            | Instruction::Unwrap(var_id, _)
+            | Instruction::SetUnitVariant(var_id, _)
+            | Instruction::GetTag(var_id, _)
+            | Instruction::GetPayload(var_id, _, _)
+            | Instruction::Switch(var_id, _)
+            | Instruction::AllocRef(_, var_id)
+            | Instruction::LoadRef(var_id, _)
+            | Instruction::NewObject(_, var_id)
+            | Instruction::GetObject(var_id, _)
+            | Instruction::ObjectRef(_, var_id)
+            | Instruction::AddI(var_id, _)
+            | Instruction::SubI(var_id, _)
+            | Instruction::MulI(var_id, _)
+            | Instruction::DivI(var_id, _)
+            | Instruction::RemI(var_id, _)
+            | Instruction::LessThanI(_, var_id, _)
+            | Instruction::GreaterThanI(_, var_id, _)
+            | Instruction::LessThanOrEqualI(_, var_id, _)
+            | Instruction::GreaterThanOrEqualI(_, var_id, _)
+            | Instruction::EqualsI(_, var_id, _)
+            | Instruction::NotEqualsI(_, var_id, _)
+            | Instruction::Not(var_id)
+            | Instruction::GotoIfTrue(_, var_id)
+            | Instruction::PushFunctionParameter(var_id)
+            | Instruction::CallNativeVoidMethod(var_id, _)
+            | Instruction::CallNativeMethod(var_id, _, _)
+            | Instruction::CallVoidIndirect(var_id)
+            | Instruction::CallIndirect(var_id, _)
+            | Instruction::Return(var_id) => vec![*var_id],
+            Instruction::SetArrayIndexI(var1_id, var2_id, _)
+            | Instruction::SetArrayIIndex(var1_id, _, var2_id)
+            | Instruction::GetArrayIndex(var1_id, _, var2_id)
+            | Instruction::SetField(var1_id, _, var2_id)
+            | Instruction::WrapSome(var1_id, var2_id)
+            | Instruction::SetVariant(var1_id, _, var2_id)
+            | Instruction::StoreRef(var1_id, var2_id)
+            | Instruction::GetRefIndex(var1_id, _, var2_id)
+            | Instruction::SetObject(var1_id, var2_id)
+            | Instruction::Add(var1_id, var2_id)
+            | Instruction::Sub(var1_id, var2_id)
+            | Instruction::Mul(var1_id, var2_id)
+            | Instruction::Div(var1_id, var2_id)
+            | Instruction::Rem(var1_id, var2_id)
+            | Instruction::LessThan(_, var1_id, var2_id)
+            | Instruction::GreaterThan(_, var1_id, var2_id)
+            | Instruction::LessThanOrEqual(_, var1_id, var2_id)
+            | Instruction::GreaterThanOrEqual(_, var1_id, var2_id)
+            | Instruction::Equals(_, var1_id, var2_id)
+            | Instruction::NotEquals(_, var1_id, var2_id)
+            | Instruction::Or(var1_id, var2_id)
+            | Instruction::And(var1_id, var2_id)
+            | Instruction::Xor(var1_id, var2_id) => vec![*var1_id, *var2_id],
+            Instruction::MakeClosure(_, _, captures) => captures
+                .iter()
+                .filter_map(|capture| match capture {
+                    Capture::Value(var_id) => Some(*var_id),
+                    Capture::Ref(_) => None,
+                })
+                .collect(),
+            Instruction::SetArrayIndex(array_var_id, index_var_id, value_var_id)
+            | Instruction::SetRefIndex(array_var_id, index_var_id, value_var_id) => vec![*array_var_id, *index_var_id, *value_var_id],
+        }
+    }
+    /// Variables in the running frame that executing this instruction may modify. The destination of a
+    /// `CallFunction` or `CallIndirect` is only written once the callee returns, so it is not included, and neither are variables
+    /// written through a reference, see `written_reference`, or heap objects.
+    pub fn written_variables(&self) -> Vec<VariableIdType> {
+        match self {
+            Instruction::Set(var_id, _)
+            | Instruction::SetI(var_id, _)
+            | Instruction::SetArrayIndex(var_id, _, _)
+            | Instruction::SetArrayIndexI(var_id, _, _)
+            | Instruction::SetArrayIIndex(var_id, _, _)
+            | Instruction::GetArrayIndex(_, var_id, _)
+            | Instruction::GetArrayIndexI(_, var_id, _)
+            | Instruction::GetField(_, var_id, _)
+            | Instruction::SetField(var_id, _, _)
+            | Instruction::SetFieldI(var_id, _, _)
+            | Instruction::WrapSome(var_id, _)
+            | Instruction::SetNone(var_id)
+            | Instruction::IsSome(_, var_id)
+            | Instruction::Unwrap(_, var_id)
+            | Instruction::SetVariant(var_id, _, _)
+            | Instruction::SetUnitVariant(var_id, _)
+            | Instruction::GetTag(_, var_id)
+            | Instruction::GetPayload(_, var_id, _)
+            | Instruction::MakeRef(var_id, _)
+            | Instruction::AllocRef(var_id, _)
+            | Instruction::LoadRef(_, var_id)
+            | Instruction::GetRefIndex(_, var_id, _)
+            | Instruction::NewObject(var_id, _)
+            | Instruction::GetObject(_, var_id)
+            | Instruction::ObjectRef(var_id, _)
+            | Instruction::Add(var_id, _)
+            | Instruction::Sub(var_id, _)
+            | Instruction::Mul(var_id, _)
+            | Instruction::Div(var_id, _)
+            | Instruction::Rem(var_id, _)
+            | Instruction::AddI(var_id, _)
+            | Instruction::SubI(var_id, _)
+            | Instruction::MulI(var_id, _)
+            | Instruction::DivI(var_id, _)
+            | Instruction::RemI(var_id, _)
+            | Instruction::LessThan(var_id, _, _)
+            | Instruction::LessThanI(var_id, _, _)
+            | Instruction::GreaterThan(var_id, _, _)
+            | Instruction::GreaterThanI(var_id, _, _)
+            | Instruction::LessThanOrEqual(var_id, _, _)
+            | Instruction::LessThanOrEqualI(var_id, _, _)
+            | Instruction::GreaterThanOrEqual(var_id, _, _)
+            | Instruction::GreaterThanOrEqualI(var_id, _, _)
+            | Instruction::Equals(var_id, _, _)
+            | Instruction::EqualsI(var_id, _, _)
+            | Instruction::NotEquals(var_id, _, _)
+            | Instruction::NotEqualsI(var_id, _, _)
+            | Instruction::Or(var_id, _)
+            | Instruction::And(var_id, _)
+            | Instruction::Xor(var_id, _)
+            | Instruction::Not(var_id)
+            | Instruction::MakeClosure(var_id, _, _)
+            | Instruction::CallNativeVoidMethod(var_id, _) => vec![*var_id],
+            Instruction::CallNativeMethod(var_id, return_var_id, _) => vec![*var_id, *return_var_id],
+            Instruction::Goto(_)
+            | Instruction::GotoIfTrue(_, _)
+            | Instruction::Switch(_, _)
+            | Instruction::StoreRef(_, _)
+            | Instruction::SetRefIndex(_, _, _)
+            | Instruction::SetObject(_, _)
+            | Instruction::PushFunctionParameter(_)
+            | Instruction::CallVoidFunction(_)
+            | Instruction::CallFunction(_, _)
+            | Instruction::CallVoidIndirect(_)
+            | Instruction::CallIndirect(_, _)
+            | Instruction::CallNativeVoidFunction(_)
+            | Instruction::Return(_)
+            | Instruction::Yield => Vec::new(),
+        }
+    }
+    /// The variable holding the reference this instruction writes through.
+    pub fn written_reference(&self) -> Option<VariableIdType> {
+        match self {
+            Instruction::StoreRef(ref_var_id, _) | Instruction::SetRefIndex(ref_var_id, _, _) => Some(*ref_var_id),
+            _ => None,
+        }
+    }
 }
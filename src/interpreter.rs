@@ -1,47 +1,106 @@
+use std::{fmt, sync::Arc, time::Instant};
+
 use crate::{
-    instructions::Instruction,
-    interpreter_error::InterpreterError,
-    operations::{op_add, op_equals, op_less_than, op_not_equals, op_rem, op_sub},
-    value::{ArrayValue, FunctionIdType, Value, VariableIdType, VariableType},
+    debugger::{Breakpoint, Debugger, FrameView, Watchpoint, WatchpointHit},
+    heap::{GcStats, Heap},
+    instructions::{Capture, Instruction},
+    interpreter_error::{Frame, InterpreterError, RuntimeError},
+    native,
+    operations::{
+        op_add, op_and, op_div, op_equals, op_greater_than, op_greater_than_or_equal, op_less_than, op_less_than_or_equal, op_mul, op_not,
+        op_not_equals, op_or, op_rem, op_sub, op_xor,
+    },
+    profiler::{Profile, Profiler},
+    snapshot::{program_fingerprint, SnapshotError, SnapshotReader, SnapshotWriter},
+    tracer::Tracer,
+    value::{
+        ArrayValue, EnumDefinition, EnumIdType, EnumValue, FunctionIdType, FunctionValue, MapValue, ObjectValue, OptionValue, RefTarget,
+        RefValue, Signature, StructDefinition, StructIdType, Value, VariableIdType, VariableType,
+    },
 };
 
 #[derive(Debug, Clone, Default)]
 pub struct Function {
+    name: Option<String>,
     instructions: Vec<Instruction>,
     variables: Vec<VariableType>,
     parameters: Vec<VariableType>,
     return_type: Option<VariableType>,
+    // Initial variable values, resolved once when the function is loaded into a `Program`.
+    default_values: Vec<Value>,
+    default_values_size: usize,
 }
 
 impl Function {
     pub fn new(parameters: &[VariableType], return_type: Option<VariableType>) -> Self {
-        let mut func = Function {
+        // parameters are the first variables
+        Function {
+            name: None,
             instructions: Vec::new(),
-            variables: Vec::new(),
+            variables: parameters.to_vec(),
             parameters: parameters.to_vec(),
             return_type,
-        };
-
-        for parameter_type in parameters.iter() {
-            func.register_variable(parameter_type.clone());
+            default_values: Vec::new(),
+            default_values_size: 0,
         }
-
-        func
+    }
+    /// Names the function in stack traces.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
     }
     pub fn set_instructions(&mut self, instructions: Vec<Instruction>) {
         self.instructions = instructions;
     }
-    pub fn register_variable(&mut self, var_type: VariableType) -> VariableIdType {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+    pub fn variables(&self) -> &[VariableType] {
+        &self.variables
+    }
+    pub(crate) fn default_values(&self) -> &[Value] {
+        &self.default_values
+    }
+    /// The parameter and return types, for function values naming this function.
+    pub fn signature(&self) -> Signature {
+        Signature::new(&self.parameters, self.return_type.clone())
+    }
+    fn has_signature(&self, signature: &Signature) -> bool {
+        self.parameters == signature.parameters() && self.return_type.as_ref() == signature.return_type()
+    }
+    /// Adds a variable, returning its id. Fails once every id is taken.
+    pub fn register_variable(&mut self, var_type: VariableType) -> Result<VariableIdType, InterpreterError> {
+        let Ok(var_id) = VariableIdType::try_from(self.variables.len()) else {
+            return Err(InterpreterError::TooManyVariables);
+        };
         self.variables.push(var_type);
-        self.variables.len() as u16
+        Ok(var_id)
     }
-    pub fn register_variables(&mut self, var_types: &[VariableType]) {
-        for var_type in var_types {
-            self.register_variable(var_type.clone());
+    /// Adds variables in order. Fails without adding any if they would not all get an id.
+    pub fn register_variables(&mut self, var_types: &[VariableType]) -> Result<(), InterpreterError> {
+        if self.variables.len() + var_types.len() > VariableIdType::MAX as usize + 1 {
+            return Err(InterpreterError::TooManyVariables);
         }
+        self.variables.extend_from_slice(var_types);
+        Ok(())
+    }
+    fn resolve_default_values(&mut self, structs: &[StructDefinition], enums: &[EnumDefinition]) {
+        self.default_values = self
+            .variables
+            .iter()
+            .map(|var_type| var_type.default_value(structs, enums))
+            .collect();
+        self.default_values_size = self.default_values.iter().map(value_size).sum();
     }
 }
 
+/// Approximate memory attributed to a value held by a frame, in bytes.
+fn value_size(value: &Value) -> usize {
+    size_of::<Value>() + value.heap_size()
+}
+
 #[derive(Debug, Clone)]
 struct ExecutionContext {
     variables: Vec<Value>,
@@ -49,33 +108,44 @@ struct ExecutionContext {
     function_id: FunctionIdType,
     expecting_return_value: Option<VariableIdType>,
     instruction_counter: usize,
+    // Approximate bytes held by `variables` and `function_parameter_stack`.
+    allocated_bytes: usize,
 }
 
 impl ExecutionContext {
     fn new(func: &Function, function_id: FunctionIdType) -> Self {
-        let mut variables: Vec<Value> = Vec::with_capacity(8);
-        for b in func.variables.iter() {
-            let default_value = match b {
-                VariableType::U8 => Value::U8(0),
-                VariableType::U16 => Value::U16(0),
-                VariableType::U32 => Value::U32(0),
-                VariableType::U64 => Value::U64(0),
-                VariableType::Bool => Value::Bool(false),
-                VariableType::String => Value::String(String::new()),
-                VariableType::Array(arr_type) => Value::Array(ArrayValue::new(*arr_type.clone())),
-            };
-
-            variables.push(default_value);
-        }
-
         ExecutionContext {
-            variables,
+            variables: func.default_values.clone(),
             function_parameter_stack: Vec::new(),
             instruction_counter: 0,
             function_id,
             expecting_return_value: None,
+            allocated_bytes: func.default_values_size,
         }
     }
+    /// Reinitialises a recycled context for a new call, keeping its allocations.
+    fn reset(&mut self, func: &Function, function_id: FunctionIdType) {
+        self.variables.clone_from(&func.default_values);
+        self.function_parameter_stack.clear();
+        self.instruction_counter = 0;
+        self.function_id = function_id;
+        self.expecting_return_value = None;
+        self.allocated_bytes = func.default_values_size;
+    }
+    fn push_parameter(&mut self, value: Value) {
+        self.allocated_bytes += value_size(&value);
+        self.function_parameter_stack.push(value);
+    }
+    fn pop_parameter(&mut self) -> Option<Value> {
+        let value = self.function_parameter_stack.pop()?;
+        self.allocated_bytes -= value_size(&value);
+        Some(value)
+    }
+    /// Moves a parameter or closure capture into its variable slot. The caller is responsible for checking its type.
+    fn store_parameter(&mut self, param_id: usize, value: Value) {
+        self.allocated_bytes = self.allocated_bytes - self.variables[param_id].heap_size() + value.heap_size();
+        self.variables[param_id] = value;
+    }
     fn get_variable(&self, var_id: VariableIdType) -> Result<&Value, InterpreterError> {
         if let Some(v) = self.variables.get(var_id as usize) {
             Ok(v)
@@ -100,13 +170,14 @@ impl ExecutionContext {
             return Err(InterpreterError::VariableDoesNotExist(var_id));
         };
 
-        if current_value.get_type() != value.get_type() {
+        if !current_value.is_same_type(&value) {
             return Err(InterpreterError::AttemptAssignedDifferentTypes(
                 current_value.get_type(),
                 value.get_type(),
             ));
         }
         if let Some(v) = self.variables.get_mut(var_id as usize) {
+            self.allocated_bytes = self.allocated_bytes - v.heap_size() + value.heap_size();
             *v = value;
         } else {
             return Err(InterpreterError::VariableDoesNotExist(var_id));
@@ -114,6 +185,14 @@ impl ExecutionContext {
         Ok(())
     }
 
+    fn view(&self) -> FrameView<'_> {
+        FrameView {
+            function_id: self.function_id,
+            instruction_counter: self.instruction_counter,
+            variables: &self.variables,
+            parameter_stack: &self.function_parameter_stack,
+        }
+    }
     #[allow(dead_code)]
     fn print_state(&self, program: &Program) {
         println!("---");
@@ -126,7 +205,10 @@ impl ExecutionContext {
         }
         println!("---");
 
-        let function = program.get_function(self.function_id).unwrap();
+        let Ok(function) = program.get_function(self.function_id) else {
+            println!("Function {} does not exist", self.function_id);
+            return;
+        };
         println!("Instruction counter: {}", self.instruction_counter);
         for (no, instruction) in function.instructions.iter().enumerate() {
             if no == self.instruction_counter {
@@ -141,306 +223,1790 @@ impl ExecutionContext {
 #[derive(Debug, Clone)]
 pub struct Program {
     functions: Vec<Function>,
+    structs: Vec<StructDefinition>,
+    enums: Vec<EnumDefinition>,
+    // Computed once on load, as snapshots are checked against it on every save and restore.
+    fingerprint: u64,
 }
 
 impl Program {
     pub fn new(functions: &[Function]) -> Self {
-        Self {
-            functions: functions.to_vec(),
+        Self::resolve(functions, Vec::new(), Vec::new())
+    }
+    /// A program whose types may refer to `structs` by index, see `with_types`.
+    pub fn with_structs(functions: &[Function], structs: &[StructDefinition]) -> Result<Self, InterpreterError> {
+        Self::with_types(functions, structs, &[])
+    }
+    /// A program whose types may refer to `structs` and `enums` by index. Fails if a type refers to a definition that
+    /// does not exist, an enum has no variants, a struct or enum holds itself in its default value, or an instruction
+    /// embeds a value that does not match its definition.
+    pub fn with_types(functions: &[Function], structs: &[StructDefinition], enums: &[EnumDefinition]) -> Result<Self, InterpreterError> {
+        for definition in structs.iter() {
+            definition.fields().iter().try_for_each(|field| check_type(field, structs, enums))?;
+        }
+        for (enum_id, definition) in enums.iter().enumerate() {
+            if definition.variants().is_empty() {
+                return Err(InterpreterError::EnumHasNoVariants(enum_id as EnumIdType));
+            }
+            definition
+                .variants()
+                .iter()
+                .flatten()
+                .try_for_each(|payload_type| check_type(payload_type, structs, enums))?;
+        }
+        let structs_and_enums = (0..structs.len() as StructIdType)
+            .map(VariableType::Struct)
+            .chain((0..enums.len() as EnumIdType).map(VariableType::Enum));
+        for root in structs_and_enums {
+            // what a default value holds directly must not lead back here, arrays, maps and options start empty
+            let mut pending = vec![root.clone()];
+            let mut seen = Vec::new();
+            while let Some(current) = pending.pop() {
+                for held in held_by_default(&current, structs, enums) {
+                    if *held == root {
+                        return Err(match root {
+                            VariableType::Struct(struct_id) => InterpreterError::StructContainsItself(struct_id),
+                            VariableType::Enum(enum_id) => InterpreterError::EnumContainsItself(enum_id),
+                            _ => unreachable!("only structs and enums are checked"),
+                        });
+                    }
+                    if matches!(held, VariableType::Struct(_) | VariableType::Enum(_)) && !seen.contains(held) {
+                        seen.push(held.clone());
+                        pending.push(held.clone());
+                    }
+                }
+            }
+        }
+        let program = Self::resolve(functions, structs.to_vec(), enums.to_vec());
+        program.check_types()?;
+        Ok(program)
+    }
+    /// Checks that the functions only use structs and enums the program defines and valid map types.
+    fn check_types(&self) -> Result<(), InterpreterError> {
+        for function in self.functions.iter() {
+            for var_type in function.variables.iter().chain(function.return_type.iter()) {
+                check_type(var_type, &self.structs, &self.enums)?;
+            }
+            for value in function.instructions.iter().filter_map(Instruction::immediate) {
+                if !value.conforms_to(&self.structs, &self.enums) {
+                    return Err(malformed_value(&value.get_type()));
+                }
+                // heap objects only exist at runtime, and the collector does not see handles held by instructions
+                let mut on_heap = false;
+                value.visit_targets(&mut |target| on_heap |= matches!(target, RefTarget::Heap(_)));
+                if on_heap {
+                    return Err(InterpreterError::DanglingReference);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn resolve(functions: &[Function], structs: Vec<StructDefinition>, enums: Vec<EnumDefinition>) -> Self {
+        let mut functions = functions.to_vec();
+        for function in functions.iter_mut() {
+            function.resolve_default_values(&structs, &enums);
         }
+        let mut program = Self {
+            functions,
+            structs,
+            enums,
+            fingerprint: 0,
+        };
+        program.fingerprint = program_fingerprint(&program);
+        program
+    }
+    pub(crate) fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+    pub(crate) fn fingerprint(&self) -> u64 {
+        self.fingerprint
     }
-    fn get_function(&self, function_id: FunctionIdType) -> Result<&Function, InterpreterError> {
+    pub fn get_function(&self, function_id: FunctionIdType) -> Result<&Function, InterpreterError> {
         if let Some(v) = self.functions.get(function_id as usize) {
             return Ok(v);
         }
         Err(InterpreterError::FunctionDoesNotExist(function_id))
     }
+    pub fn get_struct(&self, struct_id: StructIdType) -> Result<&StructDefinition, InterpreterError> {
+        self.structs
+            .get(struct_id as usize)
+            .ok_or(InterpreterError::StructDoesNotExist(struct_id))
+    }
+    pub fn structs(&self) -> &[StructDefinition] {
+        &self.structs
+    }
+    pub fn get_enum(&self, enum_id: EnumIdType) -> Result<&EnumDefinition, InterpreterError> {
+        self.enums.get(enum_id as usize).ok_or(InterpreterError::EnumDoesNotExist(enum_id))
+    }
+    pub fn enums(&self) -> &[EnumDefinition] {
+        &self.enums
+    }
 }
 
-#[derive(Debug)]
+/// Checks that a type only refers to structs and enums that exist and keys maps by hashable types.
+fn check_type(var_type: &VariableType, structs: &[StructDefinition], enums: &[EnumDefinition]) -> Result<(), InterpreterError> {
+    match var_type {
+        VariableType::Struct(struct_id) if *struct_id as usize >= structs.len() => Err(InterpreterError::StructDoesNotExist(*struct_id)),
+        VariableType::Enum(enum_id) if *enum_id as usize >= enums.len() => Err(InterpreterError::EnumDoesNotExist(*enum_id)),
+        VariableType::Array(inner_type) => check_type(inner_type, structs, enums),
+        VariableType::Map(key_type, _) if !key_type.is_hashable() => Err(InterpreterError::MapKeyNotHashable(*key_type.clone())),
+        VariableType::Map(_, value_type)
+        | VariableType::Option(value_type)
+        | VariableType::Ref(value_type)
+        | VariableType::Object(value_type) => check_type(value_type, structs, enums),
+        VariableType::Function(signature) => signature
+            .parameters()
+            .iter()
+            .chain(signature.return_type())
+            .try_for_each(|var_type| check_type(var_type, structs, enums)),
+        _ => Ok(()),
+    }
+}
+
+/// The types a default value of `var_type` holds directly: a struct's fields and the payload of an enum's first
+/// variant. The definitions must exist.
+fn held_by_default<'a>(var_type: &VariableType, structs: &'a [StructDefinition], enums: &'a [EnumDefinition]) -> &'a [VariableType] {
+    match var_type {
+        VariableType::Struct(struct_id) => structs[*struct_id as usize].fields(),
+        VariableType::Enum(enum_id) => enums[*enum_id as usize].variants()[0].as_slice(),
+        _ => &[],
+    }
+}
+
+/// The error for a value of `var_type` that does not match its definition, naming the first struct or enum its type is
+/// made of.
+fn malformed_value(var_type: &VariableType) -> InterpreterError {
+    defined_in(var_type).unwrap_or_else(|| InterpreterError::ValueMalformed(var_type.clone()))
+}
+
+fn defined_in(var_type: &VariableType) -> Option<InterpreterError> {
+    match var_type {
+        VariableType::Struct(struct_id) => Some(InterpreterError::StructValueMalformed(*struct_id)),
+        VariableType::Enum(enum_id) => Some(InterpreterError::EnumValueMalformed(*enum_id)),
+        VariableType::Array(inner_type)
+        | VariableType::Map(_, inner_type)
+        | VariableType::Option(inner_type)
+        | VariableType::Ref(inner_type)
+        | VariableType::Object(inner_type) => defined_in(inner_type),
+        VariableType::Function(signature) => signature.parameters().iter().chain(signature.return_type()).find_map(defined_in),
+        _ => None,
+    }
+}
+
+/// Fails if `value` holds a reference to a frame deeper than `frame`, which the reference would outlive. Without a
+/// frame, as for values moved to the heap, the value may not refer to any frame.
+fn check_ref_frames(value: &Value, frame: Option<usize>) -> Result<(), InterpreterError> {
+    let mut result = Ok(());
+    value.visit_targets(&mut |target| {
+        if let RefTarget::Variable { frame: target_frame, .. } = target {
+            if result.is_ok() && frame.is_none_or(|frame| target_frame > frame) {
+                result = Err(InterpreterError::ReferenceOutlivesFrame(target_frame));
+            }
+        }
+    });
+    result
+}
+
+/// Fails unless each capture of a closure over `function` has the type of the variable it is stored in, the
+/// variables following the parameters.
+fn check_captures(function: &Function, function_id: FunctionIdType, captures: &[Value]) -> Result<(), InterpreterError> {
+    let slots = &function.variables[function.parameters.len()..];
+    match captures
+        .iter()
+        .enumerate()
+        .find(|(index, capture)| !slots.get(*index).is_some_and(|slot| capture.is_type(slot)))
+    {
+        Some((index, _)) => Err(InterpreterError::CaptureTypeMismatch(function_id, index)),
+        None => Ok(()),
+    }
+}
+
+/// The frame a value written to `target` must not refer beyond, `None` for the heap.
+fn target_frame(target: RefTarget) -> Option<usize> {
+    match target {
+        RefTarget::Variable { frame, .. } => Some(frame),
+        RefTarget::Heap(_) => None,
+    }
+}
+
+/// Suspended execution contexts, plus a pool of finished contexts kept around so calls can reuse their allocations.
+#[derive(Debug, Default)]
+struct CallStack {
+    frames: Vec<ExecutionContext>,
+    pool: Vec<ExecutionContext>,
+    // Sum of `allocated_bytes` over `frames`.
+    allocated_bytes: usize,
+}
+
+impl CallStack {
+    fn with_capacity(capacity: usize) -> Self {
+        CallStack {
+            frames: Vec::with_capacity(capacity),
+            pool: Vec::with_capacity(capacity),
+            allocated_bytes: 0,
+        }
+    }
+    fn push(&mut self, context: ExecutionContext) {
+        self.allocated_bytes += context.allocated_bytes;
+        self.frames.push(context);
+    }
+    fn pop(&mut self) -> Option<ExecutionContext> {
+        let context = self.frames.pop()?;
+        self.allocated_bytes -= context.allocated_bytes;
+        Some(context)
+    }
+    fn len(&self) -> usize {
+        self.frames.len()
+    }
+    /// Takes a context for `function` from the pool, allocating only when the pool is empty.
+    fn acquire(&mut self, function: &Function, function_id: FunctionIdType) -> ExecutionContext {
+        match self.pool.pop() {
+            Some(mut context) => {
+                context.reset(function, function_id);
+                context
+            }
+            None => ExecutionContext::new(function, function_id),
+        }
+    }
+    /// Returns a finished context to the pool, dropping its values but keeping its allocations.
+    fn release(&mut self, mut context: ExecutionContext) {
+        context.variables.clear();
+        context.function_parameter_stack.clear();
+        self.pool.push(context);
+    }
+}
+
+/// Approximate memory held by values across all frames, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub current: usize,
+    pub peak: usize,
+}
+
+/// Counters accumulated over every run of an interpreter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutionStats {
+    /// Instructions executed.
+    pub instructions: u64,
+    /// Calls to functions of the program.
+    pub calls: u64,
+    pub native_calls: u64,
+    /// The most frames that were on the callstack at once.
+    pub max_call_depth: usize,
+    /// Values copied by instructions, such as `Set`, operator operands, pushed parameters and returned values.
+    pub values_cloned: u64,
+}
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+pub const DEFAULT_MAX_PARAMETER_STACK_SIZE: usize = 256;
+
 pub struct Interpreter {
-    program: Program,
-    callstack: Vec<ExecutionContext>,
+    program: Arc<Program>,
+    callstack: CallStack,
     return_value_storage: Option<Value>,
+    // Instructions left before execution stops with `OutOfFuel`, `None` when unlimited.
+    fuel: Option<u64>,
+    // Instructions executed since the fuel was last set.
+    fuel_consumed: u64,
+    stats: ExecutionStats,
+    max_call_depth: usize,
+    max_parameter_stack_size: usize,
+    memory_limit: Option<usize>,
+    peak_memory: usize,
+    debugger: Debugger,
+    tracer: Option<Box<dyn Tracer>>,
+    profiler: Option<Profiler>,
+    heap: Heap,
+}
+
+impl fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("program", &self.program)
+            .field("callstack", &self.callstack)
+            .field("return_value_storage", &self.return_value_storage)
+            .field("fuel", &self.fuel)
+            .field("fuel_consumed", &self.fuel_consumed)
+            .field("stats", &self.stats)
+            .field("max_call_depth", &self.max_call_depth)
+            .field("max_parameter_stack_size", &self.max_parameter_stack_size)
+            .field("memory_limit", &self.memory_limit)
+            .field("peak_memory", &self.peak_memory)
+            .field("debugger", &self.debugger)
+            .field("tracer", &self.tracer.is_some())
+            .field("profiler", &self.profiler)
+            .field("heap", &self.heap)
+            .finish()
+    }
 }
 
 impl Interpreter {
-    pub fn new(program: Program) -> Self {
-        let ctx = ExecutionContext::new(program.get_function(0).unwrap(), 0);
-        let mut callstack = Vec::with_capacity(32);
+    /// Prepares to run `program`, starting at function 0.
+    pub fn new(program: Program) -> Result<Self, InterpreterError> {
+        let Ok(entry_function) = program.get_function(0) else {
+            return Err(InterpreterError::NoEntryFunction);
+        };
+        program.check_types()?;
+        let ctx = ExecutionContext::new(entry_function, 0);
+        let peak_memory = ctx.allocated_bytes;
+        let mut callstack = CallStack::with_capacity(32);
         callstack.push(ctx);
-        Interpreter {
-            program,
+        Ok(Interpreter {
+            program: Arc::new(program),
             callstack,
             return_value_storage: None,
+            fuel: None,
+            fuel_consumed: 0,
+            stats: ExecutionStats {
+                max_call_depth: 1,
+                ..Default::default()
+            },
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_parameter_stack_size: DEFAULT_MAX_PARAMETER_STACK_SIZE,
+            memory_limit: None,
+            peak_memory,
+            debugger: Debugger::default(),
+            tracer: None,
+            profiler: None,
+            heap: Heap::default(),
+        })
+    }
+    /// Limits how many more instructions may run. Each executed instruction consumes one unit of fuel;
+    /// once it runs out `execute` returns `InterpreterError::OutOfFuel` and can be called again after refuelling
+    /// to resume where it stopped. `None` removes the limit. Starts counting `fuel_consumed` from zero again.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+        self.fuel_consumed = 0;
+    }
+    /// Adds fuel to a limited budget. Has no effect when fuel is unlimited.
+    pub fn add_fuel(&mut self, amount: u64) {
+        if let Some(fuel) = self.fuel.as_mut() {
+            *fuel = fuel.saturating_add(amount);
+        }
+    }
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+    /// Instructions executed since the fuel was last set with `set_fuel`, or since the interpreter was created.
+    /// Fuel added with `add_fuel` keeps counting. `stats` counts over the interpreter's whole life.
+    pub fn fuel_consumed(&self) -> u64 {
+        self.fuel_consumed
+    }
+    pub fn stats(&self) -> ExecutionStats {
+        self.stats
+    }
+    /// Maximum number of frames on the callstack, including the entry function. Calls beyond it fail with
+    /// `InterpreterError::StackOverflow`.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+    /// Maximum number of values a single frame may push with `PushFunctionParameter` before calling.
+    pub fn set_max_parameter_stack_size(&mut self, max_parameter_stack_size: usize) {
+        self.max_parameter_stack_size = max_parameter_stack_size;
+    }
+    /// Caps the approximate number of bytes held by values across all frames. Exceeding it fails with
+    /// `InterpreterError::OutOfMemory`. `None` removes the limit.
+    pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+        self.memory_limit = memory_limit;
+    }
+    /// Heap bytes allocated before the garbage collector runs on its own. After each collection the next one waits
+    /// until the heap has doubled, or grown to this threshold if that is larger.
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        self.heap.set_threshold(threshold);
+    }
+    /// Frees every heap object no frame can reach, returning how many were freed.
+    pub fn collect_garbage(&mut self) -> u64 {
+        let freed = self.heap.stats().objects_freed;
+        self.collect(None, None);
+        self.heap.stats().objects_freed - freed
+    }
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            current: self.callstack.allocated_bytes + self.heap.allocated_bytes(),
+            peak: self.peak_memory,
+        }
+    }
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+    /// Breakpoints and watchpoints, checked while the interpreter runs.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+    /// Installs a tracer that is told about every instruction, call and return from now on. `None` removes it.
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn Tracer>>) {
+        self.tracer = tracer;
+    }
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+    /// Starts timing every instruction and call from now on, discarding any profile collected so far.
+    pub fn enable_profiling(&mut self) {
+        let frames = self.callstack.frames.iter().map(|context| context.function_id);
+        self.profiler = Some(Profiler::new(frames));
+    }
+    /// Stops profiling and returns what was collected, or `None` if profiling was not enabled.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let profiler = self.profiler.take()?;
+        Some(profiler.finish(&self.program))
+    }
+    /// Encodes the execution state, so it can be resumed with `restore` by an interpreter of the same program,
+    /// possibly in another process. Breakpoints, watchpoints, the tracer and the profiler are not included.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new(&self.program);
+        writer.option(self.fuel, SnapshotWriter::u64);
+        writer.u64(self.fuel_consumed);
+        writer.u64(self.stats.instructions);
+        writer.u64(self.stats.calls);
+        writer.u64(self.stats.native_calls);
+        writer.usize(self.stats.max_call_depth);
+        writer.u64(self.stats.values_cloned);
+        writer.usize(self.max_call_depth);
+        writer.usize(self.max_parameter_stack_size);
+        writer.option(self.memory_limit, SnapshotWriter::usize);
+        writer.usize(self.peak_memory);
+        writer.option(self.return_value_storage.as_ref(), SnapshotWriter::value);
+        writer.len(self.callstack.len());
+        for context in self.callstack.frames.iter() {
+            writer.u16(context.function_id);
+            writer.usize(context.instruction_counter);
+            writer.option(context.expecting_return_value, SnapshotWriter::u16);
+            writer.values(&context.variables);
+            writer.values(&context.function_parameter_stack);
+        }
+        writer.len(self.heap.objects().len());
+        for object in self.heap.objects() {
+            writer.option(object.as_ref(), SnapshotWriter::value);
+        }
+        let gc_stats = self.heap.stats();
+        writer.u64(gc_stats.collections);
+        writer.u64(gc_stats.objects_freed);
+        writer.u64(gc_stats.bytes_freed);
+        writer.usize(self.heap.threshold());
+        writer.finish()
+    }
+    /// Replaces the execution state with one taken by `snapshot`. The snapshot must come from the same program.
+    /// On error the interpreter is left unchanged.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(snapshot, &self.program)?;
+        let fuel = reader.option(SnapshotReader::u64)?;
+        let fuel_consumed = reader.u64()?;
+        let stats = ExecutionStats {
+            instructions: reader.u64()?,
+            calls: reader.u64()?,
+            native_calls: reader.u64()?,
+            max_call_depth: reader.usize()?,
+            values_cloned: reader.u64()?,
+        };
+        let max_call_depth = reader.usize()?;
+        let max_parameter_stack_size = reader.usize()?;
+        let memory_limit = reader.option(SnapshotReader::usize)?;
+        let peak_memory = reader.usize()?;
+        let return_value_storage = reader.option(SnapshotReader::value)?;
+
+        let frame_count = reader.len(16)?;
+        let mut callstack = CallStack::with_capacity(frame_count.max(32));
+        for _ in 0..frame_count {
+            let function_id = reader.u16()?;
+            let instruction_counter = reader.usize()?;
+            let expecting_return_value = reader.option(SnapshotReader::u16)?;
+            let variables = reader.values()?;
+            let function_parameter_stack = reader.values()?;
+
+            // counters and return destinations are checked when execution reaches them, like in any running frame
+            let Ok(function) = self.program.get_function(function_id) else {
+                return Err(SnapshotError::Corrupt("frame of an unknown function"));
+            };
+            if variables.len() != function.variables.len() || !variables.iter().zip(function.variables.iter()).all(|(v, t)| v.is_type(t)) {
+                return Err(SnapshotError::Corrupt("frame variables do not match its function"));
+            }
+            // references may only point to the frame holding them or to its callers
+            let depth = callstack.len();
+            if variables
+                .iter()
+                .chain(function_parameter_stack.iter())
+                .any(|value| check_ref_frames(value, Some(depth)).is_err())
+            {
+                return Err(SnapshotError::Corrupt("reference outlives its frame"));
+            }
+            let allocated_bytes = variables.iter().chain(function_parameter_stack.iter()).map(value_size).sum();
+            callstack.push(ExecutionContext {
+                variables,
+                function_parameter_stack,
+                function_id,
+                expecting_return_value,
+                instruction_counter,
+                allocated_bytes,
+            });
+        }
+        let object_count = reader.len(1)?;
+        let objects = (0..object_count)
+            .map(|_| reader.option(SnapshotReader::value))
+            .collect::<Result<Vec<_>, _>>()?;
+        if objects.iter().flatten().any(|object| check_ref_frames(object, None).is_err()) {
+            return Err(SnapshotError::Corrupt("heap object refers to a frame"));
         }
+        let gc_stats = GcStats {
+            collections: reader.u64()?,
+            objects_freed: reader.u64()?,
+            bytes_freed: reader.u64()?,
+            ..Default::default()
+        };
+        let gc_threshold = reader.usize()?;
+        if return_value_storage
+            .as_ref()
+            .is_some_and(|value| check_ref_frames(value, frame_count.checked_sub(1)).is_err())
+        {
+            return Err(SnapshotError::Corrupt("reference outlives its frame"));
+        }
+        reader.finish()?;
+
+        self.callstack = callstack;
+        self.heap = Heap::with_objects(objects);
+        self.heap.set_stats(gc_stats);
+        self.heap.set_threshold(gc_threshold);
+        self.return_value_storage = return_value_storage;
+        self.fuel = fuel;
+        self.fuel_consumed = fuel_consumed;
+        self.stats = stats;
+        self.max_call_depth = max_call_depth;
+        self.max_parameter_stack_size = max_parameter_stack_size;
+        self.memory_limit = memory_limit;
+        self.peak_memory = peak_memory;
+        if let Some(profiler) = self.profiler.as_mut() {
+            *profiler = Profiler::new(self.callstack.frames.iter().map(|context| context.function_id));
+        }
+        Ok(())
+    }
+    /// Number of frames on the callstack while execution is paused.
+    pub fn call_depth(&self) -> usize {
+        self.callstack.len()
     }
+    /// Every frame on the callstack, from the entry function to the one that runs next.
+    pub fn frames(&self) -> Vec<FrameView<'_>> {
+        self.callstack.frames.iter().map(ExecutionContext::view).collect()
+    }
+}
+
+/// Where execution stopped after `Interpreter::step`, `Interpreter::run_for` or `Interpreter::execute` returns.
+#[derive(Debug, Clone)]
+pub enum ExecutionStatus {
+    /// The instruction budget ran out with instructions left to run.
+    Running,
+    /// The program executed `Instruction::Yield`; running again continues after it.
+    Yielded,
+    /// The entry function completed, with the value it returned if it executed `Return`.
+    Finished(Option<Value>),
+    /// The instruction that failed is left as the next one to run.
+    Error(RuntimeError),
+    /// Stopped before running the instruction at a breakpoint.
+    Breakpoint(Breakpoint),
+    /// Stopped after an instruction changed a watched variable.
+    Watchpoint(WatchpointHit),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Debug)]
 enum AfterCycleAction {
     None,
     Goto(usize),
+    Call(ExecutionContext),
+    Return,
+    Yield,
 }
 
 impl Interpreter {
-    pub fn execute(&mut self) -> Result<(), InterpreterError> {
+    /// Fails if calling `function_id` from the running context would exceed the maximum call depth.
+    fn check_call_depth(&self, function_id: FunctionIdType) -> Result<(), InterpreterError> {
+        // suspended frames, the running context, and the new callee
+        let depth = self.callstack.len() + 2;
+        if depth > self.max_call_depth {
+            return Err(InterpreterError::StackOverflow { depth, function_id });
+        }
+        Ok(())
+    }
+    /// Prepares the frame for a call from `caller` to `function_id`, which returns into `return_value_destination`
+    /// or, without one, must be void.
+    fn enter_function(
+        &mut self,
+        caller: &mut ExecutionContext,
+        function_id: FunctionIdType,
+        return_value_destination: Option<VariableIdType>,
+    ) -> Result<ExecutionContext, InterpreterError> {
+        self.check_call_depth(function_id)?;
+        let function = self.program.get_function(function_id)?;
+        match (return_value_destination, &function.return_type) {
+            (None, Some(_)) => return Err(InterpreterError::VoidCallToNonVoidFunction(function_id)),
+            (Some(_), None) => return Err(InterpreterError::ExpectingReturnCallToVoidFunction(function_id)),
+            _ => (),
+        }
+        let mut new_context = self.callstack.acquire(function, function_id);
+        Self::pass_parameters(caller, &mut new_context, function, function_id)?;
+        if return_value_destination.is_some() {
+            caller.expecting_return_value = return_value_destination;
+        }
+        Ok(new_context)
+    }
+    /// The function the function value in `var_id` names, checked to have the value's signature and to take its
+    /// captures.
+    fn function_value_target(&self, context: &ExecutionContext, var_id: VariableIdType) -> Result<FunctionIdType, InterpreterError> {
+        let function_value = match context.get_variable(var_id)? {
+            Value::Function(function_value) => function_value,
+            other => return Err(InterpreterError::CallOnNonFunctionValue(other.get_type())),
+        };
+        let function_id = function_value.function_id().ok_or(InterpreterError::NullFunctionCall)?;
+        let function = self.program.get_function(function_id)?;
+        if !function.has_signature(function_value.signature()) {
+            return Err(InterpreterError::FunctionSignatureMismatch(function_id));
+        }
+        check_captures(function, function_id, function_value.captures())?;
+        Ok(function_id)
+    }
+    /// Copies the captures of the closure in the caller's `var_id` into the callee's variables after its parameters.
+    fn store_captures(
+        &mut self,
+        caller: &ExecutionContext,
+        callee: &mut ExecutionContext,
+        var_id: VariableIdType,
+    ) -> Result<(), InterpreterError> {
+        let Value::Function(function_value) = caller.get_variable(var_id)? else {
+            return Ok(());
+        };
+        let first_slot = self.program.get_function(callee.function_id)?.parameters.len();
+        for (index, capture) in function_value.captures().iter().enumerate() {
+            // checked against the callee's variables by `function_value_target`
+            callee.store_parameter(first_slot + index, capture.clone());
+        }
+        self.stats.values_cloned += function_value.captures().len() as u64;
+        Ok(())
+    }
+    /// Suspends the failing context, so it stays inspectable and resumable, and records the callstack that led to
+    /// the error.
+    fn fail(&mut self, context: ExecutionContext, kind: InterpreterError) -> ExecutionStatus {
+        self.callstack.push(context);
+        let innermost = self.callstack.len() - 1;
+        let trace = self
+            .callstack
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, context)| Frame {
+                function_id: context.function_id,
+                name: self
+                    .program
+                    .get_function(context.function_id)
+                    .ok()
+                    .and_then(|function| function.name.clone()),
+                // callers have already moved past the call they are waiting on
+                instruction_counter: match depth == innermost {
+                    true => context.instruction_counter,
+                    false => context.instruction_counter.saturating_sub(1),
+                },
+            })
+            .collect();
+        ExecutionStatus::Error(RuntimeError { kind, trace })
+    }
+    fn check_memory(&mut self, context: &ExecutionContext) -> Result<(), InterpreterError> {
+        let mut memory_used = self.callstack.allocated_bytes + context.allocated_bytes + self.heap.allocated_bytes();
+        self.peak_memory = self.peak_memory.max(memory_used);
+        if let Some(limit) = self.memory_limit {
+            if memory_used > limit && self.heap.allocated_bytes() > 0 {
+                // unreachable objects may be all that is over the limit
+                self.collect(Some(context), None);
+                memory_used = self.callstack.allocated_bytes + context.allocated_bytes + self.heap.allocated_bytes();
+            }
+            if memory_used > limit {
+                return Err(InterpreterError::OutOfMemory { used: memory_used, limit });
+            }
+        }
+        Ok(())
+    }
+    /// Runs the garbage collector. Every frame's variables and parameter stack are roots, along with the running
+    /// context while it is popped off the callstack, the value waiting to be returned and `pending`, a value not
+    /// stored anywhere yet.
+    fn collect(&mut self, running: Option<&ExecutionContext>, pending: Option<&Value>) {
+        let roots = self
+            .callstack
+            .frames
+            .iter()
+            .chain(running)
+            .flat_map(|context| context.variables.iter().chain(context.function_parameter_stack.iter()))
+            .chain(self.return_value_storage.iter())
+            .chain(pending);
+        self.heap.collect(roots);
+    }
+    /// Moves the parameters `function` takes from the caller's parameter stack into the callee's variables.
+    fn pass_parameters(
+        caller: &mut ExecutionContext,
+        callee: &mut ExecutionContext,
+        function: &Function,
+        function_id: FunctionIdType,
+    ) -> Result<(), InterpreterError> {
+        for (param_id, param_type) in function.parameters.iter().enumerate().rev() {
+            let Some(param_value) = caller.pop_parameter() else {
+                return Err(InterpreterError::FunctionCallParameterStackEmptyPop(function_id));
+            };
+            if !param_value.is_type(param_type) {
+                return Err(InterpreterError::FunctionCallParametersInvalid(function_id, false));
+            }
+            // parameters occupy the first variable slots, and their type was just checked
+            callee.store_parameter(param_id, param_value);
+        }
+        Ok(())
+    }
+    fn pop_native_parameter(context: &mut ExecutionContext, method_id: FunctionIdType) -> Result<Value, InterpreterError> {
+        context
+            .pop_parameter()
+            .ok_or(InterpreterError::FunctionCallParameterStackEmptyPop(method_id))
+    }
+    fn map_variable(context: &ExecutionContext, var_id: VariableIdType) -> Result<&MapValue, InterpreterError> {
+        match context.get_variable(var_id)? {
+            Value::Map(map) => Ok(map),
+            other => Err(InterpreterError::MapOperationOnNonMapValue(other.get_type())),
+        }
+    }
+    fn map_variable_mut(context: &mut ExecutionContext, var_id: VariableIdType) -> Result<&mut MapValue, InterpreterError> {
+        match context.get_variable_mut(var_id)? {
+            Value::Map(map) => Ok(map),
+            other => Err(InterpreterError::MapOperationOnNonMapValue(other.get_type())),
+        }
+    }
+    fn option_variable(context: &ExecutionContext, var_id: VariableIdType) -> Result<&OptionValue, InterpreterError> {
+        match context.get_variable(var_id)? {
+            Value::Option(option) => Ok(option),
+            other => Err(InterpreterError::OptionOperationOnNonOptionValue(other.get_type())),
+        }
+    }
+    fn option_variable_mut(context: &mut ExecutionContext, var_id: VariableIdType) -> Result<&mut OptionValue, InterpreterError> {
+        match context.get_variable_mut(var_id)? {
+            Value::Option(option) => Ok(option),
+            other => Err(InterpreterError::OptionOperationOnNonOptionValue(other.get_type())),
+        }
+    }
+    fn enum_variable(context: &ExecutionContext, var_id: VariableIdType) -> Result<&EnumValue, InterpreterError> {
+        match context.get_variable(var_id)? {
+            Value::Enum(value) => Ok(value),
+            other => Err(InterpreterError::EnumOperationOnNonEnumValue(other.get_type())),
+        }
+    }
+    fn object_variable(context: &ExecutionContext, var_id: VariableIdType) -> Result<&ObjectValue, InterpreterError> {
+        match context.get_variable(var_id)? {
+            Value::Object(object) => Ok(object),
+            other => Err(InterpreterError::ObjectOperationOnNonObjectValue(other.get_type())),
+        }
+    }
+    fn ref_variable(context: &ExecutionContext, var_id: VariableIdType) -> Result<&RefValue, InterpreterError> {
+        match context.get_variable(var_id)? {
+            Value::Ref(reference) => Ok(reference),
+            other => Err(InterpreterError::RefOperationOnNonRefValue(other.get_type())),
+        }
+    }
+    /// The value `reference` points to, found in `context` when it points into the running frame.
+    fn deref<'a>(
+        callstack: &'a CallStack,
+        heap: &'a Heap,
+        context: &'a ExecutionContext,
+        reference: &RefValue,
+    ) -> Result<&'a Value, InterpreterError> {
+        let value = match reference.target().ok_or(InterpreterError::NullReference)? {
+            // the running context has been popped, so its depth is the number of suspended frames
+            RefTarget::Variable { frame, var_id } if frame == callstack.len() => context.get_variable(var_id).ok(),
+            RefTarget::Variable { frame, var_id } => callstack.frames.get(frame).and_then(|owner| owner.get_variable(var_id).ok()),
+            RefTarget::Heap(object_id) => heap.get(object_id).ok(),
+        };
+        match value {
+            Some(value) if value.is_type(reference.inner_type()) => Ok(value),
+            _ => Err(InterpreterError::DanglingReference),
+        }
+    }
+    /// Changes the value at `target` in place, keeping the memory use of the frame or heap holding it up to date.
+    /// `change` returns the heap size of what it removed and what it added.
+    fn change_referenced(
+        callstack: &mut CallStack,
+        heap: &mut Heap,
+        context: &mut ExecutionContext,
+        target: RefTarget,
+        change: impl FnOnce(&mut Value) -> Result<(usize, usize), InterpreterError>,
+    ) -> Result<(), InterpreterError> {
+        match target {
+            RefTarget::Variable { frame, var_id } if frame == callstack.len() => {
+                let (removed, added) = change(context.get_variable_mut(var_id).map_err(|_| InterpreterError::DanglingReference)?)?;
+                context.allocated_bytes = context.allocated_bytes - removed + added;
+            }
+            RefTarget::Variable { frame, var_id } => {
+                let owner = callstack.frames.get_mut(frame).ok_or(InterpreterError::DanglingReference)?;
+                let (removed, added) = change(owner.get_variable_mut(var_id).map_err(|_| InterpreterError::DanglingReference)?)?;
+                owner.allocated_bytes = owner.allocated_bytes - removed + added;
+                callstack.allocated_bytes = callstack.allocated_bytes - removed + added;
+            }
+            RefTarget::Heap(object_id) => heap.change(object_id, change)?,
+        }
+        Ok(())
+    }
+    /// Removes the entry for `key` if there is one, returning its value.
+    fn remove_map_entry(context: &mut ExecutionContext, var_id: VariableIdType, key: Value) -> Result<Option<Value>, InterpreterError> {
+        let Some((key, value)) = Self::map_variable_mut(context, var_id)?.remove(key)? else {
+            return Ok(None);
+        };
+        context.allocated_bytes -= MapValue::entry_size() + key.heap_size() + value.heap_size();
+        Ok(Some(value))
+    }
+    fn traced_values<'a>(context: &'a ExecutionContext, var_ids: &[VariableIdType]) -> Vec<(VariableIdType, &'a Value)> {
+        var_ids
+            .iter()
+            .filter_map(|var_id| Some((*var_id, context.get_variable(*var_id).ok()?)))
+            .collect()
+    }
+    /// The frame at `depth` on the callstack, where the running context, popped off the callstack, is the deepest.
+    fn frame_at<'a>(callstack: &'a CallStack, context: &'a ExecutionContext, depth: usize) -> Option<&'a ExecutionContext> {
+        match depth == callstack.len() {
+            true => Some(context),
+            false => callstack.frames.get(depth),
+        }
+    }
+    /// The frame variable `instr` writes through a reference to, as its frame's depth and its id.
+    fn ref_write_target(context: &ExecutionContext, instr: &Instruction) -> Option<(usize, VariableIdType)> {
+        let Ok(Value::Ref(reference)) = context.get_variable(instr.written_reference()?) else {
+            return None;
+        };
+        match reference.target()? {
+            RefTarget::Variable { frame, var_id } => Some((frame, var_id)),
+            RefTarget::Heap(_) => None,
+        }
+    }
+    /// Variables `instr` may write, as their frame's depth and their id: those of the running frame and one reached
+    /// through a reference.
+    fn written_locations(&self, context: &ExecutionContext, instr: &Instruction) -> Vec<(usize, VariableIdType)> {
+        let running = self.callstack.len();
+        let mut locations: Vec<(usize, VariableIdType)> = instr.written_variables().into_iter().map(|var_id| (running, var_id)).collect();
+        locations.extend(Self::ref_write_target(context, instr));
+        locations
+    }
+    /// Current values of the watched variables among `locations`, to compare against once they may have been written.
+    fn watched_values(&self, context: &ExecutionContext, locations: &[(usize, VariableIdType)]) -> Vec<(Watchpoint, usize, Value)> {
+        if !self.debugger.has_watchpoints() {
+            return Vec::new();
+        }
+        locations
+            .iter()
+            .filter_map(|&(depth, variable_id)| {
+                let frame = Self::frame_at(&self.callstack, context, depth)?;
+                let watchpoint = Watchpoint {
+                    function_id: frame.function_id,
+                    variable_id,
+                };
+                if !self.debugger.is_watched(&watchpoint) {
+                    return None;
+                }
+                Some((watchpoint, depth, frame.get_variable(variable_id).ok()?.clone()))
+            })
+            .collect()
+    }
+    fn changed_watchpoint(&self, context: &ExecutionContext, watched: Vec<(Watchpoint, usize, Value)>) -> Option<WatchpointHit> {
+        watched.into_iter().find_map(|(watchpoint, depth, old_value)| {
+            let new_value = Self::frame_at(&self.callstack, context, depth)?
+                .get_variable(watchpoint.variable_id)
+                .ok()?;
+            (*new_value != old_value).then(|| WatchpointHit {
+                watchpoint,
+                old_value,
+                new_value: new_value.clone(),
+            })
+        })
+    }
+    /// Runs until the program finishes or fails. Yields, breakpoints and watchpoints do not stop it, hosts acting on them
+    /// run the program with `run_for` instead.
+    pub fn execute(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            match self.run(None) {
+                ExecutionStatus::Finished(_) => return Ok(()),
+                ExecutionStatus::Error(error) => return Err(error),
+                ExecutionStatus::Running | ExecutionStatus::Yielded | ExecutionStatus::Breakpoint(_) | ExecutionStatus::Watchpoint(_) => {}
+            }
+        }
+    }
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> ExecutionStatus {
+        self.run(Some(1))
+    }
+    /// Executes at most `instructions` instructions, stopping early if the program finishes, yields or fails.
+    pub fn run_for(&mut self, instructions: u64) -> ExecutionStatus {
+        self.run(Some(instructions))
+    }
+
+    fn run(&mut self, mut budget: Option<u64>) -> ExecutionStatus {
+        let program = Arc::clone(&self.program);
         'execute_context: while let Some(mut context) = self.callstack.pop() {
-            let mut after_cycle: AfterCycleAction = AfterCycleAction::None;
-            let function_id = context.function_id;
-            let function = self.program.get_function(function_id)?;
+            let function = match program.get_function(context.function_id) {
+                Ok(function) => function,
+                Err(error) => {
+                    return self.fail(context, error);
+                }
+            };
 
-            if let Some(return_to_var_id) = context.expecting_return_value.take() {
-                let Some(return_value) = self.return_value_storage.take() else {
-                    return Err(InterpreterError::NoReturnValue);
+            if let Some(return_to_var_id) = context.expecting_return_value {
+                let watched = self.watched_values(&context, &[(self.callstack.len(), return_to_var_id)]);
+                let result = match self.return_value_storage.take() {
+                    Some(return_value) => context.set_variable(return_to_var_id, return_value),
+                    None => Err(InterpreterError::NoReturnValue),
                 };
-                context.set_variable(return_to_var_id, return_value)?;
+                if let Err(error) = result {
+                    return self.fail(context, error);
+                }
+                context.expecting_return_value = None;
+                if let Some(hit) = self.changed_watchpoint(&context, watched) {
+                    self.callstack.push(context);
+                    return ExecutionStatus::Watchpoint(hit);
+                }
+            }
+            // calls and returns move values between frames without passing the check after each instruction
+            if let Err(error) = self.check_memory(&context) {
+                return self.fail(context, error);
             }
             while let Some(instr) = function.instructions.get(context.instruction_counter) {
-                match instr {
-                    //
-                    // MEMORY AND ARRAYS
-                    //
-                    Instruction::Set(to_var_id, from_var_id) => {
-                        let value = context.get_variable(*from_var_id)?;
-                        context.set_variable(*to_var_id, value.clone())?
+                if self.debugger.has_breakpoints() {
+                    let location = Breakpoint {
+                        function_id: context.function_id,
+                        instruction: context.instruction_counter,
+                    };
+                    if self.debugger.should_break(location, self.callstack.len() + 1) {
+                        self.callstack.push(context);
+                        return ExecutionStatus::Breakpoint(location);
                     }
-                    Instruction::SetI(var_id, value) => context.set_variable(*var_id, value.clone())?,
-                    Instruction::SetArrayIndex(array_var_id, array_index, new_value_id) => {
-                        let array_index = context.get_variable(*array_index)?.to_usize()?;
-                        let new_value = context.get_variable(*new_value_id)?.clone();
-                        let array = context.get_variable_mut(*array_var_id)?;
-                        let Value::Array(values) = array else {
-                            return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
-                        };
-                        values.set_index(array_index, new_value)?;
+                }
+                if let Some(budget) = budget.as_mut() {
+                    if *budget == 0 {
+                        self.callstack.push(context);
+                        return ExecutionStatus::Running;
                     }
-                    Instruction::SetArrayIndexI(array_var_id, array_index, value) => {
-                        let array_index = context.get_variable(*array_index)?.to_usize()?;
-                        let array = context.get_variable_mut(*array_var_id)?;
-
-                        let Value::Array(values) = array else {
-                            return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
-                        };
-                        values.set_index(array_index, value.clone())?;
+                    *budget -= 1;
+                }
+                if let Some(fuel) = self.fuel.as_mut() {
+                    if *fuel == 0 {
+                        // the context is kept so execution can resume at this instruction
+                        return self.fail(context, InterpreterError::OutOfFuel);
                     }
-                    Instruction::GetArrayIndex(array_var_id, store_var_id, index_var_id) => {
-                        let array_index = context.get_variable(*index_var_id)?.to_usize()?;
-                        let array = context.get_variable(*array_var_id)?;
-
-                        let Value::Array(values) = array else {
-                            return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
-                        };
+                    *fuel -= 1;
+                }
+                self.fuel_consumed += 1;
+                self.stats.instructions += 1;
 
-                        let val = values.get_index(array_index)?;
-                        context.set_variable(*store_var_id, val)?;
-                    }
-                    //
-                    // ARITHMETIC
-                    //
-                    Instruction::Add(lvalue_id, rvalue_id) => {
-                        let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
-
-                        let new_value = op_add(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*lvalue_id, new_value)?;
-                    }
-                    Instruction::Sub(lvalue_id, rvalue_id) => {
-                        let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
-                        let new_value = op_sub(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*lvalue_id, new_value)?;
-                    }
-                    Instruction::Rem(lvalue_id, rvalue_id) => {
-                        let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
-                        let new_value = op_rem(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*lvalue_id, new_value)?;
-                    }
-                    Instruction::AddI(lvalue_id, rvalue) => {
-                        let lvalue = context.get_variable(*lvalue_id)?;
-                        let new_value = op_add(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*lvalue_id, new_value)?;
+                let watched = match self.debugger.has_watchpoints() {
+                    true => self.watched_values(&context, &self.written_locations(&context, instr)),
+                    false => Vec::new(),
+                };
+                if let Some(tracer) = self.tracer.as_mut() {
+                    let read = Self::traced_values(&context, &instr.read_variables());
+                    tracer.before_instruction(&context.view(), instr, &read);
+                }
+                let started = self.profiler.is_some().then(Instant::now);
+                let after_cycle = match self.execute_instruction(&mut context, instr) {
+                    Ok(after_cycle) => after_cycle,
+                    Err(error) => {
+                        return self.fail(context, error);
                     }
-                    Instruction::SubI(lvalue_id, rvalue) => {
-                        let lvalue = context.get_variable(*lvalue_id)?;
-                        let new_value = op_sub(lvalue.clone(), rvalue.clone())?;
-
-                        context.set_variable(*lvalue_id, new_value)?;
+                };
+                if let (Some(profiler), Some(started)) = (self.profiler.as_mut(), started) {
+                    profiler.record_instruction(context.function_id, context.instruction_counter, started.elapsed());
+                    match &after_cycle {
+                        AfterCycleAction::Call(new_context) => profiler.enter(new_context.function_id),
+                        AfterCycleAction::Return => profiler.exit(),
+                        _ => (),
                     }
-                    //
-                    // COMPARISON
-                    //
-                    Instruction::LessThan(bool_var_id, lvalue_id, rvalue_id) => {
-                        let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
-                        let result = op_less_than(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*bool_var_id, result)?;
+                }
+                if let Some(tracer) = self.tracer.as_mut() {
+                    // a call writes its destination when the callee returns, not now
+                    let mut written = match after_cycle {
+                        AfterCycleAction::Call(_) => Vec::new(),
+                        _ => Self::traced_values(&context, &instr.written_variables()),
+                    };
+                    // a write through a reference is listed with the frame owning the variable
+                    let running = self.callstack.len();
+                    let through_ref = Self::ref_write_target(&context, instr).and_then(|(depth, var_id)| {
+                        let frame = Self::frame_at(&self.callstack, &context, depth)?;
+                        Some((depth, frame, var_id, frame.get_variable(var_id).ok()?))
+                    });
+                    match through_ref {
+                        Some((depth, _, var_id, value)) if depth == running => {
+                            written.push((var_id, value));
+                            tracer.after_instruction(&context.view(), instr, &written);
+                        }
+                        Some((depth, frame, var_id, value)) => {
+                            tracer.after_instruction(&context.view(), instr, &written);
+                            tracer.on_ref_write(&frame.view(), running - depth, &[(var_id, value)]);
+                        }
+                        None => tracer.after_instruction(&context.view(), instr, &written),
                     }
-                    Instruction::LessThanI(bool_var_id, lvalue_id, rvalue) => {
-                        let lvalue = context.get_variable(*lvalue_id)?;
-                        let result = op_less_than(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*bool_var_id, result)?;
+                    match &after_cycle {
+                        AfterCycleAction::Call(new_context) => tracer.on_call(&new_context.view()),
+                        AfterCycleAction::Return => tracer.on_return(&context.view(), self.return_value_storage.as_ref()),
+                        _ => (),
                     }
-                    Instruction::Equals(bool_var_id, lvalue_id, rvalue_id) => {
-                        let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                }
 
-                        let result = op_equals(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*bool_var_id, result)?;
-                    }
-                    Instruction::EqualsI(bool_var_id, lvalue_id, rvalue) => {
-                        let lvalue = context.get_variable(*lvalue_id)?;
-                        let result = op_equals(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*bool_var_id, result)?;
-                    }
-                    Instruction::NotEquals(bool_var_id, lvalue_id, rvalue_id) => {
-                        let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                // context.print_state(&self.program);
 
-                        let result = op_not_equals(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*bool_var_id, result)?;
-                    }
-                    Instruction::NotEqualsI(bool_var_id, lvalue_id, rvalue) => {
-                        let lvalue = context.get_variable(*lvalue_id)?;
-                        let result = op_not_equals(lvalue.clone(), rvalue.clone())?;
-                        context.set_variable(*bool_var_id, result)?;
-                    }
-                    //
-                    // CONTROL FLOW
-                    //
-                    Instruction::Goto(instruction_number) => {
-                        after_cycle = AfterCycleAction::Goto(*instruction_number);
+                match after_cycle {
+                    AfterCycleAction::None => {
+                        context.instruction_counter += 1;
                     }
-                    Instruction::GotoIfTrue(instruction_number, bool_var_id) => match context.get_variable(*bool_var_id)?.get_bool() {
-                        Some(true) => after_cycle = AfterCycleAction::Goto(*instruction_number),
-                        Some(false) => {}
-                        None => return Err(InterpreterError::GotoNonBoolean),
-                    },
-                    //
-                    // FUNCTIONS
-                    //
-                    Instruction::PushFunctionParameter(var_id) => {
-                        let value = { context.get_variable(*var_id)? };
-                        context.function_parameter_stack.push(value.clone());
+                    AfterCycleAction::Goto(goto_location) => {
+                        context.instruction_counter = goto_location;
                     }
-
-                    Instruction::CallVoidFunction(function_id) => {
+                    AfterCycleAction::Call(new_context) => {
                         context.instruction_counter += 1;
-
                         // push back current context
                         self.callstack.push(context);
                         // then add new context
-                        let function = self.program.get_function(*function_id)?;
-                        if function.return_type.is_some() {
-                            return Err(InterpreterError::VoidCallToNonVoidFunction(*function_id));
-                        }
-                        let new_context = ExecutionContext::new(function, *function_id);
                         self.callstack.push(new_context);
-
+                        self.stats.calls += 1;
+                        self.stats.max_call_depth = self.stats.max_call_depth.max(self.callstack.len());
+                        continue 'execute_context;
+                    }
+                    AfterCycleAction::Return => {
+                        self.callstack.release(context);
                         continue 'execute_context;
                     }
-                    Instruction::CallFunction(function_id, return_value_destination_id) => {
-                        let function_id = *function_id;
+                    AfterCycleAction::Yield => {
                         context.instruction_counter += 1;
-                        context.expecting_return_value = Some(*return_value_destination_id);
-
-                        let function = self.program.get_function(function_id)?;
-                        if function.return_type.is_none() {
-                            return Err(InterpreterError::ExpectingReturnCallToVoidFunction(function_id));
-                        }
-                        let mut new_context = ExecutionContext::new(function, function_id);
-                        for (param_id, param_type) in function.parameters.iter().enumerate().rev() {
-                            let param_id = param_id as u16;
-                            let Some(param_value) = context.function_parameter_stack.pop() else {
-                                return Err(InterpreterError::FunctionCallParameterStackEmptyPop(function_id));
-                            };
-                            if param_value.get_type() != *param_type {
-                                return Err(InterpreterError::FunctionCallParametersInvalid(function_id, false));
-                            }
-                            new_context.set_variable(param_id, param_value)?;
-                        }
-                        // push back current context
                         self.callstack.push(context);
-                        // then add new context
-                        self.callstack.push(new_context);
+                        return ExecutionStatus::Yielded;
+                    }
+                }
+                if let Err(error) = self.check_memory(&context) {
+                    return self.fail(context, error);
+                }
+                if let Some(hit) = self.changed_watchpoint(&context, watched) {
+                    self.callstack.push(context);
+                    return ExecutionStatus::Watchpoint(hit);
+                }
+            }
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.on_return(&context.view(), None);
+            }
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.exit();
+            }
+            self.callstack.release(context);
+        }
+        ExecutionStatus::Finished(self.return_value_storage.take())
+    }
 
-                        continue 'execute_context;
+    fn execute_instruction(&mut self, context: &mut ExecutionContext, instr: &Instruction) -> Result<AfterCycleAction, InterpreterError> {
+        let mut after_cycle = AfterCycleAction::None;
+        match instr {
+            //
+            // MEMORY AND ARRAYS
+            //
+            Instruction::Set(to_var_id, from_var_id) => {
+                let value = context.get_variable(*from_var_id)?;
+                context.set_variable(*to_var_id, value.clone())?;
+                self.stats.values_cloned += 1;
+            }
+            Instruction::SetI(var_id, value) => {
+                context.set_variable(*var_id, value.clone())?;
+                self.stats.values_cloned += 1;
+            }
+            Instruction::SetArrayIndex(array_var_id, array_index, new_value_id) => {
+                let array_index = context.get_variable(*array_index)?.to_usize()?;
+                let new_value = context.get_variable(*new_value_id)?.clone();
+                self.stats.values_cloned += 1;
+                let new_size = new_value.heap_size();
+                let array = context.get_variable_mut(*array_var_id)?;
+                let Value::Array(values) = array else {
+                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
+                };
+                let old_size = values.replace_index(array_index, new_value)?.heap_size();
+                context.allocated_bytes = context.allocated_bytes - old_size + new_size;
+            }
+            Instruction::SetArrayIndexI(array_var_id, array_index, value) => {
+                let array_index = context.get_variable(*array_index)?.to_usize()?;
+                let array = context.get_variable_mut(*array_var_id)?;
+
+                let Value::Array(values) = array else {
+                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
+                };
+                let old_size = values.replace_index(array_index, value.clone())?.heap_size();
+                self.stats.values_cloned += 1;
+                context.allocated_bytes = context.allocated_bytes - old_size + value.heap_size();
+            }
+            Instruction::SetArrayIIndex(array_var_id, array_index, new_value_id) => {
+                let array_index = array_index.to_usize()?;
+                let new_value = context.get_variable(*new_value_id)?.clone();
+                self.stats.values_cloned += 1;
+                let new_size = new_value.heap_size();
+                let array = context.get_variable_mut(*array_var_id)?;
+                let Value::Array(values) = array else {
+                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
+                };
+                let old_size = values.replace_index(array_index, new_value)?.heap_size();
+                context.allocated_bytes = context.allocated_bytes - old_size + new_size;
+            }
+            Instruction::GetArrayIndex(array_var_id, store_var_id, index_var_id) => {
+                let array_index = context.get_variable(*index_var_id)?.to_usize()?;
+                let array = context.get_variable(*array_var_id)?;
+
+                let Value::Array(values) = array else {
+                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
+                };
+
+                let val = values.get_index(array_index)?;
+                context.set_variable(*store_var_id, val)?;
+                self.stats.values_cloned += 1;
+            }
+            Instruction::GetArrayIndexI(array_var_id, store_var_id, array_index) => {
+                let array_index = array_index.to_usize()?;
+                let array = context.get_variable(*array_var_id)?;
+
+                let Value::Array(values) = array else {
+                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
+                };
+
+                let val = values.get_index(array_index)?;
+                context.set_variable(*store_var_id, val)?;
+                self.stats.values_cloned += 1;
+            }
+            //
+            // STRUCTS
+            //
+            Instruction::GetField(struct_var_id, store_var_id, field_id) => {
+                let value = context.get_variable(*struct_var_id)?;
+                let Value::Struct(fields) = value else {
+                    return Err(InterpreterError::StructOperationOnNonStructValue(value.get_type()));
+                };
+                let field = fields.get_field(*field_id)?.clone();
+                context.set_variable(*store_var_id, field)?;
+                self.stats.values_cloned += 1;
+            }
+            Instruction::SetField(struct_var_id, field_id, new_value_id) => {
+                let new_value = context.get_variable(*new_value_id)?.clone();
+                self.stats.values_cloned += 1;
+                let new_size = new_value.heap_size();
+                let value = context.get_variable_mut(*struct_var_id)?;
+                let Value::Struct(fields) = value else {
+                    return Err(InterpreterError::StructOperationOnNonStructValue(value.get_type()));
+                };
+                let old_size = fields.replace_field(*field_id, new_value)?.heap_size();
+                context.allocated_bytes = context.allocated_bytes - old_size + new_size;
+            }
+            Instruction::SetFieldI(struct_var_id, field_id, new_value) => {
+                let value = context.get_variable_mut(*struct_var_id)?;
+                let Value::Struct(fields) = value else {
+                    return Err(InterpreterError::StructOperationOnNonStructValue(value.get_type()));
+                };
+                let old_size = fields.replace_field(*field_id, new_value.clone())?.heap_size();
+                self.stats.values_cloned += 1;
+                context.allocated_bytes = context.allocated_bytes - old_size + new_value.heap_size();
+            }
+            //
+            // OPTIONS
+            //
+            Instruction::WrapSome(option_var_id, var_id) => {
+                let value = context.get_variable(*var_id)?.clone();
+                self.stats.values_cloned += 1;
+                let new_size = value_size(&value);
+                let replaced = Self::option_variable_mut(context, *option_var_id)?.replace(Some(value))?;
+                context.allocated_bytes = context.allocated_bytes + new_size - replaced.as_ref().map_or(0, value_size);
+            }
+            Instruction::SetNone(option_var_id) => {
+                let replaced = Self::option_variable_mut(context, *option_var_id)?.replace(None)?;
+                context.allocated_bytes -= replaced.as_ref().map_or(0, value_size);
+            }
+            Instruction::IsSome(option_var_id, store_var_id) => {
+                let is_some = Self::option_variable(context, *option_var_id)?.is_some();
+                context.set_variable(*store_var_id, Value::Bool(is_some))?;
+            }
+            Instruction::Unwrap(option_var_id, store_var_id) => {
+                let option = Self::option_variable(context, *option_var_id)?;
+                let value = option.value().ok_or(InterpreterError::UnwrapNone)?.clone();
+                self.stats.values_cloned += 1;
+                context.set_variable(*store_var_id, value)?;
+            }
+            //
+            // ENUMS
+            //
+            Instruction::SetVariant(enum_var_id, tag, payload_var_id) => {
+                let enum_id = Self::enum_variable(context, *enum_var_id)?.enum_id();
+                let payload = context.get_variable(*payload_var_id)?;
+                match self.program.get_enum(enum_id)?.variant(enum_id, *tag)? {
+                    Some(payload_type) if payload.is_type(payload_type) => {}
+                    Some(payload_type) => return Err(InterpreterError::EnumPayloadTypeMismatch(payload_type.clone(), payload.get_type())),
+                    None => return Err(InterpreterError::EnumVariantHasNoPayload(enum_id, *tag)),
+                }
+                let value = EnumValue::new(enum_id, *tag, Some(payload.clone()));
+                self.stats.values_cloned += 1;
+                context.set_variable(*enum_var_id, Value::Enum(value))?;
+            }
+            Instruction::SetUnitVariant(enum_var_id, tag) => {
+                let enum_id = Self::enum_variable(context, *enum_var_id)?.enum_id();
+                if self.program.get_enum(enum_id)?.variant(enum_id, *tag)?.is_some() {
+                    return Err(InterpreterError::EnumVariantNeedsPayload(enum_id, *tag));
+                }
+                context.set_variable(*enum_var_id, Value::Enum(EnumValue::new(enum_id, *tag, None)))?;
+            }
+            Instruction::GetTag(enum_var_id, store_var_id) => {
+                let tag = Self::enum_variable(context, *enum_var_id)?.tag();
+                context.set_variable(*store_var_id, Value::U16(tag))?;
+            }
+            Instruction::GetPayload(enum_var_id, store_var_id, tag) => {
+                let payload = Self::enum_variable(context, *enum_var_id)?.get_payload(*tag)?.clone();
+                self.stats.values_cloned += 1;
+                context.set_variable(*store_var_id, payload)?;
+            }
+            //
+            // REFERENCES
+            //
+            Instruction::MakeRef(ref_var_id, var_id) => {
+                let value = context.get_variable(*var_id)?;
+                let inner_type = Self::ref_variable(context, *ref_var_id)?.inner_type();
+                if !value.is_type(inner_type) {
+                    return Err(InterpreterError::ReferenceTypeMismatch(inner_type.clone(), value.get_type()));
+                }
+                let target = RefTarget::Variable {
+                    frame: self.callstack.len(),
+                    var_id: *var_id,
+                };
+                context.set_variable(*ref_var_id, Value::Ref(RefValue::new(inner_type.clone(), target)))?;
+            }
+            Instruction::AllocRef(ref_var_id, var_id) => {
+                let value = context.get_variable(*var_id)?.clone();
+                self.stats.values_cloned += 1;
+                let inner_type = Self::ref_variable(context, *ref_var_id)?.inner_type().clone();
+                if !value.is_type(&inner_type) {
+                    return Err(InterpreterError::ReferenceTypeMismatch(inner_type, value.get_type()));
+                }
+                check_ref_frames(&value, None)?;
+                if self.heap.should_collect() {
+                    self.collect(Some(context), Some(&value));
+                }
+                let target = RefTarget::Heap(self.heap.allocate(value)?);
+                context.set_variable(*ref_var_id, Value::Ref(RefValue::new(inner_type, target)))?;
+            }
+            Instruction::LoadRef(ref_var_id, store_var_id) => {
+                let reference = Self::ref_variable(context, *ref_var_id)?;
+                let value = Self::deref(&self.callstack, &self.heap, context, reference)?.clone();
+                self.stats.values_cloned += 1;
+                context.set_variable(*store_var_id, value)?;
+            }
+            Instruction::StoreRef(ref_var_id, var_id) => {
+                let value = context.get_variable(*var_id)?.clone();
+                self.stats.values_cloned += 1;
+                let reference = Self::ref_variable(context, *ref_var_id)?;
+                if !value.is_type(reference.inner_type()) {
+                    return Err(InterpreterError::ReferenceTypeMismatch(
+                        reference.inner_type().clone(),
+                        value.get_type(),
+                    ));
+                }
+                let target = reference.target().ok_or(InterpreterError::NullReference)?;
+                check_ref_frames(&value, target_frame(target))?;
+                Self::change_referenced(&mut self.callstack, &mut self.heap, context, target, |current| {
+                    if !current.is_same_type(&value) {
+                        return Err(InterpreterError::DanglingReference);
+                    }
+                    let added = value.heap_size();
+                    Ok((std::mem::replace(current, value).heap_size(), added))
+                })?;
+            }
+            Instruction::GetRefIndex(ref_var_id, store_var_id, index_var_id) => {
+                let array_index = context.get_variable(*index_var_id)?.to_usize()?;
+                let reference = Self::ref_variable(context, *ref_var_id)?;
+                let value = match Self::deref(&self.callstack, &self.heap, context, reference)? {
+                    Value::Array(values) => values.get_index(array_index)?,
+                    other => return Err(InterpreterError::ArrayOperationOnNonArrayValue(other.get_type())),
+                };
+                self.stats.values_cloned += 1;
+                context.set_variable(*store_var_id, value)?;
+            }
+            Instruction::SetRefIndex(ref_var_id, index_var_id, var_id) => {
+                let array_index = context.get_variable(*index_var_id)?.to_usize()?;
+                let value = context.get_variable(*var_id)?.clone();
+                self.stats.values_cloned += 1;
+                let target = Self::ref_variable(context, *ref_var_id)?
+                    .target()
+                    .ok_or(InterpreterError::NullReference)?;
+                check_ref_frames(&value, target_frame(target))?;
+                Self::change_referenced(&mut self.callstack, &mut self.heap, context, target, |current| {
+                    let Value::Array(values) = current else {
+                        return Err(InterpreterError::ArrayOperationOnNonArrayValue(current.get_type()));
+                    };
+                    let added = value.heap_size();
+                    Ok((values.replace_index(array_index, value)?.heap_size(), added))
+                })?;
+            }
+            //
+            // OBJECTS
+            //
+            Instruction::NewObject(object_var_id, var_id) => {
+                let value = context.get_variable(*var_id)?.clone();
+                self.stats.values_cloned += 1;
+                let inner_type = Self::object_variable(context, *object_var_id)?.inner_type().clone();
+                if !value.is_type(&inner_type) {
+                    return Err(InterpreterError::ReferenceTypeMismatch(inner_type, value.get_type()));
+                }
+                check_ref_frames(&value, None)?;
+                if self.heap.should_collect() {
+                    self.collect(Some(context), Some(&value));
+                }
+                let handle = self.heap.allocate(value)?;
+                context.set_variable(*object_var_id, Value::Object(ObjectValue::new(inner_type, handle)))?;
+            }
+            Instruction::GetObject(object_var_id, store_var_id) => {
+                let object = Self::object_variable(context, *object_var_id)?;
+                let value = self.heap.get(object.handle().ok_or(InterpreterError::NullReference)?)?;
+                if !value.is_type(object.inner_type()) {
+                    return Err(InterpreterError::DanglingReference);
+                }
+                let value = value.clone();
+                self.stats.values_cloned += 1;
+                context.set_variable(*store_var_id, value)?;
+            }
+            Instruction::SetObject(object_var_id, var_id) => {
+                let value = context.get_variable(*var_id)?.clone();
+                self.stats.values_cloned += 1;
+                let object = Self::object_variable(context, *object_var_id)?;
+                if !value.is_type(object.inner_type()) {
+                    return Err(InterpreterError::ReferenceTypeMismatch(
+                        object.inner_type().clone(),
+                        value.get_type(),
+                    ));
+                }
+                let handle = object.handle().ok_or(InterpreterError::NullReference)?;
+                check_ref_frames(&value, None)?;
+                self.heap.change(handle, |current| {
+                    if !current.is_same_type(&value) {
+                        return Err(InterpreterError::DanglingReference);
                     }
+                    let added = value.heap_size();
+                    Ok((std::mem::replace(current, value).heap_size(), added))
+                })?;
+            }
+            Instruction::ObjectRef(ref_var_id, object_var_id) => {
+                let object = Self::object_variable(context, *object_var_id)?;
+                let inner_type = Self::ref_variable(context, *ref_var_id)?.inner_type();
+                if inner_type != object.inner_type() {
+                    return Err(InterpreterError::ReferenceTypeMismatch(
+                        inner_type.clone(),
+                        object.inner_type().clone(),
+                    ));
+                }
+                let reference = match object.handle() {
+                    Some(handle) => RefValue::new(inner_type.clone(), RefTarget::Heap(handle)),
+                    None => RefValue::null(inner_type.clone()),
+                };
+                context.set_variable(*ref_var_id, Value::Ref(reference))?;
+            }
+            //
+            // ARITHMETIC
+            //
+            Instruction::Add(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
 
-                    Instruction::CallNativeVoidFunction(native_function_id) => {
-                        // println for now
-                        #[allow(clippy::match_single_binding)]
-                        match native_function_id {
-                            _ => {
-                                let Some(value) = context.function_parameter_stack.pop() else {
-                                    return Err(InterpreterError::FunctionCallParametersInvalid(*native_function_id, true));
-                                };
-                                assert!(context.function_parameter_stack.is_empty());
-                                println!("Println {:?}", value);
-                            }
+                let new_value = op_add(lvalue.clone(), rvalue.clone())?;
+
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::Sub(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_sub(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::Mul(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_mul(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::Div(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_div(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::Rem(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_rem(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::AddI(lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let new_value = op_add(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::SubI(lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let new_value = op_sub(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::MulI(lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let new_value = op_mul(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::DivI(lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let new_value = op_div(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::RemI(lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let new_value = op_rem(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            //
+            // COMPARISON
+            //
+            Instruction::LessThan(bool_var_id, lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let result = op_less_than(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::LessThanI(bool_var_id, lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let result = op_less_than(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::GreaterThan(bool_var_id, lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let result = op_greater_than(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::GreaterThanI(bool_var_id, lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let result = op_greater_than(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::LessThanOrEqual(bool_var_id, lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let result = op_less_than_or_equal(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::LessThanOrEqualI(bool_var_id, lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let result = op_less_than_or_equal(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::GreaterThanOrEqual(bool_var_id, lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let result = op_greater_than_or_equal(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::GreaterThanOrEqualI(bool_var_id, lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let result = op_greater_than_or_equal(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::Equals(bool_var_id, lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+
+                let result = op_equals(lvalue.clone(), rvalue.clone())?;
+
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::EqualsI(bool_var_id, lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let result = op_equals(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::NotEquals(bool_var_id, lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+
+                let result = op_not_equals(lvalue.clone(), rvalue.clone())?;
+
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            Instruction::NotEqualsI(bool_var_id, lvalue_id, rvalue) => {
+                let lvalue = context.get_variable(*lvalue_id)?;
+                let result = op_not_equals(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*bool_var_id, result)?;
+            }
+            //
+            // LOGICAL
+            //
+            Instruction::Or(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_or(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::And(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_and(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::Xor(lvalue_id, rvalue_id) => {
+                let (lvalue, rvalue) = context.get_variable_pair(*lvalue_id, *rvalue_id)?;
+                let new_value = op_xor(lvalue.clone(), rvalue.clone())?;
+                self.stats.values_cloned += 2;
+                context.set_variable(*lvalue_id, new_value)?;
+            }
+            Instruction::Not(var_id) => {
+                let value = context.get_variable(*var_id)?;
+                let new_value = op_not(value.clone())?;
+                self.stats.values_cloned += 1;
+                context.set_variable(*var_id, new_value)?;
+            }
+            //
+            // CONTROL FLOW
+            //
+            Instruction::Goto(instruction_number) => {
+                after_cycle = AfterCycleAction::Goto(*instruction_number);
+            }
+            Instruction::GotoIfTrue(instruction_number, bool_var_id) => match context.get_variable(*bool_var_id)?.get_bool() {
+                Some(true) => after_cycle = AfterCycleAction::Goto(*instruction_number),
+                Some(false) => {}
+                None => return Err(InterpreterError::GotoNonBoolean),
+            },
+            Instruction::Switch(var_id, jump_table) => {
+                let index = match context.get_variable(*var_id)? {
+                    Value::Enum(value) => value.tag() as usize,
+                    value if value.is_number() => value.to_usize()?,
+                    value => return Err(InterpreterError::SwitchOnUnsupportedValue(value.get_type())),
+                };
+                if let Some(instruction_number) = jump_table.get(index) {
+                    after_cycle = AfterCycleAction::Goto(*instruction_number);
+                }
+            }
+            //
+            // FUNCTIONS
+            //
+            Instruction::PushFunctionParameter(var_id) => {
+                if context.function_parameter_stack.len() >= self.max_parameter_stack_size {
+                    return Err(InterpreterError::FunctionParameterStackOverflow {
+                        size: context.function_parameter_stack.len() + 1,
+                        function_id: context.function_id,
+                    });
+                }
+                let value = { context.get_variable(*var_id)? };
+                context.push_parameter(value.clone());
+                self.stats.values_cloned += 1;
+            }
+
+            Instruction::CallVoidFunction(function_id) => {
+                after_cycle = AfterCycleAction::Call(self.enter_function(context, *function_id, None)?);
+            }
+            Instruction::CallFunction(function_id, return_value_destination_id) => {
+                after_cycle = AfterCycleAction::Call(self.enter_function(context, *function_id, Some(*return_value_destination_id))?);
+            }
+            Instruction::CallVoidIndirect(var_id) => {
+                let function_id = self.function_value_target(context, *var_id)?;
+                let mut new_context = self.enter_function(context, function_id, None)?;
+                self.store_captures(context, &mut new_context, *var_id)?;
+                after_cycle = AfterCycleAction::Call(new_context);
+            }
+            Instruction::CallIndirect(var_id, return_value_destination_id) => {
+                let function_id = self.function_value_target(context, *var_id)?;
+                let mut new_context = self.enter_function(context, function_id, Some(*return_value_destination_id))?;
+                self.store_captures(context, &mut new_context, *var_id)?;
+                after_cycle = AfterCycleAction::Call(new_context);
+            }
+            Instruction::MakeClosure(var_id, function_id, captures) => {
+                let function = self.program.get_function(*function_id)?;
+                let mut values = Vec::with_capacity(captures.len());
+                for capture in captures.iter() {
+                    values.push(match *capture {
+                        Capture::Value(captured_id) => {
+                            self.stats.values_cloned += 1;
+                            context.get_variable(captured_id)?.clone()
+                        }
+                        Capture::Ref(captured_id) => {
+                            let target = RefTarget::Variable {
+                                frame: self.callstack.len(),
+                                var_id: captured_id,
+                            };
+                            Value::Ref(RefValue::new(context.get_variable(captured_id)?.get_type(), target))
+                        }
+                    });
+                }
+                check_captures(function, *function_id, &values)?;
+                let closure = FunctionValue::closure(function.signature(), *function_id, values);
+                context.set_variable(*var_id, Value::Function(closure))?;
+            }
+
+            Instruction::CallNativeVoidFunction(native_function_id) => {
+                self.stats.native_calls += 1;
+                match *native_function_id {
+                    native::PRINTLN => {
+                        let Some(value) = context.pop_parameter() else {
+                            return Err(InterpreterError::FunctionCallParametersInvalid(*native_function_id, true));
+                        };
+                        if !context.function_parameter_stack.is_empty() {
+                            return Err(InterpreterError::FunctionCallParametersInvalid(*native_function_id, true));
                         }
+                        println!("Println {:?}", value);
                     }
-                    Instruction::CallNativeVoidMethod(var_id, method_id) => {
-                        #[allow(clippy::match_single_binding)]
-                        match method_id {
-                            _ => {
-                                // array.push() just for now.
-
-                                let Some(push_value) = context.function_parameter_stack.pop() else {
-                                    return Err(InterpreterError::FunctionCallParameterStackEmptyPop(*method_id));
-                                };
-                                let array = context.get_variable_mut(*var_id)?;
-                                let Value::Array(values) = array else {
-                                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
-                                };
+                    _ => return Err(InterpreterError::NativeFunctionDoesNotExist(*native_function_id)),
+                }
+            }
+            Instruction::CallNativeVoidMethod(var_id, method_id) => {
+                self.stats.native_calls += 1;
+                match *method_id {
+                    native::ARRAY_PUSH => {
+                        let push_value = Self::pop_native_parameter(context, *method_id)?;
+                        let push_size = push_value.heap_size();
+                        let pushed_bytes = match (context.get_variable_mut(*var_id)?, push_value) {
+                            (Value::Array(values), push_value) => {
                                 values.push(push_value)?;
+                                values.element_size() + push_size
                             }
-                        }
-                    }
-                    Instruction::CallNativeMethod(var_id, value_return_store, method_id) => {
-                        #[allow(clippy::match_single_binding)]
-                        match method_id {
-                            _ => {
-                                // array.len() just for now.
-                                let array = context.get_variable_mut(*var_id)?;
-                                let Value::Array(values) = array else {
-                                    return Err(InterpreterError::ArrayOperationOnNonArrayValue(array.get_type()));
-                                };
-                                let len = values.len();
-                                context.set_variable(*value_return_store, Value::U64(len as u64))?;
+                            (Value::String(string), Value::Char(c)) => {
+                                string.push(c);
+                                c.len_utf8()
+                            }
+                            (Value::Bytes(bytes), Value::U8(byte)) => {
+                                bytes.push(byte);
+                                1
+                            }
+                            (target @ (Value::String(_) | Value::Bytes(_)), push_value) => {
+                                return Err(InterpreterError::ArrayTypeIncompatibleWithPushValue(
+                                    target.get_type(),
+                                    push_value.get_type(),
+                                ))
                             }
+                            (other, _) => return Err(InterpreterError::ArrayOperationOnNonArrayValue(other.get_type())),
+                        };
+                        context.allocated_bytes += pushed_bytes;
+                    }
+                    native::MAP_INSERT => {
+                        let value = Self::pop_native_parameter(context, *method_id)?;
+                        let key = Self::pop_native_parameter(context, *method_id)?;
+                        let (key_size, value_size) = (key.heap_size(), value.heap_size());
+                        match Self::map_variable_mut(context, *var_id)?.insert(key, value)? {
+                            Some(replaced) => context.allocated_bytes = context.allocated_bytes + value_size - replaced.heap_size(),
+                            None => context.allocated_bytes += MapValue::entry_size() + key_size + value_size,
                         }
                     }
-
-                    Instruction::Return(var_id_to_return) => {
-                        let value = context.get_variable(*var_id_to_return)?;
-                        self.return_value_storage = Some(value.clone());
-                        continue 'execute_context;
+                    native::MAP_REMOVE => {
+                        let key = Self::pop_native_parameter(context, *method_id)?;
+                        Self::remove_map_entry(context, *var_id, key)?;
                     }
-                    //
-                    // Unimplemented
-                    //
-                    _ => unimplemented!(),
+                    _ => return Err(InterpreterError::NativeFunctionDoesNotExist(*method_id)),
                 }
-
-                // context.print_state(&self.program);
-
-                match after_cycle {
-                    AfterCycleAction::None => {
-                        context.instruction_counter += 1;
+            }
+            Instruction::CallNativeMethod(var_id, value_return_store, method_id) => {
+                self.stats.native_calls += 1;
+                let value = match *method_id {
+                    native::LEN => match context.get_variable(*var_id)? {
+                        Value::Array(values) => Value::U64(values.len() as u64),
+                        Value::Map(map) => Value::U64(map.len() as u64),
+                        Value::Bytes(bytes) => Value::U64(bytes.len() as u64),
+                        Value::String(string) => Value::U64(string.chars().count() as u64),
+                        other => return Err(InterpreterError::ArrayOperationOnNonArrayValue(other.get_type())),
+                    },
+                    native::MAP_GET => {
+                        let key = Self::pop_native_parameter(context, *method_id)?;
+                        let value = Self::map_variable(context, *var_id)?
+                            .get(key)?
+                            .ok_or(InterpreterError::MapKeyNotFound)?
+                            .clone();
+                        self.stats.values_cloned += 1;
+                        value
                     }
-                    AfterCycleAction::Goto(goto_location) => {
-                        context.instruction_counter = goto_location;
-                        after_cycle = AfterCycleAction::None;
+                    native::MAP_TRY_GET => {
+                        let key = Self::pop_native_parameter(context, *method_id)?;
+                        let map = Self::map_variable(context, *var_id)?;
+                        let value = match map.get(key)? {
+                            Some(value) => {
+                                self.stats.values_cloned += 1;
+                                OptionValue::some(value.clone())
+                            }
+                            None => OptionValue::none(map.value_type().clone()),
+                        };
+                        Value::Option(value)
                     }
-                }
+                    native::MAP_REMOVE => {
+                        let key = Self::pop_native_parameter(context, *method_id)?;
+                        Self::remove_map_entry(context, *var_id, key)?.ok_or(InterpreterError::MapKeyNotFound)?
+                    }
+                    native::MAP_CONTAINS => {
+                        let key = Self::pop_native_parameter(context, *method_id)?;
+                        Value::Bool(Self::map_variable(context, *var_id)?.contains(key)?)
+                    }
+                    native::MAP_KEYS => Value::Array(Self::map_variable(context, *var_id)?.keys()?),
+                    native::MAP_VALUES => {
+                        let map = Self::map_variable(context, *var_id)?;
+                        self.stats.values_cloned += map.len() as u64;
+                        Value::Array(map.values()?)
+                    }
+                    native::TO_STRING => match context.get_variable(*var_id)? {
+                        Value::Char(c) => Value::String(c.to_string()),
+                        Value::String(string) => Value::String(string.clone()),
+                        Value::Bytes(bytes) => Value::String(String::from_utf8(bytes.clone()).map_err(|_| InterpreterError::BytesNotUtf8)?),
+                        other => return Err(InterpreterError::TextOperationOnNonTextValue(other.get_type())),
+                    },
+                    native::TO_BYTES => match context.get_variable(*var_id)? {
+                        Value::Char(c) => Value::Bytes(c.encode_utf8(&mut [0; 4]).as_bytes().to_vec()),
+                        Value::String(string) => Value::Bytes(string.as_bytes().to_vec()),
+                        Value::Bytes(bytes) => Value::Bytes(bytes.clone()),
+                        other => return Err(InterpreterError::TextOperationOnNonTextValue(other.get_type())),
+                    },
+                    native::CHARS => match context.get_variable(*var_id)? {
+                        Value::String(string) => Value::Array(ArrayValue::CharArray(string.chars().collect())),
+                        other => return Err(InterpreterError::TextOperationOnNonTextValue(other.get_type())),
+                    },
+                    native::AT => {
+                        let index = Self::pop_native_parameter(context, *method_id)?.to_usize()?;
+                        let value = match context.get_variable(*var_id)? {
+                            Value::String(string) => string.chars().nth(index).map(Value::Char),
+                            Value::Bytes(bytes) => bytes.get(index).copied().map(Value::U8),
+                            other => return Err(InterpreterError::TextOperationOnNonTextValue(other.get_type())),
+                        };
+                        value.ok_or(InterpreterError::TextIndexBeyondBounds(index))?
+                    }
+                    _ => return Err(InterpreterError::NativeFunctionDoesNotExist(*method_id)),
+                };
+                context.set_variable(*value_return_store, value)?;
+            }
+
+            Instruction::Return(var_id_to_return) => {
+                let value = context.get_variable(*var_id_to_return)?;
+                // the caller sits one frame below the running one
+                check_ref_frames(value, self.callstack.len().checked_sub(1))?;
+                self.return_value_storage = Some(value.clone());
+                self.stats.values_cloned += 1;
+                after_cycle = AfterCycleAction::Return;
             }
+            Instruction::Yield => after_cycle = AfterCycleAction::Yield,
         }
-        Ok(())
+        Ok(after_cycle)
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::interpreter::*;
+    use crate::value::{EnumDefinition, FunctionValue, MapValue, OptionValue, StructValue};
     fn run_function(function: Function) {
         let program = Program::new(&[function]);
-        let mut interpreter = Interpreter::new(program);
+        let mut interpreter = Interpreter::new(program).unwrap();
 
         interpreter.execute().unwrap();
     }
@@ -454,9 +2020,9 @@ mod test {
             Instruction::PushFunctionParameter(1),
             Instruction::CallNativeVoidFunction(0),
         ]);
-        main.register_variables(&[VariableType::U32, VariableType::Bool]);
+        main.register_variables(&[VariableType::U32, VariableType::Bool]).unwrap();
         let mut other = Function::new(&[], Some(VariableType::Bool));
-        other.register_variables(&[VariableType::Bool]);
+        other.register_variables(&[VariableType::Bool]).unwrap();
         other.set_instructions(vec![
             Instruction::SetI(0, Value::Bool(true)),
             Instruction::PushFunctionParameter(0),
@@ -464,9 +2030,409 @@ mod test {
             Instruction::Return(0),
         ]);
         let program = Program::new(&[main, other]);
-        let mut interpreter = Interpreter::new(program);
+        let mut interpreter = Interpreter::new(program).unwrap();
+
+        interpreter.execute().unwrap();
+    }
+    #[test]
+    fn test_execution_stats() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::Bool]).unwrap();
+        main.set_instructions(vec![
+            Instruction::CallFunction(1, 0),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallNativeVoidFunction(0),
+        ]);
+        let mut other = Function::new(&[], Some(VariableType::Bool));
+        other.register_variables(&[VariableType::Bool]).unwrap();
+        other.set_instructions(vec![
+            Instruction::SetI(0, Value::Bool(true)),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallNativeVoidFunction(0),
+            Instruction::Return(0),
+        ]);
+        let program = Program::new(&[main, other]);
+        let mut interpreter = Interpreter::new(program).unwrap();
+
+        interpreter.execute().unwrap();
+        assert_eq!(
+            interpreter.stats(),
+            ExecutionStats {
+                instructions: 7,
+                calls: 1,
+                native_calls: 2,
+                max_call_depth: 2,
+                values_cloned: 4,
+            }
+        );
+    }
+    #[test]
+    fn test_recursive_function_call() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::U64, VariableType::U64]).unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::U64(10)),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 1),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeVoidFunction(0),
+        ]);
+        let mut fibonacci = Function::new(&[VariableType::U64], Some(VariableType::U64));
+        fibonacci
+            .register_variables(&[VariableType::Bool, VariableType::U64, VariableType::U64, VariableType::U64])
+            .unwrap();
+        fibonacci.set_instructions(vec![
+            Instruction::LessThanI(1, 0, Value::U64(2)),
+            Instruction::GotoIfTrue(11, 1),
+            Instruction::Set(2, 0),
+            Instruction::SubI(2, Value::U64(1)),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallFunction(1, 3),
+            Instruction::SubI(2, Value::U64(1)),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallFunction(1, 4),
+            Instruction::Add(3, 4),
+            Instruction::Return(3),
+            Instruction::Return(0),
+        ]);
+        let program = Program::new(&[main, fibonacci]);
+        let mut interpreter = Interpreter::new(program).unwrap();
+
+        interpreter.execute().unwrap();
+    }
+    #[test]
+    fn test_out_of_fuel_resumes() {
+        let mut func = Function::new(&[], None);
+        func.register_variables(&[VariableType::U64, VariableType::Bool]).unwrap();
+        func.set_instructions(vec![
+            Instruction::AddI(0, Value::U64(1)),
+            Instruction::LessThanI(1, 0, Value::U64(10)),
+            Instruction::GotoIfTrue(0, 1),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+        interpreter.set_fuel(Some(20));
+
+        assert!(matches!(
+            interpreter.execute(),
+            Err(RuntimeError {
+                kind: InterpreterError::OutOfFuel,
+                ..
+            })
+        ));
+        assert_eq!(interpreter.fuel_consumed(), 20);
+        assert_eq!(interpreter.remaining_fuel(), Some(0));
+
+        interpreter.add_fuel(100);
+        interpreter.execute().unwrap();
+        assert_eq!(interpreter.fuel_consumed(), 30);
+        assert_eq!(interpreter.remaining_fuel(), Some(90));
+    }
+    #[test]
+    fn test_fuel_consumed_per_run() {
+        let mut func = Function::new(&[], None);
+        func.register_variables(&[VariableType::U64, VariableType::Bool]).unwrap();
+        func.set_instructions(vec![
+            Instruction::AddI(0, Value::U64(1)),
+            Instruction::LessThanI(1, 0, Value::U64(10)),
+            Instruction::GotoIfTrue(0, 1),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+        interpreter.run_for(12);
+        assert_eq!(interpreter.fuel_consumed(), 12);
+
+        // a new budget counts what the rest of the run consumes
+        interpreter.set_fuel(Some(100));
+        interpreter.execute().unwrap();
+        assert_eq!(interpreter.fuel_consumed(), 18);
+        assert_eq!(interpreter.remaining_fuel(), Some(82));
+        assert_eq!(interpreter.stats().instructions, 30);
+    }
+    #[test]
+    fn test_runaway_loop_stops() {
+        let mut func = Function::new(&[], None);
+        func.set_instructions(vec![Instruction::Goto(0)]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+        interpreter.set_fuel(Some(1000));
+
+        assert!(matches!(
+            interpreter.execute(),
+            Err(RuntimeError {
+                kind: InterpreterError::OutOfFuel,
+                ..
+            })
+        ));
+        assert_eq!(interpreter.fuel_consumed(), 1000);
+    }
+    #[test]
+    fn test_infinite_recursion_overflows() {
+        let mut main = Function::new(&[], None);
+        main.set_instructions(vec![Instruction::CallVoidFunction(0)]);
+        let mut interpreter = Interpreter::new(Program::new(&[main])).unwrap();
+        interpreter.set_max_call_depth(64);
+
+        assert!(matches!(
+            interpreter.execute(),
+            Err(RuntimeError {
+                kind: InterpreterError::StackOverflow { depth: 65, function_id: 0 },
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn test_parameter_stack_limit() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::U64]).unwrap();
+        main.set_instructions(vec![Instruction::PushFunctionParameter(0), Instruction::Goto(0)]);
+        let mut interpreter = Interpreter::new(Program::new(&[main])).unwrap();
+        interpreter.set_max_parameter_stack_size(16);
+
+        assert!(matches!(
+            interpreter.execute(),
+            Err(RuntimeError {
+                kind: InterpreterError::FunctionParameterStackOverflow { size: 17, function_id: 0 },
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn test_memory_usage_tracks_array_growth() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[
+            VariableType::Array(Box::new(VariableType::U64)),
+            VariableType::U64,
+            VariableType::Bool,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeVoidMethod(0, 0),
+            Instruction::AddI(1, Value::U64(1)),
+            Instruction::LessThanI(2, 1, Value::U64(100)),
+            Instruction::GotoIfTrue(0, 2),
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![]))),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main])).unwrap();
+        let start = interpreter.memory_usage();
+
+        interpreter.execute().unwrap();
+        let usage = interpreter.memory_usage();
+        assert!(usage.peak >= start.current + 100 * size_of::<u64>());
+        // the finished frame no longer holds anything
+        assert_eq!(usage.current, 0);
+    }
+    #[test]
+    fn test_memory_limit() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::Array(Box::new(VariableType::U64)), VariableType::U64])
+            .unwrap();
+        main.set_instructions(vec![
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeVoidMethod(0, 0),
+            Instruction::Goto(0),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main])).unwrap();
+        interpreter.set_memory_limit(Some(4096));
+
+        assert!(matches!(
+            interpreter.execute(),
+            Err(RuntimeError {
+                kind: InterpreterError::OutOfMemory { limit: 4096, .. },
+                ..
+            })
+        ));
+        assert!(interpreter.memory_usage().peak > 4096);
+    }
+    #[test]
+    fn test_memory_limit_across_calls() {
+        let array_type = VariableType::Array(Box::new(VariableType::U64));
+        let closure_type = VariableType::Function(Box::new(Signature::new(&[], Some(array_type.clone()))));
+        // returns the array it captured, only ever calling and returning once the limit is set
+        let mut captured = Function::new(&[], Some(array_type.clone()));
+        captured.register_variable(array_type.clone()).unwrap();
+        captured.set_instructions(vec![Instruction::Return(0)]);
+        let mut main = Function::new(&[], Some(array_type.clone()));
+        main.register_variables(&[array_type.clone(), closure_type, array_type]).unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![7; 1000]))),
+            Instruction::MakeClosure(1, 1, vec![Capture::Value(0)]),
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![]))),
+            Instruction::Yield,
+            Instruction::CallIndirect(1, 2),
+            Instruction::Return(2),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, captured])).unwrap();
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Yielded));
+        // room for the captured array, but not for the copy handed to the callee and returned from it
+        let limit = interpreter.memory_usage().current + 1000 * size_of::<u64>() / 2;
+        interpreter.set_memory_limit(Some(limit));
+
+        assert!(matches!(
+            interpreter.run_for(100),
+            ExecutionStatus::Error(RuntimeError {
+                kind: InterpreterError::OutOfMemory { .. },
+                ..
+            })
+        ));
+        assert!(interpreter.memory_usage().peak > limit);
+    }
+    #[test]
+    fn test_step_through_program() {
+        let mut func = Function::new(&[], Some(VariableType::U64));
+        func.register_variables(&[VariableType::U64]).unwrap();
+        func.set_instructions(vec![
+            Instruction::SetI(0, Value::U64(1)),
+            Instruction::AddI(0, Value::U64(2)),
+            Instruction::Return(0),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+
+        assert!(matches!(interpreter.step(), ExecutionStatus::Running));
+        assert!(matches!(interpreter.step(), ExecutionStatus::Running));
+        assert!(matches!(interpreter.step(), ExecutionStatus::Finished(Some(Value::U64(3)))));
+        assert_eq!(interpreter.fuel_consumed(), 3);
+    }
+    #[test]
+    fn test_run_for_timeslices() {
+        let mut func = Function::new(&[], None);
+        func.register_variables(&[VariableType::U64, VariableType::Bool]).unwrap();
+        func.set_instructions(vec![
+            Instruction::AddI(0, Value::U64(1)),
+            Instruction::LessThanI(1, 0, Value::U64(10)),
+            Instruction::GotoIfTrue(0, 1),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+
+        let mut slices = 0;
+        while let ExecutionStatus::Running = interpreter.run_for(4) {
+            slices += 1;
+        }
+        assert_eq!(slices, 7);
+        assert_eq!(interpreter.fuel_consumed(), 30);
+    }
+    #[test]
+    fn test_yield_hands_back_control() {
+        let mut main = Function::new(&[], None);
+        main.set_instructions(vec![Instruction::CallVoidFunction(1), Instruction::Yield]);
+        let mut other = Function::new(&[], None);
+        other.set_instructions(vec![Instruction::Yield]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, other])).unwrap();
 
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Yielded));
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Yielded));
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(None)));
+
+        // `execute` runs through yields
+        let mut main = Function::new(&[], None);
+        main.set_instructions(vec![Instruction::CallVoidFunction(1), Instruction::Yield]);
+        let mut other = Function::new(&[], None);
+        other.set_instructions(vec![Instruction::Yield]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, other])).unwrap();
         interpreter.execute().unwrap();
+        assert_eq!(interpreter.call_depth(), 0);
+    }
+    #[test]
+    fn test_error_leaves_failing_instruction_next() {
+        let mut func = Function::new(&[], None);
+        func.register_variables(&[VariableType::U64, VariableType::U64]).unwrap();
+        func.set_instructions(vec![Instruction::Rem(0, 1)]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+
+        assert!(matches!(
+            interpreter.run_for(10),
+            ExecutionStatus::Error(RuntimeError {
+                kind: InterpreterError::OperatorDivideByZero,
+                ..
+            })
+        ));
+        assert!(matches!(
+            interpreter.run_for(10),
+            ExecutionStatus::Error(RuntimeError {
+                kind: InterpreterError::OperatorDivideByZero,
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn test_error_trace() {
+        let mut main = Function::new(&[], None);
+        main.set_name("main");
+        main.set_instructions(vec![Instruction::SetI(0, Value::U8(0)), Instruction::CallVoidFunction(1)]);
+        main.register_variables(&[VariableType::U8]).unwrap();
+        let mut outer = Function::new(&[], None);
+        outer.set_name("outer");
+        outer.set_instructions(vec![Instruction::CallVoidFunction(2)]);
+        let mut inner = Function::new(&[], None);
+        inner
+            .register_variables(&[VariableType::Array(Box::new(VariableType::U8)), VariableType::U64, VariableType::U8])
+            .unwrap();
+        inner.set_instructions(vec![Instruction::SetI(1, Value::U64(5)), Instruction::GetArrayIndex(0, 2, 1)]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, outer, inner])).unwrap();
+
+        let error = interpreter.execute().unwrap_err();
+        assert!(matches!(error.kind, InterpreterError::ArrayIndexBeyondBounds(5)));
+        assert_eq!(
+            error.trace,
+            vec![
+                Frame {
+                    function_id: 2,
+                    name: None,
+                    instruction_counter: 1
+                },
+                Frame {
+                    function_id: 1,
+                    name: Some("outer".to_string()),
+                    instruction_counter: 0
+                },
+                Frame {
+                    function_id: 0,
+                    name: Some("main".to_string()),
+                    instruction_counter: 1
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_arithmetic_and_logic_instructions() {
+        let mut func = Function::new(&[], Some(VariableType::Bool));
+        func.register_variables(&[VariableType::U32, VariableType::U32, VariableType::Bool, VariableType::Bool])
+            .unwrap();
+        func.set_instructions(vec![
+            Instruction::SetI(0, Value::U32(7)),
+            Instruction::SetI(1, Value::U32(3)),
+            Instruction::Mul(0, 1),                                // 21
+            Instruction::DivI(0, Value::U32(2)),                   // 10
+            Instruction::RemI(0, Value::U32(4)),                   // 2
+            Instruction::GreaterThanOrEqualI(2, 0, Value::U32(2)), // true
+            Instruction::LessThanOrEqual(3, 1, 0),                 // false
+            Instruction::Not(3),                                   // true
+            Instruction::And(2, 3),
+            Instruction::Return(2),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+
+        assert!(matches!(
+            interpreter.run_for(100),
+            ExecutionStatus::Finished(Some(Value::Bool(true)))
+        ));
+    }
+    #[test]
+    fn test_string_concatenation_and_equality() {
+        let mut func = Function::new(&[], Some(VariableType::Bool));
+        func.register_variables(&[VariableType::String, VariableType::String, VariableType::Bool])
+            .unwrap();
+        func.set_instructions(vec![
+            Instruction::SetI(0, Value::String("foo".to_string())),
+            Instruction::SetI(1, Value::String("bar".to_string())),
+            Instruction::Add(0, 1),
+            Instruction::EqualsI(2, 0, Value::String("foobar".to_string())),
+            Instruction::Return(2),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[func])).unwrap();
+
+        assert!(matches!(
+            interpreter.run_for(100),
+            ExecutionStatus::Finished(Some(Value::Bool(true)))
+        ));
     }
     #[test]
     fn test_basic_loop() {
@@ -480,7 +2446,8 @@ mod test {
             Instruction::GotoIfTrue(1, 2),
         ];
         let mut func = Function::new(&[], None);
-        func.register_variables(&[VariableType::U64, VariableType::U64, VariableType::Bool]);
+        func.register_variables(&[VariableType::U64, VariableType::U64, VariableType::Bool])
+            .unwrap();
         func.set_instructions(instructions);
         run_function(func);
     }
@@ -492,9 +2459,875 @@ mod test {
             Instruction::CallNativeVoidFunction(0),
         ];
         let mut func = Function::new(&[], None);
-        func.register_variables(&[VariableType::U64, VariableType::U64, VariableType::Bool]);
+        func.register_variables(&[VariableType::U64, VariableType::U64, VariableType::Bool])
+            .unwrap();
         func.set_instructions(instructions);
 
         run_function(func);
     }
+    #[test]
+    fn test_void_call_passes_parameters() {
+        let mut main = Function::new(&[], None);
+        main.register_variable(VariableType::Array(Box::new(VariableType::U64))).unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![5]))),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallVoidFunction(1),
+        ]);
+        let mut first = Function::new(&[VariableType::Array(Box::new(VariableType::U64))], None);
+        first.register_variable(VariableType::U64).unwrap();
+        // fails on the default empty array if the parameter is not passed
+        first.set_instructions(vec![Instruction::GetArrayIndexI(0, 1, Value::U64(0))]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, first])).unwrap();
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(None)));
+    }
+    #[test]
+    fn test_struct_fields() {
+        let point = StructDefinition::new(&[VariableType::U64, VariableType::String]);
+        let line = StructDefinition::new(&[VariableType::Struct(0), VariableType::Struct(0)]);
+        let mut main = Function::new(&[], Some(VariableType::Bool));
+        main.register_variables(&[
+            VariableType::Struct(1),
+            VariableType::Struct(0),
+            VariableType::U64,
+            VariableType::Array(Box::new(VariableType::Struct(0))),
+            VariableType::Bool,
+            VariableType::Struct(0),
+            VariableType::Bool,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::GetField(0, 1, 1),                            // point = line.b
+            Instruction::SetFieldI(1, 0, Value::U64(7)),               // point.x = 7
+            Instruction::SetFieldI(1, 1, Value::String("end".into())), // point.label = "end"
+            Instruction::SetField(0, 1, 1),                            // line.b = point
+            Instruction::GetField(0, 1, 1),                            // point = line.b
+            Instruction::GetField(1, 2, 0),                            // x = point.x
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeVoidMethod(3, 0),          // points.push(point)
+            Instruction::GetArrayIndexI(3, 1, Value::U64(0)), // point = points[0]
+            Instruction::GetField(0, 5, 0),                   // other = line.a
+            Instruction::NotEquals(6, 1, 5),
+            Instruction::EqualsI(4, 2, Value::U64(7)),
+            Instruction::And(4, 6),
+            Instruction::Return(4),
+        ]);
+        let program = Program::with_structs(&[main], &[point, line]).unwrap();
+        let mut interpreter = Interpreter::new(program).unwrap();
+        assert!(matches!(
+            interpreter.run_for(100),
+            ExecutionStatus::Finished(Some(Value::Bool(true)))
+        ));
+
+        let mut wrong_type = Function::new(&[], None);
+        wrong_type.register_variable(VariableType::Struct(0)).unwrap();
+        wrong_type.set_instructions(vec![Instruction::SetFieldI(0, 0, Value::Bool(true))]);
+        let program = Program::with_structs(&[wrong_type], &[StructDefinition::new(&[VariableType::U64])]).unwrap();
+        let mut interpreter = Interpreter::new(program).unwrap();
+        assert!(matches!(
+            interpreter.execute().unwrap_err().kind,
+            InterpreterError::StructFieldTypeMismatch(VariableType::U64, VariableType::Bool)
+        ));
+    }
+    #[test]
+    fn test_struct_definitions_checked() {
+        let holds_itself = StructDefinition::new(&[VariableType::Struct(1)]);
+        let holds_first = StructDefinition::new(&[VariableType::U8, VariableType::Struct(0)]);
+        assert!(matches!(
+            Program::with_structs(&[], &[holds_itself, holds_first]),
+            Err(InterpreterError::StructContainsItself(0))
+        ));
+        // through an array the chain ends, since arrays start empty
+        let holds_array = StructDefinition::new(&[VariableType::Array(Box::new(VariableType::Struct(0)))]);
+        assert!(Program::with_structs(&[], &[holds_array]).is_ok());
+
+        let mut main = Function::new(&[], None);
+        main.register_variable(VariableType::Struct(3)).unwrap();
+        assert!(matches!(
+            Program::with_structs(&[main.clone()], &[StructDefinition::new(&[VariableType::U8])]),
+            Err(InterpreterError::StructDoesNotExist(3))
+        ));
+        assert!(matches!(
+            Interpreter::new(Program::new(&[main])),
+            Err(InterpreterError::StructDoesNotExist(3))
+        ));
+
+        let mut main = Function::new(&[], None);
+        main.register_variable(VariableType::Struct(0)).unwrap();
+        main.set_instructions(vec![Instruction::SetI(0, Value::Struct(StructValue::new(0, vec![Value::U64(1)])))]);
+        assert!(Program::with_structs(&[main], &[StructDefinition::new(&[VariableType::U64])]).is_ok());
+        let mut main = Function::new(&[], None);
+        main.set_instructions(vec![Instruction::SetI(0, Value::Struct(StructValue::new(0, vec![Value::U8(1)])))]);
+        assert!(matches!(
+            Program::with_structs(&[main], &[StructDefinition::new(&[VariableType::U64])]),
+            Err(InterpreterError::StructValueMalformed(0))
+        ));
+        // a closure's captures are not part of its type, which may name no struct at all
+        let closure = |parameters: &[VariableType]| {
+            let captures = vec![Value::Struct(StructValue::new(0, vec![Value::U8(1)]))];
+            let mut main = Function::new(&[], None);
+            main.set_instructions(vec![Instruction::SetI(
+                0,
+                Value::Function(FunctionValue::closure(Signature::new(parameters, None), 0, captures)),
+            )]);
+            Program::with_structs(&[main], &[StructDefinition::new(&[VariableType::U64])])
+        };
+        assert!(matches!(
+            closure(&[VariableType::U64]),
+            Err(InterpreterError::ValueMalformed(VariableType::Function(_)))
+        ));
+        let reference = VariableType::Ref(Box::new(VariableType::Struct(0)));
+        assert!(matches!(
+            closure(&[VariableType::U64, reference]),
+            Err(InterpreterError::StructValueMalformed(0))
+        ));
+    }
+    #[test]
+    fn test_map_methods() {
+        let string_to_u64 = VariableType::Map(Box::new(VariableType::String), Box::new(VariableType::U64));
+        let result = StructDefinition::new(&[
+            string_to_u64.clone(),
+            VariableType::Array(Box::new(VariableType::String)),
+            VariableType::Array(Box::new(VariableType::U64)),
+            VariableType::U64,
+            VariableType::Bool,
+            VariableType::U64,
+            VariableType::U64,
+        ]);
+        let mut main = Function::new(&[], Some(VariableType::Struct(0)));
+        main.register_variables(&[
+            string_to_u64.clone(),
+            VariableType::String,
+            VariableType::U64,
+            VariableType::Bool,
+            VariableType::U64,
+            VariableType::Array(Box::new(VariableType::String)),
+            VariableType::Array(Box::new(VariableType::U64)),
+            VariableType::Struct(0),
+            VariableType::U64,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(1, Value::String("b".into())),
+            Instruction::SetI(2, Value::U64(2)),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallNativeVoidMethod(0, native::MAP_INSERT), // map["b"] = 2
+            Instruction::SetI(1, Value::String("a".into())),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallNativeVoidMethod(0, native::MAP_INSERT), // map["a"] = 2
+            Instruction::SetI(2, Value::U64(10)),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallNativeVoidMethod(0, native::MAP_INSERT), // map["a"] = 10
+            Instruction::CallNativeMethod(0, 5, native::MAP_KEYS),
+            Instruction::CallNativeMethod(0, 6, native::MAP_VALUES),
+            Instruction::SetI(1, Value::String("c".into())),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeVoidMethod(0, native::MAP_REMOVE), // missing keys are ignored
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeMethod(0, 3, native::MAP_CONTAINS),
+            Instruction::SetI(1, Value::String("b".into())),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeMethod(0, 2, native::MAP_REMOVE),
+            Instruction::CallNativeMethod(0, 4, native::LEN),
+            Instruction::SetI(1, Value::String("a".into())),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallNativeMethod(0, 8, native::MAP_GET),
+            Instruction::SetField(7, 0, 0),
+            Instruction::SetField(7, 1, 5),
+            Instruction::SetField(7, 2, 6),
+            Instruction::SetField(7, 3, 4),
+            Instruction::SetField(7, 4, 3),
+            Instruction::SetField(7, 5, 2),
+            Instruction::SetField(7, 6, 8),
+            Instruction::Return(7),
+        ]);
+        let program = Program::with_structs(&[main], &[result]).unwrap();
+        let mut interpreter = Interpreter::new(program).unwrap();
+        let ExecutionStatus::Finished(Some(Value::Struct(result))) = interpreter.run_for(100) else {
+            panic!("main did not return its results");
+        };
+        let mut expected_map = MapValue::new(VariableType::String, VariableType::U64);
+        expected_map.insert(Value::String("a".into()), Value::U64(10)).unwrap();
+        assert_eq!(
+            result.fields(),
+            &[
+                Value::Map(expected_map),
+                Value::Array(ArrayValue::StringArray(vec!["a".into(), "b".into()])),
+                Value::Array(ArrayValue::U64Array(vec![10, 2])),
+                Value::U64(1),
+                Value::Bool(false),
+                Value::U64(2),
+                Value::U64(10),
+            ]
+        );
+
+        let run = |key: Value, method_id: FunctionIdType| {
+            let mut main = Function::new(&[], None);
+            main.register_variables(&[string_to_u64.clone(), key.get_type(), VariableType::U64])
+                .unwrap();
+            main.set_instructions(vec![
+                Instruction::SetI(1, key),
+                Instruction::PushFunctionParameter(1),
+                Instruction::CallNativeMethod(0, 2, method_id),
+            ]);
+            Interpreter::new(Program::new(&[main])).unwrap().execute().unwrap_err().kind
+        };
+        assert!(matches!(
+            run(Value::String("a".into()), native::MAP_GET),
+            InterpreterError::MapKeyNotFound
+        ));
+        assert!(matches!(
+            run(Value::U8(1), native::MAP_GET),
+            InterpreterError::MapKeyTypeMismatch(VariableType::String, VariableType::U8)
+        ));
+        assert!(matches!(run(Value::U8(1), 900), InterpreterError::NativeFunctionDoesNotExist(900)));
+
+        let mut main = Function::new(&[], None);
+        main.register_variable(VariableType::Map(
+            Box::new(VariableType::Array(Box::new(VariableType::U8))),
+            Box::new(VariableType::U8),
+        ))
+        .unwrap();
+        assert!(matches!(
+            Interpreter::new(Program::new(&[main])),
+            Err(InterpreterError::MapKeyNotHashable(VariableType::Array(_)))
+        ));
+    }
+    #[test]
+    fn test_options() {
+        let option_u64 = VariableType::Option(Box::new(VariableType::U64));
+        // returns the index of the value in the array, if it is there
+        let mut find = Function::new(
+            &[VariableType::Array(Box::new(VariableType::U64)), VariableType::U64],
+            Some(option_u64.clone()),
+        );
+        find.register_variables(&[
+            VariableType::U64,
+            VariableType::U64,
+            VariableType::Bool,
+            VariableType::U64,
+            option_u64.clone(),
+        ])
+        .unwrap();
+        find.set_instructions(vec![
+            Instruction::CallNativeMethod(0, 3, native::LEN),
+            Instruction::GreaterThanOrEqual(4, 2, 3),
+            Instruction::GotoIfTrue(9, 4),
+            Instruction::GetArrayIndex(0, 5, 2),
+            Instruction::Equals(4, 5, 1),
+            Instruction::GotoIfTrue(8, 4),
+            Instruction::AddI(2, Value::U64(1)),
+            Instruction::Goto(1),
+            Instruction::WrapSome(6, 2),
+            Instruction::Return(6),
+        ]);
+        let mut main = Function::new(&[], Some(VariableType::Array(Box::new(option_u64.clone()))));
+        main.register_variables(&[
+            VariableType::Array(Box::new(VariableType::U64)),
+            VariableType::U64,
+            option_u64.clone(),
+            option_u64.clone(),
+            VariableType::Bool,
+            VariableType::Array(Box::new(option_u64.clone())),
+            VariableType::U64,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![3, 5, 7]))),
+            Instruction::SetI(1, Value::U64(7)),
+            Instruction::PushFunctionParameter(0),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallFunction(1, 2), // found = find(values, 7)
+            Instruction::SetI(1, Value::U64(4)),
+            Instruction::PushFunctionParameter(0),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallFunction(1, 3), // missing = find(values, 4)
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallNativeVoidMethod(5, native::ARRAY_PUSH),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallNativeVoidMethod(5, native::ARRAY_PUSH),
+            Instruction::IsSome(3, 4),
+            Instruction::GotoIfTrue(20, 4),
+            Instruction::Unwrap(2, 6),
+            Instruction::AddI(6, Value::U64(10)),
+            Instruction::WrapSome(3, 6),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallNativeVoidMethod(5, native::ARRAY_PUSH),
+            Instruction::Return(5),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, find])).unwrap();
+        let ExecutionStatus::Finished(Some(Value::Array(ArrayValue::OptionArray(VariableType::U64, results)))) = interpreter.run_for(1000)
+        else {
+            panic!("main did not return an array of options");
+        };
+        let results: Vec<Option<&Value>> = results.iter().map(OptionValue::value).collect();
+        assert_eq!(results, vec![Some(&Value::U64(2)), None, Some(&Value::U64(12))]);
+
+        let interpreter = |instructions: Vec<Instruction>| {
+            let mut main = Function::new(&[], Some(VariableType::Option(Box::new(VariableType::String))));
+            main.register_variables(&[
+                VariableType::Option(Box::new(VariableType::String)),
+                VariableType::String,
+                VariableType::Map(Box::new(VariableType::U8), Box::new(VariableType::String)),
+                VariableType::U8,
+            ])
+            .unwrap();
+            main.set_instructions(instructions);
+            Interpreter::new(Program::new(&[main])).unwrap()
+        };
+        assert!(matches!(
+            interpreter(vec![Instruction::Unwrap(0, 1)]).execute().unwrap_err().kind,
+            InterpreterError::UnwrapNone
+        ));
+        assert!(matches!(
+            interpreter(vec![Instruction::WrapSome(0, 3)]).execute().unwrap_err().kind,
+            InterpreterError::OptionValueTypeMismatch(VariableType::String, VariableType::U8)
+        ));
+        let mut try_get = interpreter(vec![
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallNativeMethod(2, 0, native::MAP_TRY_GET),
+            Instruction::Return(0),
+        ]);
+        assert!(matches!(
+            try_get.run_for(100),
+            ExecutionStatus::Finished(Some(Value::Option(option))) if option == OptionValue::none(VariableType::String)
+        ));
+    }
+    #[test]
+    fn test_enums() {
+        let rect = StructDefinition::new(&[VariableType::U64, VariableType::U64]);
+        let shape = EnumDefinition::new(&[Some(VariableType::U64), Some(VariableType::Struct(0)), None]);
+        let mut area = Function::new(&[VariableType::Enum(0)], Some(VariableType::U64));
+        area.register_variables(&[
+            VariableType::U64,
+            VariableType::Struct(0),
+            VariableType::U64,
+            VariableType::U64,
+            VariableType::U64,
+        ])
+        .unwrap();
+        area.set_instructions(vec![
+            Instruction::Switch(0, vec![1, 6, 11]),
+            Instruction::GetPayload(0, 1, 0), // circle
+            Instruction::Set(3, 1),
+            Instruction::Mul(1, 3),
+            Instruction::MulI(1, Value::U64(3)),
+            Instruction::Return(1),
+            Instruction::GetPayload(0, 2, 1), // rect
+            Instruction::GetField(2, 3, 0),
+            Instruction::GetField(2, 4, 1),
+            Instruction::Mul(3, 4),
+            Instruction::Return(3),
+            Instruction::Return(5), // empty
+        ]);
+        let mut main = Function::new(&[], Some(VariableType::U64));
+        main.register_variables(&[
+            VariableType::Enum(0),
+            VariableType::U64,
+            VariableType::Struct(0),
+            VariableType::U64,
+            VariableType::U64,
+            VariableType::U16,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(4, Value::U64(2)),
+            Instruction::SetVariant(0, 0, 4),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 3),
+            Instruction::Add(1, 3),
+            Instruction::SetFieldI(2, 0, Value::U64(4)),
+            Instruction::SetFieldI(2, 1, Value::U64(5)),
+            Instruction::SetVariant(0, 1, 2),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 3),
+            Instruction::Add(1, 3),
+            Instruction::SetUnitVariant(0, 2),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 3),
+            Instruction::Add(1, 3),
+            Instruction::GetTag(0, 5),
+            Instruction::Switch(5, vec![19]), // past the table, continues
+            Instruction::Switch(5, vec![19, 19, 18]),
+            Instruction::Return(1),
+            Instruction::Return(4),
+        ]);
+        let program = Program::with_types(&[main, area], &[rect], std::slice::from_ref(&shape)).unwrap();
+        let mut interpreter = Interpreter::new(program).unwrap();
+        assert!(matches!(interpreter.run_for(1000), ExecutionStatus::Finished(Some(Value::U64(32)))));
+
+        let error = |instruction: Instruction| {
+            let mut main = Function::new(&[], None);
+            main.register_variables(&[VariableType::Enum(0), VariableType::Bool]).unwrap();
+            main.set_instructions(vec![instruction]);
+            let rect = StructDefinition::new(&[VariableType::U64, VariableType::U64]);
+            let program = Program::with_types(&[main], &[rect], std::slice::from_ref(&shape)).unwrap();
+            Interpreter::new(program).unwrap().execute().unwrap_err().kind
+        };
+        assert!(matches!(
+            error(Instruction::GetPayload(0, 1, 1)),
+            InterpreterError::EnumTagMismatch(1, 0)
+        ));
+        assert!(matches!(
+            error(Instruction::SetVariant(0, 0, 1)),
+            InterpreterError::EnumPayloadTypeMismatch(VariableType::U64, VariableType::Bool)
+        ));
+        assert!(matches!(
+            error(Instruction::SetVariant(0, 2, 1)),
+            InterpreterError::EnumVariantHasNoPayload(0, 2)
+        ));
+        assert!(matches!(
+            error(Instruction::SetUnitVariant(0, 3)),
+            InterpreterError::EnumVariantDoesNotExist(0, 3)
+        ));
+        assert!(matches!(
+            error(Instruction::Switch(1, vec![])),
+            InterpreterError::SwitchOnUnsupportedValue(VariableType::Bool)
+        ));
+
+        // a default value must not hold itself, later variants are only built from existing values
+        let list = EnumDefinition::new(&[None, Some(VariableType::Struct(0))]);
+        let node = StructDefinition::new(&[VariableType::U64, VariableType::Enum(0)]);
+        assert!(Program::with_types(&[], std::slice::from_ref(&node), &[list]).is_ok());
+        let list = EnumDefinition::new(&[Some(VariableType::Struct(0))]);
+        assert!(matches!(
+            Program::with_types(&[], &[node], &[list]),
+            Err(InterpreterError::StructContainsItself(0))
+        ));
+        assert!(matches!(
+            Program::with_types(&[], &[], &[EnumDefinition::new(&[])]),
+            Err(InterpreterError::EnumHasNoVariants(0))
+        ));
+    }
+    #[test]
+    fn test_enum_equality() {
+        let shape = EnumDefinition::new(&[Some(VariableType::U64), None, None]);
+        let unit = EnumDefinition::new(&[None]);
+        let run = |instructions: Vec<Instruction>| {
+            let mut main = Function::new(&[], Some(VariableType::Bool));
+            main.register_variables(&[
+                VariableType::Enum(0),
+                VariableType::Enum(0),
+                VariableType::Bool,
+                VariableType::Bool,
+                VariableType::Enum(1),
+                VariableType::U64,
+            ])
+            .unwrap();
+            main.set_instructions(instructions);
+            let program = Program::with_types(&[main], &[], &[shape.clone(), unit.clone()]).unwrap();
+            Interpreter::new(program).unwrap().run_for(100)
+        };
+        let same_payload = run(vec![
+            Instruction::SetI(5, Value::U64(2)),
+            Instruction::SetVariant(0, 0, 5),
+            Instruction::SetVariant(1, 0, 5),
+            Instruction::Equals(2, 0, 1),
+            Instruction::SetUnitVariant(1, 1),
+            Instruction::NotEquals(3, 0, 1),
+            Instruction::And(2, 3),
+            Instruction::Return(2),
+        ]);
+        assert!(matches!(same_payload, ExecutionStatus::Finished(Some(Value::Bool(true)))));
+        let other_payload = run(vec![
+            Instruction::SetI(5, Value::U64(2)),
+            Instruction::SetVariant(0, 0, 5),
+            Instruction::AddI(5, Value::U64(1)),
+            Instruction::SetVariant(1, 0, 5),
+            Instruction::Equals(2, 0, 1),
+            Instruction::Return(2),
+        ]);
+        assert!(matches!(other_payload, ExecutionStatus::Finished(Some(Value::Bool(false)))));
+        assert!(matches!(
+            run(vec![Instruction::Equals(2, 0, 4)]),
+            ExecutionStatus::Error(RuntimeError {
+                kind: InterpreterError::OperandsNotSameType,
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn test_chars_and_bytes() {
+        let run = |return_type: VariableType, instructions: Vec<Instruction>| {
+            let mut main = Function::new(&[], Some(return_type));
+            main.register_variables(&[
+                VariableType::String,
+                VariableType::Char,
+                VariableType::Bytes,
+                VariableType::U64,
+                VariableType::Array(Box::new(VariableType::Char)),
+                VariableType::U8,
+                VariableType::U64,
+            ])
+            .unwrap();
+            main.set_instructions(instructions);
+            Interpreter::new(Program::new(&[main])).unwrap().run_for(1000)
+        };
+        let status = run(
+            VariableType::Array(Box::new(VariableType::Char)),
+            vec![
+                Instruction::SetI(0, Value::String("hé".into())),
+                Instruction::SetI(1, Value::Char('!')),
+                Instruction::PushFunctionParameter(1),
+                Instruction::CallNativeVoidMethod(0, native::ARRAY_PUSH), // "hé!"
+                Instruction::CallNativeMethod(0, 2, native::TO_BYTES),
+                Instruction::SetI(5, Value::U8(b'?')),
+                Instruction::PushFunctionParameter(5),
+                Instruction::CallNativeVoidMethod(2, native::ARRAY_PUSH), // b"h\xc3\xa9!?"
+                Instruction::CallNativeMethod(2, 0, native::TO_STRING),
+                Instruction::SetI(3, Value::U64(1)),
+                Instruction::PushFunctionParameter(3),
+                Instruction::CallNativeMethod(0, 1, native::AT), // 'é'
+                Instruction::CallNativeMethod(0, 4, native::CHARS),
+                Instruction::PushFunctionParameter(1),
+                Instruction::CallNativeVoidMethod(4, native::ARRAY_PUSH),
+                Instruction::Return(4),
+            ],
+        );
+        assert!(matches!(
+            status,
+            ExecutionStatus::Finished(Some(Value::Array(ArrayValue::CharArray(chars)))) if chars == vec!['h', 'é', '!', '?', 'é']
+        ));
+
+        // strings count chars and bytes count bytes, so the two byte é counts once more
+        let status = run(
+            VariableType::U64,
+            vec![
+                Instruction::SetI(0, Value::String("hé".into())),
+                Instruction::CallNativeMethod(0, 2, native::TO_BYTES),
+                Instruction::CallNativeMethod(0, 3, native::LEN),
+                Instruction::CallNativeMethod(2, 6, native::LEN),
+                Instruction::Sub(6, 3),
+                Instruction::Return(6),
+            ],
+        );
+        assert!(matches!(status, ExecutionStatus::Finished(Some(Value::U64(1)))));
+
+        let status = run(
+            VariableType::String,
+            vec![
+                Instruction::SetI(2, Value::Bytes(vec![b'a', 0xff])),
+                Instruction::CallNativeMethod(2, 0, native::TO_STRING),
+            ],
+        );
+        assert!(matches!(status, ExecutionStatus::Error(error) if matches!(error.kind, InterpreterError::BytesNotUtf8)));
+        let status = run(
+            VariableType::String,
+            vec![
+                Instruction::SetI(3, Value::U64(2)),
+                Instruction::PushFunctionParameter(3),
+                Instruction::CallNativeMethod(0, 1, native::AT),
+            ],
+        );
+        assert!(matches!(status, ExecutionStatus::Error(error) if matches!(error.kind, InterpreterError::TextIndexBeyondBounds(2))));
+    }
+
+    #[test]
+    fn test_references() {
+        let u64_array = VariableType::Array(Box::new(VariableType::U64));
+        let ref_u64 = VariableType::Ref(Box::new(VariableType::U64));
+        // writes through references to the caller's variables
+        let mut update = Function::new(&[VariableType::Ref(Box::new(u64_array.clone())), ref_u64.clone()], None);
+        update.register_variables(&[VariableType::U64, VariableType::U64]).unwrap();
+        update.set_instructions(vec![
+            Instruction::SetI(2, Value::U64(1)),
+            Instruction::GetRefIndex(0, 3, 2),
+            Instruction::MulI(3, Value::U64(10)),
+            Instruction::SetRefIndex(0, 2, 3), // array[1] *= 10
+            Instruction::SetI(3, Value::U64(7)),
+            Instruction::StoreRef(1, 3), // *count = 7
+        ]);
+        let mut main = Function::new(&[], Some(u64_array.clone()));
+        main.register_variables(&[
+            u64_array.clone(),
+            VariableType::Ref(Box::new(u64_array.clone())),
+            VariableType::U64,
+            ref_u64.clone(),
+            ref_u64.clone(),
+            VariableType::U64,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![1, 2, 3]))),
+            Instruction::MakeRef(1, 0),
+            Instruction::MakeRef(3, 2),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallVoidFunction(1),
+            Instruction::AllocRef(4, 2), // a heap copy of count, unaffected by later writes to count
+            Instruction::SetI(2, Value::U64(0)),
+            Instruction::LoadRef(4, 5),
+            Instruction::PushFunctionParameter(5),
+            Instruction::CallNativeVoidMethod(0, native::ARRAY_PUSH),
+            Instruction::Return(0),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, update])).unwrap();
+        assert!(matches!(
+            interpreter.run_for(100),
+            ExecutionStatus::Finished(Some(Value::Array(ArrayValue::U64Array(values)))) if values == vec![1, 20, 3, 7]
+        ));
+
+        let escape = |instructions: Vec<Instruction>| {
+            let mut main = Function::new(&[], None);
+            main.register_variables(&[VariableType::Ref(Box::new(ref_u64.clone())), ref_u64.clone()])
+                .unwrap();
+            main.set_instructions(vec![
+                Instruction::MakeRef(0, 1),
+                Instruction::PushFunctionParameter(0),
+                Instruction::CallFunction(1, 1),
+            ]);
+            let mut callee = Function::new(&[VariableType::Ref(Box::new(ref_u64.clone()))], Some(ref_u64.clone()));
+            callee.register_variables(&[VariableType::U64, ref_u64.clone()]).unwrap();
+            callee.set_instructions(instructions);
+            let mut interpreter = Interpreter::new(Program::new(&[main, callee])).unwrap();
+            interpreter.execute().unwrap_err().kind
+        };
+        assert!(matches!(
+            escape(vec![Instruction::MakeRef(2, 1), Instruction::Return(2)]),
+            InterpreterError::ReferenceOutlivesFrame(1)
+        ));
+        assert!(matches!(
+            escape(vec![Instruction::MakeRef(2, 1), Instruction::StoreRef(0, 2)]),
+            InterpreterError::ReferenceOutlivesFrame(1)
+        ));
+        assert!(matches!(escape(vec![Instruction::LoadRef(2, 1)]), InterpreterError::NullReference));
+    }
+
+    #[test]
+    fn test_objects() {
+        // node { value: u64, next: object<node> }
+        let node = VariableType::Struct(0);
+        let object_node = VariableType::Object(Box::new(node.clone()));
+        let mut main = Function::new(&[], Some(VariableType::U64));
+        main.register_variables(&[
+            object_node.clone(),
+            object_node.clone(),
+            node.clone(),
+            VariableType::U64,
+            object_node.clone(),
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::NewObject(0, 2), // a = node { 0, null }
+            Instruction::SetField(2, 1, 0),
+            Instruction::SetI(3, Value::U64(5)),
+            Instruction::SetField(2, 0, 3),
+            Instruction::NewObject(1, 2), // b = node { 5, a }
+            Instruction::GetObject(0, 2),
+            Instruction::SetField(2, 1, 1),
+            Instruction::SetObject(0, 2), // a.next = b, a cycle
+            Instruction::GetObject(1, 2),
+            Instruction::GetField(2, 4, 1),
+            Instruction::GetObject(4, 2),
+            Instruction::SetI(3, Value::U64(9)),
+            Instruction::SetField(2, 0, 3),
+            Instruction::SetObject(4, 2), // b.next.value = 9, seen through a
+            Instruction::GetObject(0, 2),
+            Instruction::GetField(2, 3, 0),
+            Instruction::Yield,
+            Instruction::SetI(0, Value::Object(ObjectValue::null(node.clone()))),
+            Instruction::SetI(1, Value::Object(ObjectValue::null(node.clone()))),
+            Instruction::SetI(4, Value::Object(ObjectValue::null(node.clone()))),
+            Instruction::SetField(2, 1, 4),
+            Instruction::Yield,
+            Instruction::Return(3),
+        ]);
+        let definition = StructDefinition::new(&[VariableType::U64, object_node.clone()]);
+        let mut interpreter = Interpreter::new(Program::with_structs(&[main], &[definition]).unwrap()).unwrap();
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Yielded));
+        assert_eq!(interpreter.collect_garbage(), 0);
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Yielded));
+        // the cycle is unreachable once no variable holds a handle to it
+        assert_eq!(interpreter.collect_garbage(), 2);
+        let stats = interpreter.gc_stats();
+        assert_eq!((stats.collections, stats.objects_freed, stats.objects, stats.bytes), (2, 2, 0, 0));
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(Some(Value::U64(9)))));
+
+        // garbage is collected on its own as the heap grows
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[
+            VariableType::Object(Box::new(VariableType::U64)),
+            VariableType::U64,
+            VariableType::Bool,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::NewObject(0, 1),
+            Instruction::AddI(1, Value::U64(1)),
+            Instruction::LessThanI(2, 1, Value::U64(50)),
+            Instruction::GotoIfTrue(0, 2),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main])).unwrap();
+        interpreter.set_gc_threshold(0);
+        assert!(matches!(interpreter.run_for(1000), ExecutionStatus::Finished(None)));
+        let stats = interpreter.gc_stats();
+        assert!(stats.collections > 0);
+        assert_eq!(stats.objects_freed + stats.objects as u64, 50);
+    }
+
+    #[test]
+    fn test_function_values() {
+        let binary = Signature::new(&[VariableType::U64, VariableType::U64], Some(VariableType::U64));
+        let binary_type = VariableType::Function(Box::new(binary.clone()));
+        // apply(f, a, b) = f(a, b)
+        let mut apply = Function::new(
+            &[binary_type.clone(), VariableType::U64, VariableType::U64],
+            Some(VariableType::U64),
+        );
+        apply.register_variable(VariableType::U64).unwrap();
+        apply.set_instructions(vec![
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallIndirect(0, 3),
+            Instruction::Return(3),
+        ]);
+        let mut add = Function::new(&[VariableType::U64, VariableType::U64], Some(VariableType::U64));
+        add.set_instructions(vec![Instruction::Add(0, 1), Instruction::Return(0)]);
+        let mut mul = Function::new(&[VariableType::U64, VariableType::U64], Some(VariableType::U64));
+        mul.set_instructions(vec![Instruction::Mul(0, 1), Instruction::Return(0)]);
+        assert_eq!(add.signature(), binary);
+
+        let mut main = Function::new(&[], Some(VariableType::U64));
+        main.register_variables(&[
+            VariableType::Array(Box::new(binary_type.clone())),
+            binary_type.clone(),
+            VariableType::U64,
+            VariableType::U64,
+            VariableType::U64,
+            VariableType::U64,
+        ])
+        .unwrap();
+        let operations = vec![FunctionValue::new(binary.clone(), 2), FunctionValue::new(binary.clone(), 3)];
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::Array(ArrayValue::FunctionArray(binary.clone(), operations))),
+            Instruction::SetI(2, Value::U64(6)),
+            Instruction::SetI(3, Value::U64(7)),
+            Instruction::GetArrayIndexI(0, 1, Value::U64(0)),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallFunction(1, 4), // apply(add, 6, 7)
+            Instruction::Set(5, 4),
+            Instruction::GetArrayIndexI(0, 1, Value::U64(1)),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallFunction(1, 4), // apply(mul, 6, 7)
+            Instruction::Add(5, 4),
+            Instruction::Return(5),
+        ]);
+        let program = Program::new(&[main, apply.clone(), add.clone(), mul]);
+        let mut interpreter = Interpreter::new(program).unwrap();
+        assert!(matches!(interpreter.run_for(100), ExecutionStatus::Finished(Some(Value::U64(55)))));
+
+        let call = |function_value: FunctionValue| {
+            let mut main = Function::new(&[], None);
+            main.register_variables(&[binary_type.clone(), VariableType::U64]).unwrap();
+            main.set_instructions(vec![
+                Instruction::SetI(0, Value::Function(function_value)),
+                Instruction::PushFunctionParameter(0),
+                Instruction::PushFunctionParameter(1),
+                Instruction::PushFunctionParameter(1),
+                Instruction::CallFunction(1, 1),
+            ]);
+            let mut interpreter = Interpreter::new(Program::new(&[main, apply.clone(), add.clone()])).unwrap();
+            interpreter.execute().unwrap_err().kind
+        };
+        assert!(matches!(
+            call(FunctionValue::null(binary.clone())),
+            InterpreterError::NullFunctionCall
+        ));
+        // function 1 takes a function value first
+        assert!(matches!(
+            call(FunctionValue::new(binary.clone(), 1)),
+            InterpreterError::FunctionSignatureMismatch(1)
+        ));
+    }
+
+    #[test]
+    fn test_closures() {
+        let unary_type = VariableType::Function(Box::new(Signature::new(&[VariableType::U64], Some(VariableType::U64))));
+        let void_type = VariableType::Function(Box::new(Signature::new(&[], None)));
+        // add_base(x) = x + base, base captured by value
+        let mut add_base = Function::new(&[VariableType::U64], Some(VariableType::U64));
+        add_base.register_variable(VariableType::U64).unwrap();
+        add_base.set_instructions(vec![Instruction::Add(0, 1), Instruction::Return(0)]);
+        // increment() adds one to a counter captured by reference
+        let mut increment = Function::new(&[], None);
+        increment
+            .register_variables(&[VariableType::Ref(Box::new(VariableType::U64)), VariableType::U64])
+            .unwrap();
+        increment.set_instructions(vec![
+            Instruction::LoadRef(0, 1),
+            Instruction::AddI(1, Value::U64(1)),
+            Instruction::StoreRef(0, 1),
+        ]);
+        // make_counter() returns a closure over its own variable, which would outlive it
+        let mut make_counter = Function::new(&[], Some(void_type.clone()));
+        make_counter.register_variables(&[VariableType::U64, void_type.clone()]).unwrap();
+        make_counter.set_instructions(vec![Instruction::MakeClosure(1, 2, vec![Capture::Ref(0)]), Instruction::Return(1)]);
+        let functions = [add_base, increment, make_counter];
+
+        let run = |instructions: Vec<Instruction>| {
+            let mut main = Function::new(&[], Some(VariableType::U64));
+            main.register_variables(&[
+                unary_type.clone(),
+                VariableType::U64,
+                VariableType::U64,
+                VariableType::U64,
+                void_type.clone(),
+            ])
+            .unwrap();
+            main.set_instructions(instructions);
+            let mut program = vec![main];
+            program.extend(functions.iter().cloned());
+            let mut interpreter = Interpreter::new(Program::new(&program)).unwrap();
+            interpreter.run_for(100)
+        };
+        let status = run(vec![
+            Instruction::SetI(1, Value::U64(10)),
+            Instruction::MakeClosure(0, 1, vec![Capture::Value(1)]),
+            Instruction::SetI(1, Value::U64(20)), // not seen by the closure
+            Instruction::SetI(3, Value::U64(5)),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallIndirect(0, 2), // 5 + 10
+            Instruction::MakeClosure(4, 2, vec![Capture::Ref(1)]),
+            Instruction::CallVoidIndirect(4),
+            Instruction::CallVoidIndirect(4), // base is now 22
+            Instruction::Add(2, 1),
+            Instruction::Return(2),
+        ]);
+        assert!(matches!(status, ExecutionStatus::Finished(Some(Value::U64(37)))));
+
+        let status = run(vec![Instruction::MakeClosure(0, 1, vec![Capture::Ref(1)])]);
+        assert!(matches!(
+            status,
+            ExecutionStatus::Error(RuntimeError {
+                kind: InterpreterError::CaptureTypeMismatch(1, 0),
+                ..
+            })
+        ));
+        let status = run(vec![Instruction::CallFunction(3, 4)]);
+        assert!(matches!(
+            status,
+            ExecutionStatus::Error(RuntimeError {
+                kind: InterpreterError::ReferenceOutlivesFrame(1),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_register_variable_ids() {
+        let mut function = Function::new(&[VariableType::Bool], None);
+        assert_eq!(function.register_variable(VariableType::U64).unwrap(), 1);
+        assert_eq!(function.register_variable(VariableType::U8).unwrap(), 2);
+        let many = vec![VariableType::U8; VariableIdType::MAX as usize - 2];
+        function.register_variables(&many).unwrap();
+        assert!(function.register_variable(VariableType::U8).is_err());
+        assert_eq!(function.variables().len(), VariableIdType::MAX as usize + 1);
+    }
 }
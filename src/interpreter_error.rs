@@ -1,14 +1,18 @@
-use crate::value::{FunctionIdType, Value, VariableIdType, VariableType};
+use std::fmt;
+
+use crate::value::{EnumIdType, FieldIdType, FunctionIdType, StructIdType, Value, VariableIdType, VariableType, VariantIdType};
 
 #[derive(Debug, Clone)]
 pub enum InterpreterError {
     VariableDoesNotExist(VariableIdType),
     FunctionDoesNotExist(FunctionIdType),
     AttemptAssignedDifferentTypes(VariableType, VariableType),
+    TooManyVariables,
 
     OperandsNotSameType,
     OperandNotNumeric,
     OperatorDivideByZero,
+    OperandNotBoolean,
 
     // Function Calling/Callstack/Return/ControlFlow
     NoEntryFunction,
@@ -19,9 +23,40 @@ pub enum InterpreterError {
     FunctionCallParameterStackEmptyPop(FunctionIdType),
     FunctionCallParametersInvalid(FunctionIdType, bool),
     GotoNonBoolean,
+    NativeFunctionDoesNotExist(FunctionIdType),
+    SwitchOnUnsupportedValue(VariableType),
+    NullFunctionCall,
+    /// The function a function value names does not have the signature of the value's type.
+    FunctionSignatureMismatch(FunctionIdType),
+    CallOnNonFunctionValue(VariableType),
+    /// The function a closure is over, then the index of the capture its variables do not take.
+    CaptureTypeMismatch(FunctionIdType, usize),
+
+    // Resource limits
+    OutOfFuel,
+    StackOverflow {
+        depth: usize,
+        function_id: FunctionIdType,
+    },
+    FunctionParameterStackOverflow {
+        size: usize,
+        function_id: FunctionIdType,
+    },
+    OutOfMemory {
+        used: usize,
+        limit: usize,
+    },
 
     // Value related
     ValueIsNotNumeric(Value),
+    UnwrapNone,
+    OptionOperationOnNonOptionValue(VariableType),
+    OptionValueTypeMismatch(VariableType, VariableType),
+    BytesNotUtf8,
+    TextOperationOnNonTextValue(VariableType),
+    TextIndexBeyondBounds(usize),
+    /// A value of the type does not match the definitions it uses, without a struct or enum to name.
+    ValueMalformed(VariableType),
 
     // Array related
     ArraySetValueWithIncompatibleType(VariableType, VariableType),
@@ -29,4 +64,367 @@ pub enum InterpreterError {
     ArrayIndexBeyondBounds(usize),
     ArrayTypeIncompatibleWithPushValue(VariableType, VariableType),
     ArrayOperationOnNonArrayValue(VariableType),
+
+    // Struct related
+    StructDoesNotExist(StructIdType),
+    StructFieldDoesNotExist(StructIdType, FieldIdType),
+    StructFieldTypeMismatch(VariableType, VariableType),
+    StructOperationOnNonStructValue(VariableType),
+    StructContainsItself(StructIdType),
+    StructValueMalformed(StructIdType),
+
+    // Map related
+    MapKeyNotHashable(VariableType),
+    MapKeyTypeMismatch(VariableType, VariableType),
+    MapValueTypeMismatch(VariableType, VariableType),
+    MapKeyNotFound,
+    MapOperationOnNonMapValue(VariableType),
+
+    // Enum related
+    EnumDoesNotExist(EnumIdType),
+    EnumVariantDoesNotExist(EnumIdType, VariantIdType),
+    EnumPayloadTypeMismatch(VariableType, VariableType),
+    EnumVariantHasNoPayload(EnumIdType, VariantIdType),
+    EnumVariantNeedsPayload(EnumIdType, VariantIdType),
+    /// The expected tag, then the tag the value has.
+    EnumTagMismatch(VariantIdType, VariantIdType),
+    EnumOperationOnNonEnumValue(VariableType),
+    EnumContainsItself(EnumIdType),
+    EnumHasNoVariants(EnumIdType),
+    EnumValueMalformed(EnumIdType),
+
+    // Reference related
+    NullReference,
+    /// The frame variable or heap object a reference points to no longer exists.
+    DanglingReference,
+    /// A reference to the frame at this depth would outlive it.
+    ReferenceOutlivesFrame(usize),
+    /// The type a reference points to, then the type of the value.
+    ReferenceTypeMismatch(VariableType, VariableType),
+    RefOperationOnNonRefValue(VariableType),
+    HeapExhausted,
+    ObjectOperationOnNonObjectValue(VariableType),
+}
+
+impl InterpreterError {
+    /// A stable identifier for the kind of error, for tooling to match on. Codes are grouped by the hundred:
+    /// variables, operators, calls and control flow, resource limits, values, arrays, structs, maps, enums, then references and objects.
+    pub fn code(&self) -> &'static str {
+        match self {
+            InterpreterError::VariableDoesNotExist(_) => "E0001",
+            InterpreterError::FunctionDoesNotExist(_) => "E0002",
+            InterpreterError::AttemptAssignedDifferentTypes(_, _) => "E0003",
+            InterpreterError::TooManyVariables => "E0004",
+
+            InterpreterError::OperandsNotSameType => "E0100",
+            InterpreterError::OperandNotNumeric => "E0101",
+            InterpreterError::OperatorDivideByZero => "E0102",
+            InterpreterError::OperandNotBoolean => "E0103",
+
+            InterpreterError::NoEntryFunction => "E0200",
+            InterpreterError::NoReturnValue => "E0201",
+            InterpreterError::ExpectingReturnCallToVoidFunction(_) => "E0202",
+            InterpreterError::VoidCallToNonVoidFunction(_) => "E0203",
+            InterpreterError::CallstackReferencesUnknownFunction(_) => "E0204",
+            InterpreterError::FunctionCallParameterStackEmptyPop(_) => "E0205",
+            InterpreterError::FunctionCallParametersInvalid(_, _) => "E0206",
+            InterpreterError::GotoNonBoolean => "E0207",
+            InterpreterError::NativeFunctionDoesNotExist(_) => "E0208",
+            InterpreterError::SwitchOnUnsupportedValue(_) => "E0209",
+            InterpreterError::NullFunctionCall => "E0210",
+            InterpreterError::FunctionSignatureMismatch(_) => "E0211",
+            InterpreterError::CallOnNonFunctionValue(_) => "E0212",
+            InterpreterError::CaptureTypeMismatch(_, _) => "E0213",
+
+            InterpreterError::OutOfFuel => "E0300",
+            InterpreterError::StackOverflow { .. } => "E0301",
+            InterpreterError::FunctionParameterStackOverflow { .. } => "E0302",
+            InterpreterError::OutOfMemory { .. } => "E0303",
+
+            InterpreterError::ValueIsNotNumeric(_) => "E0400",
+            InterpreterError::UnwrapNone => "E0401",
+            InterpreterError::OptionOperationOnNonOptionValue(_) => "E0402",
+            InterpreterError::OptionValueTypeMismatch(_, _) => "E0403",
+            InterpreterError::BytesNotUtf8 => "E0404",
+            InterpreterError::TextOperationOnNonTextValue(_) => "E0405",
+            InterpreterError::TextIndexBeyondBounds(_) => "E0406",
+            InterpreterError::ValueMalformed(_) => "E0407",
+
+            InterpreterError::ArraySetValueWithIncompatibleType(_, _) => "E0500",
+            InterpreterError::ArrayIndexWithNonNumericType(_) => "E0501",
+            InterpreterError::ArrayIndexBeyondBounds(_) => "E0502",
+            InterpreterError::ArrayTypeIncompatibleWithPushValue(_, _) => "E0503",
+            InterpreterError::ArrayOperationOnNonArrayValue(_) => "E0504",
+
+            InterpreterError::StructDoesNotExist(_) => "E0600",
+            InterpreterError::StructFieldDoesNotExist(_, _) => "E0601",
+            InterpreterError::StructFieldTypeMismatch(_, _) => "E0602",
+            InterpreterError::StructOperationOnNonStructValue(_) => "E0603",
+            InterpreterError::StructContainsItself(_) => "E0604",
+            InterpreterError::StructValueMalformed(_) => "E0605",
+
+            InterpreterError::MapKeyNotHashable(_) => "E0700",
+            InterpreterError::MapKeyTypeMismatch(_, _) => "E0701",
+            InterpreterError::MapValueTypeMismatch(_, _) => "E0702",
+            InterpreterError::MapKeyNotFound => "E0703",
+            InterpreterError::MapOperationOnNonMapValue(_) => "E0704",
+
+            InterpreterError::EnumDoesNotExist(_) => "E0800",
+            InterpreterError::EnumVariantDoesNotExist(_, _) => "E0801",
+            InterpreterError::EnumPayloadTypeMismatch(_, _) => "E0802",
+            InterpreterError::EnumVariantHasNoPayload(_, _) => "E0803",
+            InterpreterError::EnumVariantNeedsPayload(_, _) => "E0804",
+            InterpreterError::EnumTagMismatch(_, _) => "E0805",
+            InterpreterError::EnumOperationOnNonEnumValue(_) => "E0806",
+            InterpreterError::EnumContainsItself(_) => "E0807",
+            InterpreterError::EnumHasNoVariants(_) => "E0808",
+            InterpreterError::EnumValueMalformed(_) => "E0809",
+            InterpreterError::NullReference => "E0900",
+            InterpreterError::DanglingReference => "E0901",
+            InterpreterError::ReferenceOutlivesFrame(_) => "E0902",
+            InterpreterError::ReferenceTypeMismatch(_, _) => "E0903",
+            InterpreterError::RefOperationOnNonRefValue(_) => "E0904",
+            InterpreterError::HeapExhausted => "E0905",
+            InterpreterError::ObjectOperationOnNonObjectValue(_) => "E0906",
+        }
+    }
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::VariableDoesNotExist(var_id) => write!(f, "variable {var_id} does not exist"),
+            InterpreterError::FunctionDoesNotExist(function_id) => write!(f, "function {function_id} does not exist"),
+            InterpreterError::AttemptAssignedDifferentTypes(expected, found) => {
+                write!(f, "cannot assign a value of type {found} to a variable of type {expected}")
+            }
+            InterpreterError::TooManyVariables => {
+                write!(f, "function has more than {} variables", VariableIdType::MAX as usize + 1)
+            }
+
+            InterpreterError::OperandsNotSameType => write!(f, "operands are not of the same type"),
+            InterpreterError::OperandNotNumeric => write!(f, "operand is not numeric"),
+            InterpreterError::OperatorDivideByZero => write!(f, "division by zero"),
+            InterpreterError::OperandNotBoolean => write!(f, "operand is not a boolean"),
+
+            InterpreterError::NoEntryFunction => write!(f, "program has no entry function"),
+            InterpreterError::NoReturnValue => write!(f, "called function finished without returning a value"),
+            InterpreterError::ExpectingReturnCallToVoidFunction(function_id) => {
+                write!(f, "expected a return value from function {function_id}, which returns nothing")
+            }
+            InterpreterError::VoidCallToNonVoidFunction(function_id) => {
+                write!(f, "function {function_id} returns a value and cannot be called as a void function")
+            }
+            InterpreterError::CallstackReferencesUnknownFunction(function_id) => {
+                write!(f, "callstack references unknown function {function_id}")
+            }
+            InterpreterError::FunctionCallParameterStackEmptyPop(function_id) => {
+                write!(f, "not enough parameters pushed to call function {function_id}")
+            }
+            InterpreterError::FunctionCallParametersInvalid(function_id, native) => match native {
+                true => write!(f, "invalid parameters for native function {function_id}"),
+                false => write!(f, "invalid parameters for function {function_id}"),
+            },
+            InterpreterError::GotoNonBoolean => write!(f, "conditional goto on a non-boolean variable"),
+            InterpreterError::NativeFunctionDoesNotExist(native_id) => write!(f, "native function {native_id} does not exist"),
+            InterpreterError::SwitchOnUnsupportedValue(var_type) => write!(f, "cannot switch on a value of type {var_type}"),
+            InterpreterError::NullFunctionCall => write!(f, "called a null function value"),
+            InterpreterError::FunctionSignatureMismatch(function_id) => {
+                write!(
+                    f,
+                    "function {function_id} does not have the signature of the function value naming it"
+                )
+            }
+            InterpreterError::CallOnNonFunctionValue(var_type) => write!(f, "cannot call a value of type {var_type}"),
+            InterpreterError::CaptureTypeMismatch(function_id, index) => {
+                write!(
+                    f,
+                    "capture {index} of a closure does not match the variable function {function_id} stores it in"
+                )
+            }
+
+            InterpreterError::OutOfFuel => write!(f, "out of fuel"),
+            InterpreterError::StackOverflow { depth, function_id } => {
+                write!(f, "stack overflow: call to function {function_id} would reach depth {depth}")
+            }
+            InterpreterError::FunctionParameterStackOverflow { size, function_id } => {
+                write!(f, "parameter stack overflow: function {function_id} pushed {size} parameters")
+            }
+            InterpreterError::OutOfMemory { used, limit } => write!(f, "out of memory: {used} bytes used, limit is {limit}"),
+
+            InterpreterError::ValueIsNotNumeric(value) => write!(f, "value of type {} is not numeric", value.get_type()),
+            InterpreterError::UnwrapNone => write!(f, "unwrapped an option holding no value"),
+            InterpreterError::OptionOperationOnNonOptionValue(var_type) => {
+                write!(f, "option operation on a value of type {var_type}")
+            }
+            InterpreterError::OptionValueTypeMismatch(inner_type, found) => {
+                write!(f, "cannot store a value of type {found} in an option<{inner_type}>")
+            }
+            InterpreterError::BytesNotUtf8 => write!(f, "bytes are not valid utf-8"),
+            InterpreterError::TextOperationOnNonTextValue(var_type) => {
+                write!(f, "string, char or bytes operation on a value of type {var_type}")
+            }
+            InterpreterError::TextIndexBeyondBounds(index) => write!(f, "index {index} is out of bounds of the string or bytes"),
+            InterpreterError::ValueMalformed(var_type) => write!(f, "value of type {var_type} does not match its definitions"),
+
+            InterpreterError::ArraySetValueWithIncompatibleType(array_type, value_type) => {
+                write!(f, "cannot store a value of type {value_type} in an {array_type}")
+            }
+            InterpreterError::ArrayIndexWithNonNumericType(value) => {
+                write!(f, "cannot index an array with a value of type {}", value.get_type())
+            }
+            InterpreterError::ArrayIndexBeyondBounds(index) => write!(f, "array index {index} is out of bounds"),
+            InterpreterError::ArrayTypeIncompatibleWithPushValue(array_type, value_type) => {
+                write!(f, "cannot push a value of type {value_type} to an {array_type}")
+            }
+            InterpreterError::ArrayOperationOnNonArrayValue(var_type) => {
+                write!(f, "array operation on a value of type {var_type}")
+            }
+
+            InterpreterError::StructDoesNotExist(struct_id) => write!(f, "struct {struct_id} does not exist"),
+            InterpreterError::StructFieldDoesNotExist(struct_id, field_id) => {
+                write!(f, "struct {struct_id} has no field {field_id}")
+            }
+            InterpreterError::StructFieldTypeMismatch(field_type, value_type) => {
+                write!(f, "cannot store a value of type {value_type} in a field of type {field_type}")
+            }
+            InterpreterError::StructOperationOnNonStructValue(var_type) => {
+                write!(f, "struct operation on a value of type {var_type}")
+            }
+            InterpreterError::StructContainsItself(struct_id) => {
+                write!(f, "struct {struct_id} contains itself and would have no end")
+            }
+            InterpreterError::StructValueMalformed(struct_id) => {
+                write!(f, "value of struct {struct_id} does not match its definition")
+            }
+
+            InterpreterError::MapKeyNotHashable(key_type) => write!(f, "a value of type {key_type} cannot be a map key"),
+            InterpreterError::MapKeyTypeMismatch(key_type, found) => {
+                write!(f, "cannot use a key of type {found} in a map with {key_type} keys")
+            }
+            InterpreterError::MapValueTypeMismatch(value_type, found) => {
+                write!(f, "cannot store a value of type {found} in a map with {value_type} values")
+            }
+            InterpreterError::MapKeyNotFound => write!(f, "key is not in the map"),
+            InterpreterError::MapOperationOnNonMapValue(var_type) => write!(f, "map operation on a value of type {var_type}"),
+
+            InterpreterError::EnumDoesNotExist(enum_id) => write!(f, "enum {enum_id} does not exist"),
+            InterpreterError::EnumVariantDoesNotExist(enum_id, tag) => write!(f, "enum {enum_id} has no variant {tag}"),
+            InterpreterError::EnumPayloadTypeMismatch(payload_type, found) => {
+                write!(f, "cannot use a value of type {found} as a payload of type {payload_type}")
+            }
+            InterpreterError::EnumVariantHasNoPayload(enum_id, tag) => {
+                write!(f, "variant {tag} of enum {enum_id} carries no payload")
+            }
+            InterpreterError::EnumVariantNeedsPayload(enum_id, tag) => {
+                write!(f, "variant {tag} of enum {enum_id} needs a payload")
+            }
+            InterpreterError::EnumTagMismatch(expected, found) => write!(f, "expected variant {expected}, found variant {found}"),
+            InterpreterError::EnumOperationOnNonEnumValue(var_type) => write!(f, "enum operation on a value of type {var_type}"),
+            InterpreterError::EnumContainsItself(enum_id) => {
+                write!(f, "enum {enum_id} contains itself in its default value and would have no end")
+            }
+            InterpreterError::EnumHasNoVariants(enum_id) => write!(f, "enum {enum_id} has no variants"),
+            InterpreterError::EnumValueMalformed(enum_id) => {
+                write!(f, "value of enum {enum_id} does not match its definition")
+            }
+
+            InterpreterError::NullReference => write!(f, "used a null reference"),
+            InterpreterError::DanglingReference => write!(f, "reference points to a variable or object that no longer exists"),
+            InterpreterError::ReferenceOutlivesFrame(frame) => {
+                write!(f, "reference to a variable of the frame at depth {frame} would outlive it")
+            }
+            InterpreterError::ReferenceTypeMismatch(inner_type, found) => {
+                write!(f, "cannot refer to a value of type {found} with a ref<{inner_type}>")
+            }
+            InterpreterError::RefOperationOnNonRefValue(var_type) => write!(f, "reference operation on a value of type {var_type}"),
+            InterpreterError::HeapExhausted => write!(f, "no heap object ids left"),
+            InterpreterError::ObjectOperationOnNonObjectValue(var_type) => write!(f, "object operation on a value of type {var_type}"),
+        }
+    }
+}
+
+impl std::error::Error for InterpreterError {}
+
+/// A function on the callstack when an error occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function_id: FunctionIdType,
+    pub name: Option<String>,
+    /// The instruction that failed in the innermost frame, or the call being waited on in its callers.
+    pub instruction_counter: usize,
+}
+
+/// An error raised while executing, with the callstack at the point of failure, innermost frame first.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: InterpreterError,
+    pub trace: Vec<Frame>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.kind.code(), self.kind)?;
+        for frame in self.trace.iter() {
+            match &frame.name {
+                Some(name) => write!(
+                    f,
+                    "\n    at {name} (function {}) instruction {}",
+                    frame.function_id, frame.instruction_counter
+                )?,
+                None => write!(
+                    f,
+                    "\n    at function {} instruction {}",
+                    frame.function_id, frame.instruction_counter
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter_error::*;
+
+    #[test]
+    fn test_display_renders_types() {
+        let error = InterpreterError::AttemptAssignedDifferentTypes(VariableType::Array(Box::new(VariableType::U8)), VariableType::Bool);
+        assert_eq!(
+            error.to_string(),
+            "cannot assign a value of type bool to a variable of type array<u8>"
+        );
+        assert_eq!(error.code(), "E0003");
+    }
+
+    #[test]
+    fn test_runtime_error_display() {
+        let error = RuntimeError {
+            kind: InterpreterError::ArrayIndexBeyondBounds(5),
+            trace: vec![
+                Frame {
+                    function_id: 1,
+                    name: None,
+                    instruction_counter: 4,
+                },
+                Frame {
+                    function_id: 0,
+                    name: Some("main".to_string()),
+                    instruction_counter: 2,
+                },
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            "error[E0502]: array index 5 is out of bounds\n    at function 1 instruction 4\n    at main (function 0) instruction 2"
+        );
+        let boxed: Box<dyn std::error::Error> = Box::new(error);
+        assert!(boxed.source().is_some());
+    }
 }
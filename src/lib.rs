@@ -1,5 +1,15 @@
+pub mod builder;
+pub mod compiler;
+pub mod debugger;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+pub mod heap;
 pub mod instructions;
 pub mod interpreter;
 pub mod interpreter_error;
+pub mod native;
 mod operations;
+pub mod profiler;
+pub mod snapshot;
+pub mod tracer;
 pub mod value;
@@ -0,0 +1,36 @@
+//! Ids of the native functions and methods provided by the interpreter.
+//!
+//! Native functions are called with `CallNativeVoidFunction`. Methods act on the variable named by
+//! `CallNativeVoidMethod` or `CallNativeMethod` and take their arguments from the parameter stack, pushed in order.
+
+use crate::value::FunctionIdType;
+
+/// `println(value)` prints a value.
+pub const PRINTLN: FunctionIdType = 0;
+
+/// `array.push(value)`. Also appends a char to a string or a u8 to bytes.
+pub const ARRAY_PUSH: FunctionIdType = 0;
+/// `array.len()`, `map.len()` or `bytes.len()`, as a u64. The length of a string counts its chars.
+pub const LEN: FunctionIdType = 1;
+/// `map.insert(key, value)`, replacing any previous value.
+pub const MAP_INSERT: FunctionIdType = 2;
+/// `map.get(key)`, failing if the key is missing.
+pub const MAP_GET: FunctionIdType = 3;
+/// `map.remove(key)`. As a void method a missing key is ignored, otherwise the removed value is returned.
+pub const MAP_REMOVE: FunctionIdType = 4;
+/// `map.contains(key)`, as a bool.
+pub const MAP_CONTAINS: FunctionIdType = 5;
+/// `map.keys()`, an array of the keys in order.
+pub const MAP_KEYS: FunctionIdType = 6;
+/// `map.values()`, an array of the values in key order.
+pub const MAP_VALUES: FunctionIdType = 7;
+/// `map.get(key)` as an option, none if the key is missing.
+pub const MAP_TRY_GET: FunctionIdType = 8;
+/// `value.to_string()` of a char, string or bytes. Bytes must be valid utf-8.
+pub const TO_STRING: FunctionIdType = 9;
+/// `value.to_bytes()`, the utf-8 encoding of a char or string, or a copy of bytes.
+pub const TO_BYTES: FunctionIdType = 10;
+/// `string.chars()`, an array of the string's chars in order.
+pub const CHARS: FunctionIdType = 11;
+/// `string.at(index)` as the char at that position, or `bytes.at(index)` as a u8.
+pub const AT: FunctionIdType = 12;
@@ -1,17 +1,24 @@
+use std::cmp::Ordering;
+
 use num_traits::Num;
 
 use crate::{interpreter_error::InterpreterError, value::Value};
 
 pub fn op_add(left: Value, right: Value) -> Result<Value, InterpreterError> {
-    if !(left.is_number() && right.is_number()) {
-        return Err(InterpreterError::OperandNotNumeric);
-    }
     Ok(match (left, right) {
         (Value::U8(lvalue), Value::U8(rvalue)) => Value::U8(lvalue.wrapping_add(rvalue)),
         (Value::U16(lvalue), Value::U16(rvalue)) => Value::U16(lvalue.wrapping_add(rvalue)),
         (Value::U32(lvalue), Value::U32(rvalue)) => Value::U32(lvalue.wrapping_add(rvalue)),
         (Value::U64(lvalue), Value::U64(rvalue)) => Value::U64(lvalue.wrapping_add(rvalue)),
-        (Value::String(_), Value::String(_)) => unimplemented!(),
+        (Value::String(mut lvalue), Value::String(rvalue)) => {
+            lvalue.push_str(&rvalue);
+            Value::String(lvalue)
+        }
+        (Value::Bytes(mut lvalue), Value::Bytes(rvalue)) => {
+            lvalue.extend_from_slice(&rvalue);
+            Value::Bytes(lvalue)
+        }
+        (lvalue, rvalue) if !(lvalue.is_number() && rvalue.is_number()) => return Err(InterpreterError::OperandNotNumeric),
         _ => return Err(InterpreterError::OperandsNotSameType),
     })
 }
@@ -27,6 +34,38 @@ pub fn op_sub(left: Value, right: Value) -> Result<Value, InterpreterError> {
         _ => return Err(InterpreterError::OperandsNotSameType),
     })
 }
+pub fn op_mul(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    if !(left.is_number() && right.is_number()) {
+        return Err(InterpreterError::OperandNotNumeric);
+    }
+    Ok(match (left, right) {
+        (Value::U8(lvalue), Value::U8(rvalue)) => Value::U8(lvalue.wrapping_mul(rvalue)),
+        (Value::U16(lvalue), Value::U16(rvalue)) => Value::U16(lvalue.wrapping_mul(rvalue)),
+        (Value::U32(lvalue), Value::U32(rvalue)) => Value::U32(lvalue.wrapping_mul(rvalue)),
+        (Value::U64(lvalue), Value::U64(rvalue)) => Value::U64(lvalue.wrapping_mul(rvalue)),
+        _ => return Err(InterpreterError::OperandsNotSameType),
+    })
+}
+#[inline]
+fn internal_div<T: Num>(l: T, r: T) -> Result<T, InterpreterError> {
+    if r.is_zero() {
+        return Err(InterpreterError::OperatorDivideByZero);
+    };
+    Ok(l / r)
+}
+
+pub fn op_div(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    if !(left.is_number() && right.is_number()) {
+        return Err(InterpreterError::OperandNotNumeric);
+    }
+    Ok(match (left, right) {
+        (Value::U8(lvalue), Value::U8(rvalue)) => Value::U8(internal_div(lvalue, rvalue)?),
+        (Value::U16(lvalue), Value::U16(rvalue)) => Value::U16(internal_div(lvalue, rvalue)?),
+        (Value::U32(lvalue), Value::U32(rvalue)) => Value::U32(internal_div(lvalue, rvalue)?),
+        (Value::U64(lvalue), Value::U64(rvalue)) => Value::U64(internal_div(lvalue, rvalue)?),
+        _ => return Err(InterpreterError::OperandsNotSameType),
+    })
+}
 #[inline]
 fn internal_rem<T: Num>(l: T, r: T) -> Result<T, InterpreterError> {
     if r.is_zero() {
@@ -48,18 +87,31 @@ pub fn op_rem(left: Value, right: Value) -> Result<Value, InterpreterError> {
     })
 }
 
-pub fn op_less_than(left: Value, right: Value) -> Result<Value, InterpreterError> {
-    if !(left.is_number() && right.is_number()) {
-        return Err(InterpreterError::OperandNotNumeric);
-    }
+// Chars compare by code point and byte strings lexicographically.
+fn compare(left: Value, right: Value) -> Result<Ordering, InterpreterError> {
     Ok(match (left, right) {
-        (Value::U8(lvalue), Value::U8(rvalue)) => Value::Bool(lvalue < rvalue),
-        (Value::U16(lvalue), Value::U16(rvalue)) => Value::Bool(lvalue < rvalue),
-        (Value::U32(lvalue), Value::U32(rvalue)) => Value::Bool(lvalue < rvalue),
-        (Value::U64(lvalue), Value::U64(rvalue)) => Value::Bool(lvalue < rvalue),
+        (Value::U8(lvalue), Value::U8(rvalue)) => lvalue.cmp(&rvalue),
+        (Value::U16(lvalue), Value::U16(rvalue)) => lvalue.cmp(&rvalue),
+        (Value::U32(lvalue), Value::U32(rvalue)) => lvalue.cmp(&rvalue),
+        (Value::U64(lvalue), Value::U64(rvalue)) => lvalue.cmp(&rvalue),
+        (Value::Char(lvalue), Value::Char(rvalue)) => lvalue.cmp(&rvalue),
+        (Value::Bytes(lvalue), Value::Bytes(rvalue)) => lvalue.cmp(&rvalue),
+        (lvalue, rvalue) if !(lvalue.is_number() && rvalue.is_number()) => return Err(InterpreterError::OperandNotNumeric),
         _ => return Err(InterpreterError::OperandsNotSameType),
     })
 }
+pub fn op_less_than(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    Ok(Value::Bool(compare(left, right)?.is_lt()))
+}
+pub fn op_greater_than(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    Ok(Value::Bool(compare(left, right)?.is_gt()))
+}
+pub fn op_less_than_or_equal(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    Ok(Value::Bool(compare(left, right)?.is_le()))
+}
+pub fn op_greater_than_or_equal(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    Ok(Value::Bool(compare(left, right)?.is_ge()))
+}
 pub fn op_equals(left: Value, right: Value) -> Result<Value, InterpreterError> {
     Ok(match (left, right) {
         (Value::U8(lvalue), Value::U8(rvalue)) => Value::Bool(lvalue == rvalue),
@@ -67,20 +119,48 @@ pub fn op_equals(left: Value, right: Value) -> Result<Value, InterpreterError> {
         (Value::U32(lvalue), Value::U32(rvalue)) => Value::Bool(lvalue == rvalue),
         (Value::U64(lvalue), Value::U64(rvalue)) => Value::Bool(lvalue == rvalue),
         (Value::Bool(lvalue), Value::Bool(rvalue)) => Value::Bool(lvalue == rvalue),
-        (Value::String(_), Value::String(_)) => unimplemented!(),
-        (Value::Array(_), Value::Array(_)) => unimplemented!(),
+        (Value::String(lvalue), Value::String(rvalue)) => Value::Bool(lvalue == rvalue),
+        (Value::Char(lvalue), Value::Char(rvalue)) => Value::Bool(lvalue == rvalue),
+        (Value::Bytes(lvalue), Value::Bytes(rvalue)) => Value::Bool(lvalue == rvalue),
+        (Value::Array(lvalue), Value::Array(rvalue)) if lvalue.get_type() == rvalue.get_type() => Value::Bool(lvalue == rvalue),
+        (Value::Struct(lvalue), Value::Struct(rvalue)) if lvalue.struct_id() == rvalue.struct_id() => Value::Bool(lvalue == rvalue),
+        (Value::Enum(lvalue), Value::Enum(rvalue)) if lvalue.enum_id() == rvalue.enum_id() => Value::Bool(lvalue == rvalue),
+        (lvalue @ (Value::Map(_) | Value::Option(_) | Value::Ref(_) | Value::Object(_) | Value::Function(_)), rvalue)
+            if lvalue.is_same_type(&rvalue) =>
+        {
+            Value::Bool(lvalue == rvalue)
+        }
         _ => return Err(InterpreterError::OperandsNotSameType),
     })
 }
 pub fn op_not_equals(left: Value, right: Value) -> Result<Value, InterpreterError> {
-    Ok(match (left, right) {
-        (Value::U8(lvalue), Value::U8(rvalue)) => Value::Bool(lvalue != rvalue),
-        (Value::U16(lvalue), Value::U16(rvalue)) => Value::Bool(lvalue != rvalue),
-        (Value::U32(lvalue), Value::U32(rvalue)) => Value::Bool(lvalue != rvalue),
-        (Value::U64(lvalue), Value::U64(rvalue)) => Value::Bool(lvalue != rvalue),
-        (Value::Bool(lvalue), Value::Bool(rvalue)) => Value::Bool(lvalue != rvalue),
-        (Value::Array(_), Value::Array(_)) => unimplemented!(),
-        (Value::String(_), Value::String(_)) => unimplemented!(),
-        _ => unimplemented!(),
-    })
+    let Value::Bool(equals) = op_equals(left, right)? else {
+        return Err(InterpreterError::OperandsNotSameType);
+    };
+    Ok(Value::Bool(!equals))
+}
+
+pub fn op_and(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    match (left, right) {
+        (Value::Bool(lvalue), Value::Bool(rvalue)) => Ok(Value::Bool(lvalue && rvalue)),
+        _ => Err(InterpreterError::OperandNotBoolean),
+    }
+}
+pub fn op_or(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    match (left, right) {
+        (Value::Bool(lvalue), Value::Bool(rvalue)) => Ok(Value::Bool(lvalue || rvalue)),
+        _ => Err(InterpreterError::OperandNotBoolean),
+    }
+}
+pub fn op_xor(left: Value, right: Value) -> Result<Value, InterpreterError> {
+    match (left, right) {
+        (Value::Bool(lvalue), Value::Bool(rvalue)) => Ok(Value::Bool(lvalue ^ rvalue)),
+        _ => Err(InterpreterError::OperandNotBoolean),
+    }
+}
+pub fn op_not(value: Value) -> Result<Value, InterpreterError> {
+    match value {
+        Value::Bool(value) => Ok(Value::Bool(!value)),
+        _ => Err(InterpreterError::OperandNotBoolean),
+    }
 }
@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+use crate::{interpreter::Program, value::FunctionIdType};
+
+/// Executions of, and time spent in, a single instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionProfile {
+    pub function_id: FunctionIdType,
+    pub instruction: usize,
+    pub count: u64,
+    pub time: Duration,
+}
+
+/// Time spent in a function. Inclusive time counts the functions it called, exclusive time only its own instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub function_id: FunctionIdType,
+    pub name: Option<String>,
+    pub calls: u64,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct ProfiledFrame {
+    function_id: FunctionIdType,
+    entered: Instant,
+    // exclusive time of this frame not yet added to the totals
+    self_time: Duration,
+}
+
+/// Collects timings while the interpreter runs, see `Interpreter::enable_profiling`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Profiler {
+    stack: Vec<ProfiledFrame>,
+    instructions: HashMap<(FunctionIdType, usize), (u64, Duration)>,
+    // calls, inclusive and exclusive time per function
+    functions: HashMap<FunctionIdType, (u64, Duration, Duration)>,
+    // exclusive time per stack of function ids, outermost first
+    stacks: HashMap<Vec<FunctionIdType>, Duration>,
+}
+
+impl Profiler {
+    /// Starts profiling with `frames` already on the callstack, outermost first.
+    pub(crate) fn new(frames: impl Iterator<Item = FunctionIdType>) -> Self {
+        let mut profiler = Profiler::default();
+        for function_id in frames {
+            profiler.enter(function_id);
+        }
+        profiler
+    }
+    pub(crate) fn record_instruction(&mut self, function_id: FunctionIdType, instruction: usize, time: Duration) {
+        let entry = self.instructions.entry((function_id, instruction)).or_default();
+        entry.0 += 1;
+        entry.1 += time;
+        if let Some(frame) = self.stack.last_mut() {
+            frame.self_time += time;
+        }
+    }
+    pub(crate) fn enter(&mut self, function_id: FunctionIdType) {
+        self.functions.entry(function_id).or_default().0 += 1;
+        self.stack.push(ProfiledFrame {
+            function_id,
+            entered: Instant::now(),
+            self_time: Duration::ZERO,
+        });
+    }
+    pub(crate) fn exit(&mut self) {
+        self.flush_top();
+        if let Some(frame) = self.stack.pop() {
+            // recursive calls are already covered by the outermost frame of the same function
+            if !self.stack.iter().any(|outer| outer.function_id == frame.function_id) {
+                self.functions.entry(frame.function_id).or_default().1 += frame.entered.elapsed();
+            }
+        }
+    }
+
+    fn flush_top(&mut self) {
+        let Some(frame) = self.stack.last_mut() else {
+            return;
+        };
+        let self_time = std::mem::take(&mut frame.self_time);
+        self.functions.entry(frame.function_id).or_default().2 += self_time;
+        let path: Vec<FunctionIdType> = self.stack.iter().map(|frame| frame.function_id).collect();
+        *self.stacks.entry(path).or_default() += self_time;
+    }
+
+    /// Ends profiling, counting frames still on the callstack as if they returned now.
+    pub(crate) fn finish(mut self, program: &Program) -> Profile {
+        while !self.stack.is_empty() {
+            self.exit();
+        }
+
+        let mut instructions: Vec<InstructionProfile> = self
+            .instructions
+            .into_iter()
+            .map(|((function_id, instruction), (count, time))| InstructionProfile {
+                function_id,
+                instruction,
+                count,
+                time,
+            })
+            .collect();
+        instructions.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then(a.function_id.cmp(&b.function_id))
+                .then(a.instruction.cmp(&b.instruction))
+        });
+
+        let mut functions: Vec<FunctionProfile> = self
+            .functions
+            .into_iter()
+            .map(|(function_id, (calls, inclusive, exclusive))| FunctionProfile {
+                function_id,
+                name: program.get_function(function_id).ok().and_then(|f| f.name()).map(str::to_string),
+                calls,
+                inclusive,
+                exclusive,
+            })
+            .collect();
+        functions.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then(a.function_id.cmp(&b.function_id)));
+
+        let mut stacks: Vec<(Vec<FunctionIdType>, Duration)> = self.stacks.into_iter().collect();
+        stacks.sort();
+
+        Profile {
+            instructions,
+            functions,
+            stacks,
+        }
+    }
+}
+
+/// The result of a profiled run. Instructions and functions are sorted by time spent, most first.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub instructions: Vec<InstructionProfile>,
+    pub functions: Vec<FunctionProfile>,
+    /// Exclusive time per callstack, outermost function first.
+    pub stacks: Vec<(Vec<FunctionIdType>, Duration)>,
+}
+
+impl Profile {
+    /// A human readable report of the functions and instructions where the most time was spent.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{:>12} {:>12} {:>10}  function", "exclusive", "inclusive", "calls");
+        for function in self.functions.iter() {
+            let _ = writeln!(
+                out,
+                "{:>12?} {:>12?} {:>10}  {}",
+                function.exclusive,
+                function.inclusive,
+                function.calls,
+                self.function_name(function.function_id)
+            );
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{:>12} {:>10}  instruction", "time", "count");
+        for instruction in self.instructions.iter() {
+            let _ = writeln!(
+                out,
+                "{:>12?} {:>10}  {} #{}",
+                instruction.time,
+                instruction.count,
+                self.function_name(instruction.function_id),
+                instruction.instruction
+            );
+        }
+        out
+    }
+
+    /// One line per callstack with its exclusive time in nanoseconds, in the folded format read by flamegraph tools.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (stack, time) in self.stacks.iter() {
+            if time.is_zero() {
+                continue;
+            }
+            let names: Vec<String> = stack
+                .iter()
+                .map(|function_id| self.function_name(*function_id).replace([';', ' '], "_"))
+                .collect();
+            let _ = writeln!(out, "{} {}", names.join(";"), time.as_nanos());
+        }
+        out
+    }
+
+    fn function_name(&self, function_id: FunctionIdType) -> String {
+        let name = self
+            .functions
+            .iter()
+            .find(|f| f.function_id == function_id)
+            .and_then(|f| f.name.clone());
+        name.unwrap_or_else(|| format!("function_{function_id}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        instructions::Instruction,
+        interpreter::{Function, Interpreter, Program},
+        value::{Value, VariableType},
+    };
+
+    #[test]
+    fn test_profile_counts() {
+        let mut add_one = Function::new(&[VariableType::U64], Some(VariableType::U64));
+        add_one.set_name("add_one");
+        add_one.set_instructions(vec![Instruction::AddI(0, Value::U64(1)), Instruction::Return(0)]);
+        let mut main = Function::new(&[], None);
+        main.set_name("main");
+        main.register_variable(VariableType::U64).unwrap();
+        main.register_variable(VariableType::Bool).unwrap();
+        main.set_instructions(vec![
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 0),
+            Instruction::LessThanI(1, 0, Value::U64(5)),
+            Instruction::GotoIfTrue(0, 1),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, add_one])).unwrap();
+        interpreter.enable_profiling();
+        interpreter.execute().unwrap();
+        let profile = interpreter.take_profile().unwrap();
+        assert!(interpreter.take_profile().is_none());
+
+        let add_one = profile.functions.iter().find(|f| f.function_id == 1).unwrap();
+        assert_eq!(add_one.calls, 5);
+        assert!(add_one.inclusive >= add_one.exclusive);
+        let main = profile.functions.iter().find(|f| f.function_id == 0).unwrap();
+        assert!(main.inclusive >= main.exclusive + add_one.exclusive);
+
+        let add = profile
+            .instructions
+            .iter()
+            .find(|i| i.function_id == 1 && i.instruction == 0)
+            .unwrap();
+        assert_eq!(add.count, 5);
+        let total: u64 = profile.instructions.iter().map(|i| i.count).sum();
+        assert_eq!(total, 5 * 4 + 5 * 2);
+
+        let folded = profile.folded();
+        assert!(folded
+            .lines()
+            .all(|line| line.starts_with("main ") || line.starts_with("main;add_one ")));
+        assert!(profile.report().contains("add_one #0"));
+    }
+}
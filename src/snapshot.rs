@@ -0,0 +1,920 @@
+use std::fmt;
+
+use crate::{
+    instructions::{Capture, Instruction},
+    interpreter::{Function, Program},
+    value::{
+        ArrayValue, EnumDefinition, EnumIdType, EnumValue, FunctionValue, MapValue, ObjectValue, OptionValue, RefTarget, RefValue,
+        Signature, StructDefinition, StructIdType, StructValue, Value, VariableType,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"NISN";
+/// Bumped whenever the layout of a snapshot changes. Snapshots of other versions are rejected.
+pub const SNAPSHOT_VERSION: u16 = 12;
+// Arrays, structs, maps, options, enums, references, objects and function signatures nest, so untrusted input could otherwise recurse without bound.
+const MAX_TYPE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    NotASnapshot,
+    UnsupportedVersion(u16),
+    /// The snapshot was taken from a different program.
+    ProgramMismatch,
+    Truncated,
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "data is not an interpreter snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "snapshot version {version} is not supported, expected {SNAPSHOT_VERSION}")
+            }
+            SnapshotError::ProgramMismatch => write!(f, "snapshot was taken from a different program"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Corrupt(reason) => write!(f, "snapshot is corrupt: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// A hash identifying a program, so a snapshot is only restored against the program it was taken from.
+pub fn program_fingerprint(program: &Program) -> u64 {
+    // FNV-1a over the encoding `SnapshotWriter` gives the program's definitions and functions, so the hash is stable
+    // across Rust versions and does not depend on `Debug` output
+    let mut writer = SnapshotWriter::default();
+    writer.program(program);
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in writer.finish() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Encodes interpreter state, little-endian.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    pub(crate) fn new(program: &Program) -> Self {
+        let mut writer = SnapshotWriter::default();
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u16(SNAPSHOT_VERSION);
+        writer.u64(program.fingerprint());
+        writer
+    }
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+    pub(crate) fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+    pub(crate) fn option<T>(&mut self, value: Option<T>, mut write: impl FnMut(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                write(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+    pub(crate) fn values(&mut self, values: &[Value]) {
+        self.len(values.len());
+        for value in values.iter() {
+            self.value(value);
+        }
+    }
+    fn string(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+    fn variable_type(&mut self, var_type: &VariableType) {
+        match var_type {
+            VariableType::U8 => self.u8(0),
+            VariableType::U16 => self.u8(1),
+            VariableType::U32 => self.u8(2),
+            VariableType::U64 => self.u8(3),
+            VariableType::String => self.u8(4),
+            VariableType::Bool => self.u8(5),
+            VariableType::Array(inner_type) => {
+                self.u8(6);
+                self.variable_type(inner_type);
+            }
+            VariableType::Struct(struct_id) => {
+                self.u8(7);
+                self.u16(*struct_id);
+            }
+            VariableType::Map(key_type, value_type) => {
+                self.u8(8);
+                self.variable_type(key_type);
+                self.variable_type(value_type);
+            }
+            VariableType::Option(inner_type) => {
+                self.u8(9);
+                self.variable_type(inner_type);
+            }
+            VariableType::Enum(enum_id) => {
+                self.u8(10);
+                self.u16(*enum_id);
+            }
+            VariableType::Char => self.u8(11),
+            VariableType::Bytes => self.u8(12),
+            VariableType::Ref(inner_type) => {
+                self.u8(13);
+                self.variable_type(inner_type);
+            }
+            VariableType::Object(inner_type) => {
+                self.u8(14);
+                self.variable_type(inner_type);
+            }
+            VariableType::Function(signature) => {
+                self.u8(15);
+                self.signature(signature);
+            }
+        }
+    }
+    fn signature(&mut self, signature: &Signature) {
+        self.len(signature.parameters().len());
+        signature.parameters().iter().for_each(|parameter| self.variable_type(parameter));
+        self.option(signature.return_type(), Self::variable_type);
+    }
+    pub(crate) fn value(&mut self, value: &Value) {
+        match value {
+            Value::U8(v) => {
+                self.u8(0);
+                self.u8(*v);
+            }
+            Value::U16(v) => {
+                self.u8(1);
+                self.u16(*v);
+            }
+            Value::U32(v) => {
+                self.u8(2);
+                self.u32(*v);
+            }
+            Value::U64(v) => {
+                self.u8(3);
+                self.u64(*v);
+            }
+            Value::String(s) => {
+                self.u8(4);
+                self.string(s);
+            }
+            Value::Bool(b) => {
+                self.u8(5);
+                self.u8(*b as u8);
+            }
+            Value::Array(array) => {
+                self.u8(6);
+                self.array(array);
+            }
+            Value::Struct(value) => {
+                self.u8(7);
+                self.u16(value.struct_id());
+                self.values(value.fields());
+            }
+            Value::Map(map) => {
+                self.u8(8);
+                self.map(map);
+            }
+            Value::Option(option) => {
+                self.u8(9);
+                self.option_value(option);
+            }
+            Value::Enum(value) => {
+                self.u8(10);
+                self.u16(value.enum_id());
+                self.enum_value(value);
+            }
+            Value::Char(c) => {
+                self.u8(11);
+                self.u32(*c as u32);
+            }
+            Value::Bytes(bytes) => {
+                self.u8(12);
+                self.byte_string(bytes);
+            }
+            Value::Ref(reference) => {
+                self.u8(13);
+                self.variable_type(reference.inner_type());
+                self.option(reference.target(), Self::ref_target);
+            }
+            Value::Object(object) => {
+                self.u8(14);
+                self.variable_type(object.inner_type());
+                self.option(object.handle(), Self::u32);
+            }
+            Value::Function(function) => {
+                self.u8(15);
+                self.signature(function.signature());
+                self.function_value(function);
+            }
+        }
+    }
+    fn array(&mut self, array: &ArrayValue) {
+        self.variable_type(&array.get_inner_type());
+        self.len(array.len());
+        match array {
+            ArrayValue::BoolArray(values) => values.iter().for_each(|v| self.u8(*v as u8)),
+            ArrayValue::U8Array(values) => self.bytes.extend_from_slice(values),
+            ArrayValue::U16Array(values) => values.iter().for_each(|v| self.u16(*v)),
+            ArrayValue::U32Array(values) => values.iter().for_each(|v| self.u32(*v)),
+            ArrayValue::U64Array(values) => values.iter().for_each(|v| self.u64(*v)),
+            ArrayValue::StringArray(values) => values.iter().for_each(|v| self.string(v)),
+            ArrayValue::ArrayArray(_, values) => values.iter().for_each(|v| self.array(v)),
+            ArrayValue::StructArray(_, values) => values.iter().for_each(|v| self.values(v.fields())),
+            ArrayValue::MapArray(_, _, values) => values.iter().for_each(|v| self.map(v)),
+            ArrayValue::OptionArray(_, values) => values.iter().for_each(|v| self.option_value(v)),
+            ArrayValue::EnumArray(_, values) => values.iter().for_each(|v| self.enum_value(v)),
+            ArrayValue::CharArray(values) => values.iter().for_each(|v| self.u32(*v as u32)),
+            ArrayValue::BytesArray(values) => values.iter().for_each(|v| self.byte_string(v)),
+            ArrayValue::RefArray(_, values) => values.iter().for_each(|v| self.option(v.target(), Self::ref_target)),
+            ArrayValue::ObjectArray(_, values) => values.iter().for_each(|v| self.option(v.handle(), Self::u32)),
+            ArrayValue::FunctionArray(_, values) => values.iter().for_each(|v| self.function_value(v)),
+        }
+    }
+    fn ref_target(&mut self, target: RefTarget) {
+        match target {
+            RefTarget::Variable { frame, var_id } => {
+                self.u8(0);
+                self.usize(frame);
+                self.u16(var_id);
+            }
+            RefTarget::Heap(object_id) => {
+                self.u8(1);
+                self.u32(object_id);
+            }
+        }
+    }
+    fn function_value(&mut self, function: &FunctionValue) {
+        self.option(function.function_id(), Self::u16);
+        self.values(function.captures());
+    }
+    fn byte_string(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+    fn enum_value(&mut self, value: &EnumValue) {
+        self.u16(value.tag());
+        self.option(value.payload(), Self::value);
+    }
+    fn option_value(&mut self, option: &OptionValue) {
+        self.variable_type(option.inner_type());
+        self.option(option.value(), Self::value);
+    }
+    fn map(&mut self, map: &MapValue) {
+        self.variable_type(map.key_type());
+        self.variable_type(map.value_type());
+        self.len(map.len());
+        for (key, value) in map.entries() {
+            self.value(&key.to_value());
+            self.value(value);
+        }
+    }
+    fn program(&mut self, program: &Program) {
+        self.len(program.structs().len());
+        for definition in program.structs() {
+            self.option(definition.name(), Self::string);
+            self.len(definition.fields().len());
+            definition.fields().iter().for_each(|field| self.variable_type(field));
+        }
+        self.len(program.enums().len());
+        for definition in program.enums() {
+            self.option(definition.name(), Self::string);
+            self.len(definition.variants().len());
+            definition
+                .variants()
+                .iter()
+                .for_each(|variant| self.option(variant.as_ref(), Self::variable_type));
+        }
+        self.len(program.functions().len());
+        program.functions().iter().for_each(|function| self.function(function));
+    }
+    fn function(&mut self, function: &Function) {
+        self.option(function.name(), Self::string);
+        self.signature(&function.signature());
+        self.len(function.variables().len());
+        function.variables().iter().for_each(|var_type| self.variable_type(var_type));
+        self.values(function.default_values());
+        self.len(function.instructions().len());
+        function.instructions().iter().for_each(|instruction| self.instruction(instruction));
+    }
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Set(a, b) => self.ids(0, &[*a, *b]),
+            Instruction::SetI(a, value) => self.ids_value(1, &[*a], value),
+            Instruction::SetArrayIndex(a, b, c) => self.ids(2, &[*a, *b, *c]),
+            Instruction::SetArrayIndexI(a, b, value) => self.ids_value(3, &[*a, *b], value),
+            Instruction::SetArrayIIndex(a, value, b) => self.ids_value(4, &[*a, *b], value),
+            Instruction::GetArrayIndex(a, b, c) => self.ids(5, &[*a, *b, *c]),
+            Instruction::GetArrayIndexI(a, b, value) => self.ids_value(6, &[*a, *b], value),
+            Instruction::GetField(a, b, c) => self.ids(7, &[*a, *b, *c]),
+            Instruction::SetField(a, b, c) => self.ids(8, &[*a, *b, *c]),
+            Instruction::SetFieldI(a, b, value) => self.ids_value(9, &[*a, *b], value),
+            Instruction::WrapSome(a, b) => self.ids(10, &[*a, *b]),
+            Instruction::SetNone(a) => self.ids(11, &[*a]),
+            Instruction::IsSome(a, b) => self.ids(12, &[*a, *b]),
+            Instruction::Unwrap(a, b) => self.ids(13, &[*a, *b]),
+            Instruction::SetVariant(a, b, c) => self.ids(14, &[*a, *b, *c]),
+            Instruction::SetUnitVariant(a, b) => self.ids(15, &[*a, *b]),
+            Instruction::GetTag(a, b) => self.ids(16, &[*a, *b]),
+            Instruction::GetPayload(a, b, c) => self.ids(17, &[*a, *b, *c]),
+            Instruction::MakeRef(a, b) => self.ids(18, &[*a, *b]),
+            Instruction::AllocRef(a, b) => self.ids(19, &[*a, *b]),
+            Instruction::LoadRef(a, b) => self.ids(20, &[*a, *b]),
+            Instruction::StoreRef(a, b) => self.ids(21, &[*a, *b]),
+            Instruction::GetRefIndex(a, b, c) => self.ids(22, &[*a, *b, *c]),
+            Instruction::SetRefIndex(a, b, c) => self.ids(23, &[*a, *b, *c]),
+            Instruction::NewObject(a, b) => self.ids(24, &[*a, *b]),
+            Instruction::GetObject(a, b) => self.ids(25, &[*a, *b]),
+            Instruction::SetObject(a, b) => self.ids(26, &[*a, *b]),
+            Instruction::ObjectRef(a, b) => self.ids(27, &[*a, *b]),
+            Instruction::Add(a, b) => self.ids(28, &[*a, *b]),
+            Instruction::Sub(a, b) => self.ids(29, &[*a, *b]),
+            Instruction::Mul(a, b) => self.ids(30, &[*a, *b]),
+            Instruction::Div(a, b) => self.ids(31, &[*a, *b]),
+            Instruction::Rem(a, b) => self.ids(32, &[*a, *b]),
+            Instruction::AddI(a, value) => self.ids_value(33, &[*a], value),
+            Instruction::SubI(a, value) => self.ids_value(34, &[*a], value),
+            Instruction::MulI(a, value) => self.ids_value(35, &[*a], value),
+            Instruction::DivI(a, value) => self.ids_value(36, &[*a], value),
+            Instruction::RemI(a, value) => self.ids_value(37, &[*a], value),
+            Instruction::LessThan(a, b, c) => self.ids(38, &[*a, *b, *c]),
+            Instruction::LessThanI(a, b, value) => self.ids_value(39, &[*a, *b], value),
+            Instruction::GreaterThan(a, b, c) => self.ids(40, &[*a, *b, *c]),
+            Instruction::GreaterThanI(a, b, value) => self.ids_value(41, &[*a, *b], value),
+            Instruction::LessThanOrEqual(a, b, c) => self.ids(42, &[*a, *b, *c]),
+            Instruction::LessThanOrEqualI(a, b, value) => self.ids_value(43, &[*a, *b], value),
+            Instruction::GreaterThanOrEqual(a, b, c) => self.ids(44, &[*a, *b, *c]),
+            Instruction::GreaterThanOrEqualI(a, b, value) => self.ids_value(45, &[*a, *b], value),
+            Instruction::Equals(a, b, c) => self.ids(46, &[*a, *b, *c]),
+            Instruction::EqualsI(a, b, value) => self.ids_value(47, &[*a, *b], value),
+            Instruction::NotEquals(a, b, c) => self.ids(48, &[*a, *b, *c]),
+            Instruction::NotEqualsI(a, b, value) => self.ids_value(49, &[*a, *b], value),
+            Instruction::Or(a, b) => self.ids(50, &[*a, *b]),
+            Instruction::And(a, b) => self.ids(51, &[*a, *b]),
+            Instruction::Xor(a, b) => self.ids(52, &[*a, *b]),
+            Instruction::Not(a) => self.ids(53, &[*a]),
+            Instruction::Goto(target) => {
+                self.u8(54);
+                self.usize(*target);
+            }
+            Instruction::GotoIfTrue(target, a) => {
+                self.u8(55);
+                self.usize(*target);
+                self.u16(*a);
+            }
+            Instruction::Switch(a, targets) => {
+                self.ids(56, &[*a]);
+                self.len(targets.len());
+                targets.iter().for_each(|target| self.usize(*target));
+            }
+            Instruction::PushFunctionParameter(a) => self.ids(57, &[*a]),
+            Instruction::CallVoidFunction(a) => self.ids(58, &[*a]),
+            Instruction::CallFunction(a, b) => self.ids(59, &[*a, *b]),
+            Instruction::CallVoidIndirect(a) => self.ids(60, &[*a]),
+            Instruction::CallIndirect(a, b) => self.ids(61, &[*a, *b]),
+            Instruction::MakeClosure(a, function_id, captures) => {
+                self.ids(62, &[*a, *function_id]);
+                self.len(captures.len());
+                captures.iter().for_each(|capture| self.capture(*capture));
+            }
+            Instruction::CallNativeVoidFunction(a) => self.ids(63, &[*a]),
+            Instruction::CallNativeVoidMethod(a, b) => self.ids(64, &[*a, *b]),
+            Instruction::CallNativeMethod(a, b, c) => self.ids(65, &[*a, *b, *c]),
+            Instruction::Return(a) => self.ids(66, &[*a]),
+            Instruction::Yield => self.u8(67),
+        }
+    }
+    // An instruction's tag followed by its variable, field, variant and function ids.
+    fn ids(&mut self, tag: u8, ids: &[u16]) {
+        self.u8(tag);
+        ids.iter().for_each(|id| self.u16(*id));
+    }
+    fn ids_value(&mut self, tag: u8, ids: &[u16], value: &Value) {
+        self.ids(tag, ids);
+        self.value(value);
+    }
+    fn capture(&mut self, capture: Capture) {
+        match capture {
+            Capture::Value(var_id) => self.ids(0, &[var_id]),
+            Capture::Ref(var_id) => self.ids(1, &[var_id]),
+        }
+    }
+}
+
+/// Decodes what `SnapshotWriter` wrote, checking every length against the remaining input.
+#[derive(Debug)]
+pub(crate) struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+    // struct and enum values are checked against their definitions
+    structs: &'a [StructDefinition],
+    enums: &'a [EnumDefinition],
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], program: &'a Program) -> Result<Self, SnapshotError> {
+        let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+            return Err(SnapshotError::NotASnapshot);
+        };
+        let mut reader = SnapshotReader {
+            bytes,
+            structs: program.structs(),
+            enums: program.enums(),
+        };
+        let version = reader.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        if reader.u64()? != program.fingerprint() {
+            return Err(SnapshotError::ProgramMismatch);
+        }
+        Ok(reader)
+    }
+    pub(crate) fn finish(self) -> Result<(), SnapshotError> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(SnapshotError::Corrupt("trailing bytes")),
+        }
+    }
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        let Some((taken, rest)) = self.bytes.split_first_chunk::<N>() else {
+            return Err(SnapshotError::Truncated);
+        };
+        self.bytes = rest;
+        Ok(*taken)
+    }
+    pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take::<1>()?[0])
+    }
+    pub(crate) fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+    pub(crate) fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+    pub(crate) fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+    pub(crate) fn usize(&mut self) -> Result<usize, SnapshotError> {
+        usize::try_from(self.u64()?).map_err(|_| SnapshotError::Corrupt("size does not fit in usize"))
+    }
+    /// A length prefix for `element_size`-byte elements, rejected early if the input cannot hold that many.
+    pub(crate) fn len(&mut self, element_size: usize) -> Result<usize, SnapshotError> {
+        let len = self.u32()? as usize;
+        if len.saturating_mul(element_size) > self.bytes.len() {
+            return Err(SnapshotError::Truncated);
+        }
+        Ok(len)
+    }
+    fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Corrupt("invalid bool")),
+        }
+    }
+    pub(crate) fn option<T>(&mut self, mut read: impl FnMut(&mut Self) -> Result<T, SnapshotError>) -> Result<Option<T>, SnapshotError> {
+        match self.bool()? {
+            true => Ok(Some(read(self)?)),
+            false => Ok(None),
+        }
+    }
+    pub(crate) fn values(&mut self) -> Result<Vec<Value>, SnapshotError> {
+        let len = self.len(2)?;
+        (0..len).map(|_| self.value()).collect()
+    }
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let len = self.len(1)?;
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SnapshotError::Corrupt("invalid utf-8 in string"))
+    }
+    fn byte_string(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        let len = self.len(1)?;
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes.to_vec())
+    }
+    fn char(&mut self) -> Result<char, SnapshotError> {
+        char::from_u32(self.u32()?).ok_or(SnapshotError::Corrupt("invalid char"))
+    }
+    fn variable_type(&mut self, depth: usize) -> Result<VariableType, SnapshotError> {
+        Ok(match self.u8()? {
+            0 => VariableType::U8,
+            1 => VariableType::U16,
+            2 => VariableType::U32,
+            3 => VariableType::U64,
+            4 => VariableType::String,
+            5 => VariableType::Bool,
+            6 if depth < MAX_TYPE_DEPTH => VariableType::Array(Box::new(self.variable_type(depth + 1)?)),
+            6 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            7 => VariableType::Struct(self.u16()?),
+            8 if depth < MAX_TYPE_DEPTH => {
+                VariableType::Map(Box::new(self.variable_type(depth + 1)?), Box::new(self.variable_type(depth + 1)?))
+            }
+            8 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            9 if depth < MAX_TYPE_DEPTH => VariableType::Option(Box::new(self.variable_type(depth + 1)?)),
+            9 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            10 => VariableType::Enum(self.u16()?),
+            11 => VariableType::Char,
+            12 => VariableType::Bytes,
+            13 if depth < MAX_TYPE_DEPTH => VariableType::Ref(Box::new(self.variable_type(depth + 1)?)),
+            13 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            14 if depth < MAX_TYPE_DEPTH => VariableType::Object(Box::new(self.variable_type(depth + 1)?)),
+            14 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            15 if depth < MAX_TYPE_DEPTH => VariableType::Function(Box::new(self.signature(depth + 1)?)),
+            15 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            _ => return Err(SnapshotError::Corrupt("unknown type tag")),
+        })
+    }
+    pub(crate) fn value(&mut self) -> Result<Value, SnapshotError> {
+        self.nested_value(0)
+    }
+    fn nested_value(&mut self, depth: usize) -> Result<Value, SnapshotError> {
+        Ok(match self.u8()? {
+            0 => Value::U8(self.u8()?),
+            1 => Value::U16(self.u16()?),
+            2 => Value::U32(self.u32()?),
+            3 => Value::U64(self.u64()?),
+            4 => Value::String(self.string()?),
+            5 => Value::Bool(self.bool()?),
+            6 => Value::Array(self.array(depth)?),
+            7 => {
+                let struct_id = self.u16()?;
+                Value::Struct(self.struct_value(struct_id, depth)?)
+            }
+            8 => Value::Map(self.map(depth)?),
+            9 => Value::Option(self.option_value(depth)?),
+            10 => {
+                let enum_id = self.u16()?;
+                Value::Enum(self.enum_value(enum_id, depth)?)
+            }
+            11 => Value::Char(self.char()?),
+            12 => Value::Bytes(self.byte_string()?),
+            13 => {
+                let inner_type = self.variable_type(depth)?;
+                match self.option(Self::ref_target)? {
+                    Some(target) => Value::Ref(RefValue::new(inner_type, target)),
+                    None => Value::Ref(RefValue::null(inner_type)),
+                }
+            }
+            14 => {
+                let inner_type = self.variable_type(depth)?;
+                match self.option(Self::u32)? {
+                    Some(handle) => Value::Object(ObjectValue::new(inner_type, handle)),
+                    None => Value::Object(ObjectValue::null(inner_type)),
+                }
+            }
+            15 if depth < MAX_TYPE_DEPTH => {
+                let signature = self.signature(depth + 1)?;
+                Value::Function(self.function_value(signature, depth)?)
+            }
+            15 => return Err(SnapshotError::Corrupt("types nested too deeply")),
+            _ => return Err(SnapshotError::Corrupt("unknown value tag")),
+        })
+    }
+    fn array(&mut self, depth: usize) -> Result<ArrayValue, SnapshotError> {
+        Ok(match self.variable_type(depth)? {
+            VariableType::Bool => ArrayValue::BoolArray((0..self.len(1)?).map(|_| self.bool()).collect::<Result<_, _>>()?),
+            VariableType::U8 => {
+                let len = self.len(1)?;
+                let (values, rest) = self.bytes.split_at(len);
+                self.bytes = rest;
+                ArrayValue::U8Array(values.to_vec())
+            }
+            VariableType::U16 => ArrayValue::U16Array((0..self.len(2)?).map(|_| self.u16()).collect::<Result<_, _>>()?),
+            VariableType::U32 => ArrayValue::U32Array((0..self.len(4)?).map(|_| self.u32()).collect::<Result<_, _>>()?),
+            VariableType::U64 => ArrayValue::U64Array((0..self.len(8)?).map(|_| self.u64()).collect::<Result<_, _>>()?),
+            VariableType::String => ArrayValue::StringArray((0..self.len(4)?).map(|_| self.string()).collect::<Result<_, _>>()?),
+            VariableType::Array(element_type) => {
+                let len = self.len(5)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let element = self.array(depth + 1)?;
+                    if element.get_inner_type() != *element_type {
+                        return Err(SnapshotError::Corrupt("array element has the wrong type"));
+                    }
+                    values.push(element);
+                }
+                ArrayValue::ArrayArray(*element_type, values)
+            }
+            VariableType::Struct(struct_id) => {
+                let len = self.len(4)?;
+                let values = (0..len)
+                    .map(|_| self.struct_value(struct_id, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                ArrayValue::StructArray(struct_id, values)
+            }
+            VariableType::Map(key_type, value_type) => {
+                let len = self.len(6)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let element = self.map(depth + 1)?;
+                    if !element.has_types(&key_type, &value_type) {
+                        return Err(SnapshotError::Corrupt("array element has the wrong type"));
+                    }
+                    values.push(element);
+                }
+                ArrayValue::MapArray(*key_type, *value_type, values)
+            }
+            VariableType::Option(inner_type) => {
+                let len = self.len(2)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let element = self.option_value(depth + 1)?;
+                    if element.inner_type() != inner_type.as_ref() {
+                        return Err(SnapshotError::Corrupt("array element has the wrong type"));
+                    }
+                    values.push(element);
+                }
+                ArrayValue::OptionArray(*inner_type, values)
+            }
+            VariableType::Enum(enum_id) => {
+                let len = self.len(3)?;
+                let values = (0..len).map(|_| self.enum_value(enum_id, depth + 1)).collect::<Result<_, _>>()?;
+                ArrayValue::EnumArray(enum_id, values)
+            }
+            VariableType::Char => ArrayValue::CharArray((0..self.len(4)?).map(|_| self.char()).collect::<Result<_, _>>()?),
+            VariableType::Bytes => ArrayValue::BytesArray((0..self.len(4)?).map(|_| self.byte_string()).collect::<Result<_, _>>()?),
+            VariableType::Ref(inner_type) => {
+                let len = self.len(1)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(match self.option(Self::ref_target)? {
+                        Some(target) => RefValue::new(*inner_type.clone(), target),
+                        None => RefValue::null(*inner_type.clone()),
+                    });
+                }
+                ArrayValue::RefArray(*inner_type, values)
+            }
+            VariableType::Object(inner_type) => {
+                let len = self.len(1)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(match self.option(Self::u32)? {
+                        Some(handle) => ObjectValue::new(*inner_type.clone(), handle),
+                        None => ObjectValue::null(*inner_type.clone()),
+                    });
+                }
+                ArrayValue::ObjectArray(*inner_type, values)
+            }
+            VariableType::Function(signature) => {
+                let len = self.len(5)?;
+                let values = (0..len)
+                    .map(|_| self.function_value(*signature.clone(), depth + 1))
+                    .collect::<Result<_, _>>()?;
+                ArrayValue::FunctionArray(*signature, values)
+            }
+        })
+    }
+    fn signature(&mut self, depth: usize) -> Result<Signature, SnapshotError> {
+        let parameters = (0..self.len(1)?)
+            .map(|_| self.variable_type(depth))
+            .collect::<Result<Vec<_>, _>>()?;
+        let return_type = self.option(|reader| reader.variable_type(depth))?;
+        Ok(Signature::new(&parameters, return_type))
+    }
+    fn ref_target(&mut self) -> Result<RefTarget, SnapshotError> {
+        Ok(match self.u8()? {
+            0 => RefTarget::Variable {
+                frame: self.usize()?,
+                var_id: self.u16()?,
+            },
+            1 => RefTarget::Heap(self.u32()?),
+            _ => return Err(SnapshotError::Corrupt("unknown reference target")),
+        })
+    }
+    fn enum_value(&mut self, enum_id: EnumIdType, depth: usize) -> Result<EnumValue, SnapshotError> {
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(SnapshotError::Corrupt("values nested too deeply"));
+        }
+        let Some(definition) = self.enums.get(enum_id as usize) else {
+            return Err(SnapshotError::Corrupt("unknown enum"));
+        };
+        let tag = self.u16()?;
+        let Ok(payload_type) = definition.variant(enum_id, tag) else {
+            return Err(SnapshotError::Corrupt("unknown enum variant"));
+        };
+        let payload = self.option(|reader| reader.nested_value(depth + 1))?;
+        match (payload_type, payload) {
+            (Some(payload_type), Some(payload)) if payload.is_type(payload_type) => Ok(EnumValue::new(enum_id, tag, Some(payload))),
+            (None, None) => Ok(EnumValue::new(enum_id, tag, None)),
+            _ => Err(SnapshotError::Corrupt("enum payload does not match its variant")),
+        }
+    }
+    fn option_value(&mut self, depth: usize) -> Result<OptionValue, SnapshotError> {
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(SnapshotError::Corrupt("values nested too deeply"));
+        }
+        let mut option = OptionValue::none(self.variable_type(depth)?);
+        if let Some(value) = self.option(|reader| reader.nested_value(depth + 1))? {
+            option
+                .replace(Some(value))
+                .map_err(|_| SnapshotError::Corrupt("option holds a value of the wrong type"))?;
+        }
+        Ok(option)
+    }
+    fn map(&mut self, depth: usize) -> Result<MapValue, SnapshotError> {
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(SnapshotError::Corrupt("values nested too deeply"));
+        }
+        let key_type = self.variable_type(depth)?;
+        if !key_type.is_hashable() {
+            return Err(SnapshotError::Corrupt("map key type is not hashable"));
+        }
+        let mut map = MapValue::new(key_type, self.variable_type(depth)?);
+        let len = self.len(4)?;
+        for _ in 0..len {
+            let key = self.nested_value(depth + 1)?;
+            let value = self.nested_value(depth + 1)?;
+            match map.insert(key, value) {
+                Ok(None) => {}
+                Ok(Some(_)) => return Err(SnapshotError::Corrupt("map has a duplicate key")),
+                Err(_) => return Err(SnapshotError::Corrupt("map entry has the wrong type")),
+            }
+        }
+        Ok(map)
+    }
+    fn function_value(&mut self, signature: Signature, depth: usize) -> Result<FunctionValue, SnapshotError> {
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(SnapshotError::Corrupt("values nested too deeply"));
+        }
+        let function_id = self.option(Self::u16)?;
+        let captures = (0..self.len(2)?)
+            .map(|_| self.nested_value(depth + 1))
+            .collect::<Result<Vec<_>, _>>()?;
+        match function_id {
+            Some(function_id) => Ok(FunctionValue::closure(signature, function_id, captures)),
+            None if captures.is_empty() => Ok(FunctionValue::null(signature)),
+            None => Err(SnapshotError::Corrupt("null function with captures")),
+        }
+    }
+    fn struct_value(&mut self, struct_id: StructIdType, depth: usize) -> Result<StructValue, SnapshotError> {
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(SnapshotError::Corrupt("values nested too deeply"));
+        }
+        let Some(definition) = self.structs.get(struct_id as usize) else {
+            return Err(SnapshotError::Corrupt("unknown struct"));
+        };
+        if self.len(2)? != definition.fields().len() {
+            return Err(SnapshotError::Corrupt("struct has the wrong number of fields"));
+        }
+        let mut fields = Vec::with_capacity(definition.fields().len());
+        for field_type in definition.fields() {
+            let field = self.nested_value(depth + 1)?;
+            if !field.is_type(field_type) {
+                return Err(SnapshotError::Corrupt("struct field has the wrong type"));
+            }
+            fields.push(field);
+        }
+        Ok(StructValue::new(struct_id, fields))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        instructions::Instruction,
+        interpreter::{ExecutionStatus, Function, Interpreter},
+        snapshot::*,
+    };
+
+    fn squares_program() -> Program {
+        let mut main = Function::new(&[], Some(VariableType::Array(Box::new(VariableType::U64))));
+        main.register_variables(&[
+            VariableType::U64,
+            VariableType::Array(Box::new(VariableType::U64)),
+            VariableType::Bool,
+            VariableType::U64,
+        ])
+        .unwrap();
+        main.set_instructions(vec![
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 3),
+            Instruction::PushFunctionParameter(3),
+            Instruction::CallNativeVoidMethod(1, 0),
+            Instruction::AddI(0, Value::U64(1)),
+            Instruction::LessThanI(2, 0, Value::U64(20)),
+            Instruction::GotoIfTrue(0, 2),
+            Instruction::Return(1),
+        ]);
+        let mut square = Function::new(&[VariableType::U64], Some(VariableType::U64));
+        square.set_instructions(vec![Instruction::Mul(0, 0), Instruction::Return(0)]);
+        Program::new(&[main, square])
+    }
+
+    #[test]
+    fn test_restore_resumes_execution() {
+        let mut original = Interpreter::new(squares_program()).unwrap();
+        original.set_fuel(Some(10_000));
+        // stop inside the callee, with its caller waiting for the return value
+        assert!(matches!(original.run_for(39), ExecutionStatus::Running));
+        assert_eq!(original.call_depth(), 2);
+        let snapshot = original.snapshot();
+
+        let mut restored = Interpreter::new(squares_program()).unwrap();
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.memory_usage(), original.memory_usage());
+
+        let ExecutionStatus::Finished(expected) = original.run_for(u64::MAX) else {
+            panic!("original did not finish");
+        };
+        let ExecutionStatus::Finished(result) = restored.run_for(u64::MAX) else {
+            panic!("restored did not finish");
+        };
+        assert_eq!(result, expected);
+        assert_eq!(result, Some(Value::Array(ArrayValue::U64Array((0..20).map(|i| i * i).collect()))));
+        assert_eq!(restored.stats(), original.stats());
+        assert_eq!(restored.remaining_fuel(), original.remaining_fuel());
+    }
+
+    #[test]
+    fn test_restore_maps() {
+        let nested = VariableType::Map(
+            Box::new(VariableType::U8),
+            Box::new(VariableType::Array(Box::new(VariableType::Map(
+                Box::new(VariableType::String),
+                Box::new(VariableType::U64),
+            )))),
+        );
+        let mut inner = MapValue::new(VariableType::String, VariableType::U64);
+        inner.insert(Value::String("inner".into()), Value::U64(3)).unwrap();
+        let mut array = ArrayValue::new(inner.get_type());
+        array.push(Value::Map(inner)).unwrap();
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[nested, VariableType::U8, array.get_type()]).unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(1, Value::U8(7)),
+            Instruction::SetI(2, Value::Array(array)),
+            Instruction::PushFunctionParameter(1),
+            Instruction::PushFunctionParameter(2),
+            Instruction::CallNativeVoidMethod(0, crate::native::MAP_INSERT),
+            Instruction::Yield,
+        ]);
+        let program = || Program::new(std::slice::from_ref(&main));
+        let mut original = Interpreter::new(program()).unwrap();
+        assert!(matches!(original.run_for(100), ExecutionStatus::Yielded));
+        let snapshot = original.snapshot();
+        let mut restored = Interpreter::new(program()).unwrap();
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.memory_usage(), original.memory_usage());
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_snapshots() {
+        let mut interpreter = Interpreter::new(squares_program()).unwrap();
+        interpreter.run_for(10);
+        let snapshot = interpreter.snapshot();
+
+        let mut other = Function::new(&[], None);
+        other.set_instructions(vec![Instruction::Yield]);
+        let mut different = Interpreter::new(Program::new(&[other])).unwrap();
+        assert_eq!(different.restore(&snapshot), Err(SnapshotError::ProgramMismatch));
+
+        // programs differing in a single immediate operand are told apart
+        let counting = |limit| {
+            let mut main = Function::new(&[], None);
+            main.register_variables(&[VariableType::U64, VariableType::Bool]).unwrap();
+            main.set_instructions(vec![
+                Instruction::AddI(0, Value::U64(1)),
+                Instruction::LessThanI(1, 0, Value::U64(limit)),
+            ]);
+            Program::new(&[main])
+        };
+        assert_eq!(program_fingerprint(&counting(20)), program_fingerprint(&counting(20)));
+        assert_ne!(program_fingerprint(&counting(20)), program_fingerprint(&counting(21)));
+
+        let mut fresh = Interpreter::new(squares_program()).unwrap();
+        let before = fresh.snapshot();
+        assert_eq!(fresh.restore(&snapshot[..snapshot.len() - 1]), Err(SnapshotError::Truncated));
+        assert_eq!(fresh.restore(b"not a snapshot"), Err(SnapshotError::NotASnapshot));
+        let mut future = snapshot.clone();
+        future[4] = 0xff;
+        assert!(matches!(fresh.restore(&future), Err(SnapshotError::UnsupportedVersion(_))));
+        assert_eq!(fresh.snapshot(), before);
+    }
+}
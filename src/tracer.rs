@@ -0,0 +1,398 @@
+use std::{fmt::Write as _, io::Write};
+
+use crate::{
+    debugger::FrameView,
+    instructions::Instruction,
+    value::{ArrayValue, EnumValue, FunctionValue, MapValue, ObjectValue, OptionValue, RefTarget, RefValue, Value, VariableIdType},
+};
+
+/// Receives events from a running `Interpreter`, see `Interpreter::set_tracer`. Every method does nothing by default.
+pub trait Tracer {
+    /// Called before `instruction` runs in `frame`, with the variables it reads.
+    fn before_instruction(&mut self, _frame: &FrameView<'_>, _instruction: &Instruction, _read: &[(VariableIdType, &Value)]) {}
+    /// Called after `instruction` ran successfully in `frame`, with the new values of the variables it wrote.
+    /// The frame's instruction counter still points at the instruction.
+    fn after_instruction(&mut self, _frame: &FrameView<'_>, _instruction: &Instruction, _written: &[(VariableIdType, &Value)]) {}
+    /// Called after an instruction wrote through a reference to variables of `owner`, the frame `frames_up` frames below
+    /// the running one, with their new values. Writes through a reference to the running frame are listed by
+    /// `after_instruction`.
+    fn on_ref_write(&mut self, _owner: &FrameView<'_>, _frames_up: usize, _written: &[(VariableIdType, &Value)]) {}
+    /// Called when a new frame is entered, with its parameters already stored.
+    fn on_call(&mut self, _callee: &FrameView<'_>) {}
+    /// Called when `frame` finishes, with the value it returned.
+    fn on_return(&mut self, _frame: &FrameView<'_>, _value: Option<&Value>) {}
+}
+
+/// A tracer writing one JSON object per event and line, so traces from different interpreter versions can be diffed.
+#[derive(Debug)]
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+    depth: usize,
+    line: String,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesTracer {
+            writer,
+            depth: 0,
+            line: String::new(),
+            error: None,
+        }
+    }
+    /// The first error the writer returned. Tracing stops once writing fails.
+    pub fn error(&self) -> Option<&std::io::Error> {
+        self.error.as_ref()
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn begin(&mut self, event: &str, depth: usize, frame: &FrameView<'_>) {
+        self.line.clear();
+        let _ = write!(
+            self.line,
+            "{{\"event\":\"{event}\",\"depth\":{depth},\"function\":{},\"instruction\":{}",
+            frame.function_id, frame.instruction_counter
+        );
+    }
+    fn variables(&mut self, key: &str, variables: &[(VariableIdType, &Value)]) {
+        let _ = write!(self.line, ",\"{key}\":[");
+        for (i, (var_id, value)) in variables.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            let _ = write!(self.line, "{{\"id\":{var_id},\"type\":\"{}\",\"value\":", value.get_type());
+            write_json_value(&mut self.line, value);
+            self.line.push('}');
+        }
+        self.line.push(']');
+    }
+    fn finish(&mut self) {
+        self.line.push_str("}\n");
+        if self.error.is_none() {
+            if let Err(error) = self.writer.write_all(self.line.as_bytes()) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn before_instruction(&mut self, frame: &FrameView<'_>, instruction: &Instruction, read: &[(VariableIdType, &Value)]) {
+        self.begin("before", self.depth, frame);
+        self.line.push_str(",\"op\":");
+        write_json_string(&mut self.line, &format!("{instruction:?}"));
+        self.variables("read", read);
+        self.finish();
+    }
+    fn after_instruction(&mut self, frame: &FrameView<'_>, _instruction: &Instruction, written: &[(VariableIdType, &Value)]) {
+        self.begin("after", self.depth, frame);
+        self.variables("written", written);
+        self.finish();
+    }
+    fn on_ref_write(&mut self, owner: &FrameView<'_>, frames_up: usize, written: &[(VariableIdType, &Value)]) {
+        self.begin("write", self.depth.saturating_sub(frames_up), owner);
+        self.variables("written", written);
+        self.finish();
+    }
+    fn on_call(&mut self, callee: &FrameView<'_>) {
+        self.depth += 1;
+        self.begin("call", self.depth, callee);
+        let parameters: Vec<(VariableIdType, &Value)> =
+            callee.variables.iter().enumerate().map(|(i, v)| (i as VariableIdType, v)).collect();
+        self.variables("variables", &parameters);
+        self.finish();
+    }
+    fn on_return(&mut self, frame: &FrameView<'_>, value: Option<&Value>) {
+        self.begin("return", self.depth, frame);
+        self.line.push_str(",\"value\":");
+        match value {
+            Some(value) => write_json_value(&mut self.line, value),
+            None => self.line.push_str("null"),
+        }
+        self.finish();
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_json_array<T>(out: &mut String, values: &[T], mut write_one: impl FnMut(&mut String, &T)) {
+    out.push('[');
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_one(out, value);
+    }
+    out.push(']');
+}
+
+// Integers are written as strings so u64 values survive JSON parsers that read numbers as doubles.
+fn write_json_value(out: &mut String, value: &Value) {
+    match value {
+        Value::U8(v) => {
+            let _ = write!(out, "\"{v}\"");
+        }
+        Value::U16(v) => {
+            let _ = write!(out, "\"{v}\"");
+        }
+        Value::U32(v) => {
+            let _ = write!(out, "\"{v}\"");
+        }
+        Value::U64(v) => {
+            let _ = write!(out, "\"{v}\"");
+        }
+        Value::String(s) => write_json_string(out, s),
+        Value::Bool(b) => {
+            let _ = write!(out, "{b}");
+        }
+        Value::Array(array) => write_json_array_value(out, array),
+        Value::Struct(value) => write_json_array(out, value.fields(), write_json_value),
+        Value::Map(map) => write_json_map(out, map),
+        Value::Option(option) => write_json_option(out, option),
+        Value::Enum(value) => write_json_enum(out, value),
+        Value::Char(c) => write_json_string(out, c.encode_utf8(&mut [0; 4])),
+        Value::Bytes(bytes) => write_json_bytes(out, bytes),
+        Value::Ref(reference) => write_json_ref(out, reference),
+        Value::Object(object) => write_json_object(out, object),
+        Value::Function(function) => write_json_function(out, function),
+    }
+}
+
+// Function values are written as the function they name, `{"function":"f"}`, or null. Closures add their captures,
+// `{"function":"f","captures":[...]}`.
+fn write_json_function(out: &mut String, function: &FunctionValue) {
+    let Some(function_id) = function.function_id() else {
+        out.push_str("null");
+        return;
+    };
+    let _ = write!(out, "{{\"function\":\"{function_id}\"");
+    if !function.captures().is_empty() {
+        out.push_str(",\"captures\":");
+        write_json_array(out, function.captures(), write_json_value);
+    }
+    out.push('}');
+}
+
+// Objects are written as their handle, `{"object":"h"}`, or null. Following handles could loop forever.
+fn write_json_object(out: &mut String, object: &ObjectValue) {
+    let _ = match object.handle() {
+        Some(handle) => write!(out, "{{\"object\":\"{handle}\"}}"),
+        None => write!(out, "null"),
+    };
+}
+
+// References are written as where they point, `{"frame":"f","variable":"v"}` or `{"heap":"h"}`, or null.
+fn write_json_ref(out: &mut String, reference: &RefValue) {
+    let _ = match reference.target() {
+        Some(RefTarget::Variable { frame, var_id }) => write!(out, "{{\"frame\":\"{frame}\",\"variable\":\"{var_id}\"}}"),
+        Some(RefTarget::Heap(object_id)) => write!(out, "{{\"heap\":\"{object_id}\"}}"),
+        None => write!(out, "null"),
+    };
+}
+
+// Byte strings need not be valid utf-8, so they are written like u8 arrays.
+fn write_json_bytes(out: &mut String, bytes: &[u8]) {
+    write_json_array(out, bytes, |out, v| {
+        let _ = write!(out, "\"{v}\"");
+    });
+}
+
+fn write_json_option(out: &mut String, option: &OptionValue) {
+    match option.value() {
+        Some(value) => write_json_value(out, value),
+        None => out.push_str("null"),
+    }
+}
+
+fn write_json_enum(out: &mut String, value: &EnumValue) {
+    let _ = write!(out, "{{\"tag\":\"{}\",\"payload\":", value.tag());
+    match value.payload() {
+        Some(payload) => write_json_value(out, payload),
+        None => out.push_str("null"),
+    }
+    out.push('}');
+}
+
+// Keys need not be strings, so a map is written as an array of `[key, value]` pairs in key order.
+fn write_json_map(out: &mut String, map: &MapValue) {
+    let entries: Vec<_> = map.entries().collect();
+    write_json_array(out, &entries, |out, &(key, value)| {
+        out.push('[');
+        write_json_value(out, &key.to_value());
+        out.push(',');
+        write_json_value(out, value);
+        out.push(']');
+    });
+}
+
+fn write_json_array_value(out: &mut String, array: &ArrayValue) {
+    match array {
+        ArrayValue::BoolArray(values) => write_json_array(out, values, |out, v| {
+            let _ = write!(out, "{v}");
+        }),
+        ArrayValue::U8Array(values) => write_json_array(out, values, |out, v| {
+            let _ = write!(out, "\"{v}\"");
+        }),
+        ArrayValue::U16Array(values) => write_json_array(out, values, |out, v| {
+            let _ = write!(out, "\"{v}\"");
+        }),
+        ArrayValue::U32Array(values) => write_json_array(out, values, |out, v| {
+            let _ = write!(out, "\"{v}\"");
+        }),
+        ArrayValue::U64Array(values) => write_json_array(out, values, |out, v| {
+            let _ = write!(out, "\"{v}\"");
+        }),
+        ArrayValue::StringArray(values) => write_json_array(out, values, |out, v| write_json_string(out, v)),
+        ArrayValue::ArrayArray(_, values) => write_json_array(out, values, write_json_array_value),
+        ArrayValue::StructArray(_, values) => write_json_array(out, values, |out, v| write_json_array(out, v.fields(), write_json_value)),
+        ArrayValue::MapArray(_, _, values) => write_json_array(out, values, write_json_map),
+        ArrayValue::OptionArray(_, values) => write_json_array(out, values, write_json_option),
+        ArrayValue::EnumArray(_, values) => write_json_array(out, values, write_json_enum),
+        ArrayValue::CharArray(values) => write_json_array(out, values, |out, v| write_json_string(out, v.encode_utf8(&mut [0; 4]))),
+        ArrayValue::BytesArray(values) => write_json_array(out, values, |out, v| write_json_bytes(out, v)),
+        ArrayValue::RefArray(_, values) => write_json_array(out, values, write_json_ref),
+        ArrayValue::ObjectArray(_, values) => write_json_array(out, values, write_json_object),
+        ArrayValue::FunctionArray(_, values) => write_json_array(out, values, write_json_function),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        instructions::Instruction,
+        interpreter::{Function, Interpreter, Program},
+        tracer::*,
+        value::VariableType,
+    };
+
+    #[test]
+    fn test_json_lines_trace() {
+        let mut double = Function::new(&[VariableType::U64], Some(VariableType::U64));
+        double.set_instructions(vec![Instruction::Add(0, 0), Instruction::Return(0)]);
+        let mut main = Function::new(&[], None);
+        main.register_variable(VariableType::U64).unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::U64(21)),
+            Instruction::PushFunctionParameter(0),
+            Instruction::CallFunction(1, 0),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, double])).unwrap();
+        let buffer = SharedBuffer::default();
+        interpreter.set_tracer(Some(Box::new(JsonLinesTracer::new(buffer.clone()))));
+        interpreter.execute().unwrap();
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"event":"before","depth":0,"function":0,"instruction":0,"op":"SetI(0, U64(21))","read":[]}"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"event":"after","depth":0,"function":0,"instruction":0,"written":[{"id":0,"type":"u64","value":"21"}]}"#
+        );
+        assert!(
+            lines.contains(&r#"{"event":"call","depth":1,"function":1,"instruction":0,"variables":[{"id":0,"type":"u64","value":"21"}]}"#)
+        );
+        assert!(lines.contains(&r#"{"event":"return","depth":1,"function":1,"instruction":1,"value":"42"}"#));
+        assert_eq!(
+            lines.last().unwrap(),
+            &r#"{"event":"return","depth":0,"function":0,"instruction":3,"value":null}"#
+        );
+    }
+
+    #[test]
+    fn test_json_lines_trace_reads_array_index() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::Array(Box::new(VariableType::U64)), VariableType::U64])
+            .unwrap();
+        main.set_instructions(vec![
+            Instruction::SetI(0, Value::Array(ArrayValue::U64Array(vec![1, 2]))),
+            Instruction::SetI(1, Value::U64(1)),
+            Instruction::SetArrayIndexI(0, 1, Value::U64(5)),
+        ]);
+        let mut interpreter = Interpreter::new(Program::new(&[main])).unwrap();
+        let buffer = SharedBuffer::default();
+        interpreter.set_tracer(Some(Box::new(JsonLinesTracer::new(buffer.clone()))));
+        interpreter.execute().unwrap();
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[4],
+            r#"{"event":"before","depth":0,"function":0,"instruction":2,"op":"SetArrayIndexI(0, 1, U64(5))","read":[{"id":0,"type":"array<u64>","value":["1","2"]},{"id":1,"type":"u64","value":"1"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_lines_trace_writes_through_references() {
+        let mut main = Function::new(&[], None);
+        main.register_variables(&[VariableType::U64, VariableType::Ref(Box::new(VariableType::U64)), VariableType::U64])
+            .unwrap();
+        main.set_instructions(vec![
+            Instruction::MakeRef(1, 0),
+            Instruction::PushFunctionParameter(1),
+            Instruction::CallVoidFunction(1),
+            Instruction::SetI(2, Value::U64(9)),
+            Instruction::StoreRef(1, 2),
+        ]);
+        let mut set = Function::new(&[VariableType::Ref(Box::new(VariableType::U64))], None);
+        set.register_variable(VariableType::U64).unwrap();
+        set.set_instructions(vec![Instruction::SetI(1, Value::U64(7)), Instruction::StoreRef(0, 1)]);
+        let mut interpreter = Interpreter::new(Program::new(&[main, set])).unwrap();
+        let buffer = SharedBuffer::default();
+        interpreter.set_tracer(Some(Box::new(JsonLinesTracer::new(buffer.clone()))));
+        interpreter.execute().unwrap();
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        // the callee's write shows up as an event of the caller's frame
+        let write = lines
+            .iter()
+            .position(|line| {
+                *line == r#"{"event":"write","depth":0,"function":0,"instruction":3,"written":[{"id":0,"type":"u64","value":"7"}]}"#
+            })
+            .expect("the write through the reference is traced");
+        assert_eq!(
+            lines[write - 1],
+            r#"{"event":"after","depth":1,"function":1,"instruction":1,"written":[]}"#
+        );
+        assert!(
+            lines.contains(&r#"{"event":"after","depth":0,"function":0,"instruction":4,"written":[{"id":0,"type":"u64","value":"9"}]}"#)
+        );
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -1,9 +1,16 @@
+use std::{collections::BTreeMap, fmt};
+
 use crate::interpreter_error::InterpreterError;
 
 pub type StringIdType = u16;
 pub type VariableIdType = u16;
 pub type ArrayIdType = u16;
 pub type FunctionIdType = u16;
+pub type StructIdType = u16;
+pub type FieldIdType = u16;
+pub type EnumIdType = u16;
+pub type VariantIdType = u16;
+pub type HeapIdType = u32;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VariableType {
@@ -14,9 +21,100 @@ pub enum VariableType {
     String,
     Array(Box<VariableType>),
     Bool,
+    Struct(StructIdType),
+    /// Map from a key type to a value type. Keys must be integers, bools, strings, chars or bytes.
+    Map(Box<VariableType>, Box<VariableType>),
+    /// Either a value of the inner type or none.
+    Option(Box<VariableType>),
+    Enum(EnumIdType),
+    /// A unicode scalar value.
+    Char,
+    /// A byte string. Stored like a u8 array but copied, compared and concatenated as a whole.
+    Bytes,
+    /// A reference to a variable or heap object of the inner type.
+    Ref(Box<VariableType>),
+    /// A handle to a garbage collected heap object of the inner type, which any number of values may share.
+    Object(Box<VariableType>),
+    /// A function taking and returning the types of the signature.
+    Function(Box<Signature>),
+}
+
+impl VariableType {
+    /// The value a variable of this type starts with. Structs get the default of each field and enums their first
+    /// variant, so `structs` and `enums` must hold the definitions the type refers to. An unknown struct starts with no
+    /// fields and an unknown enum with no payload.
+    pub fn default_value(&self, structs: &[StructDefinition], enums: &[EnumDefinition]) -> Value {
+        match self {
+            VariableType::U8 => Value::U8(0),
+            VariableType::U16 => Value::U16(0),
+            VariableType::U32 => Value::U32(0),
+            VariableType::U64 => Value::U64(0),
+            VariableType::Bool => Value::Bool(false),
+            VariableType::String => Value::String(String::new()),
+            VariableType::Char => Value::Char('\0'),
+            VariableType::Bytes => Value::Bytes(Vec::new()),
+            VariableType::Ref(inner_type) => Value::Ref(RefValue::null(*inner_type.clone())),
+            VariableType::Object(inner_type) => Value::Object(ObjectValue::null(*inner_type.clone())),
+            VariableType::Function(signature) => Value::Function(FunctionValue::null(*signature.clone())),
+            VariableType::Array(arr_type) => Value::Array(ArrayValue::new(*arr_type.clone())),
+            VariableType::Struct(struct_id) => {
+                let fields = match structs.get(*struct_id as usize) {
+                    Some(definition) => definition.fields.iter().map(|field| field.default_value(structs, enums)).collect(),
+                    None => Vec::new(),
+                };
+                Value::Struct(StructValue::new(*struct_id, fields))
+            }
+            VariableType::Map(key_type, value_type) => Value::Map(MapValue::new(*key_type.clone(), *value_type.clone())),
+            VariableType::Option(inner_type) => Value::Option(OptionValue::none(*inner_type.clone())),
+            VariableType::Enum(enum_id) => {
+                let payload = match enums.get(*enum_id as usize).and_then(|definition| definition.variants.first()) {
+                    Some(Some(payload_type)) => Some(payload_type.default_value(structs, enums)),
+                    _ => None,
+                };
+                Value::Enum(EnumValue::new(*enum_id, 0, payload))
+            }
+        }
+    }
+    /// Whether values of this type can be used as map keys.
+    pub fn is_hashable(&self) -> bool {
+        matches!(
+            self,
+            VariableType::U8
+                | VariableType::U16
+                | VariableType::U32
+                | VariableType::U64
+                | VariableType::Bool
+                | VariableType::String
+                | VariableType::Char
+                | VariableType::Bytes
+        )
+    }
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariableType::U8 => write!(f, "u8"),
+            VariableType::U16 => write!(f, "u16"),
+            VariableType::U32 => write!(f, "u32"),
+            VariableType::U64 => write!(f, "u64"),
+            VariableType::String => write!(f, "string"),
+            VariableType::Array(inner_type) => write!(f, "array<{inner_type}>"),
+            VariableType::Bool => write!(f, "bool"),
+            VariableType::Struct(struct_id) => write!(f, "struct<{struct_id}>"),
+            VariableType::Map(key_type, value_type) => write!(f, "map<{key_type}, {value_type}>"),
+            VariableType::Option(inner_type) => write!(f, "option<{inner_type}>"),
+            VariableType::Enum(enum_id) => write!(f, "enum<{enum_id}>"),
+            VariableType::Char => write!(f, "char"),
+            VariableType::Bytes => write!(f, "bytes"),
+            VariableType::Ref(inner_type) => write!(f, "ref<{inner_type}>"),
+            VariableType::Object(inner_type) => write!(f, "object<{inner_type}>"),
+            VariableType::Function(signature) => write!(f, "{signature}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Bool(bool),
     U8(u8),
@@ -25,6 +123,15 @@ pub enum Value {
     U64(u64),
     String(String),
     Array(ArrayValue),
+    Struct(StructValue),
+    Map(MapValue),
+    Option(OptionValue),
+    Enum(EnumValue),
+    Char(char),
+    Bytes(Vec<u8>),
+    Ref(RefValue),
+    Object(ObjectValue),
+    Function(FunctionValue),
 }
 
 impl Value {
@@ -37,6 +144,92 @@ impl Value {
             Value::String(_) => VariableType::String,
             Value::Array(array) => array.get_type(),
             Value::Bool(_) => VariableType::Bool,
+            Value::Struct(value) => VariableType::Struct(value.struct_id),
+            Value::Map(map) => map.get_type(),
+            Value::Option(option) => option.get_type(),
+            Value::Enum(value) => VariableType::Enum(value.enum_id),
+            Value::Char(_) => VariableType::Char,
+            Value::Bytes(_) => VariableType::Bytes,
+            Value::Ref(reference) => reference.get_type(),
+            Value::Object(object) => object.get_type(),
+            Value::Function(function) => function.get_type(),
+        }
+    }
+    /// Checks the type of the value without building a `VariableType`, which allocates for arrays.
+    pub fn is_type(&self, var_type: &VariableType) -> bool {
+        match (self, var_type) {
+            (Value::U8(_), VariableType::U8)
+            | (Value::U16(_), VariableType::U16)
+            | (Value::U32(_), VariableType::U32)
+            | (Value::U64(_), VariableType::U64)
+            | (Value::String(_), VariableType::String)
+            | (Value::Bool(_), VariableType::Bool)
+            | (Value::Char(_), VariableType::Char)
+            | (Value::Bytes(_), VariableType::Bytes) => true,
+            (Value::Array(array), VariableType::Array(inner_type)) => array.has_inner_type(inner_type),
+            (Value::Struct(value), VariableType::Struct(struct_id)) => value.struct_id == *struct_id,
+            (Value::Map(map), VariableType::Map(key_type, value_type)) => map.has_types(key_type, value_type),
+            (Value::Option(option), VariableType::Option(inner_type)) => option.inner_type == **inner_type,
+            (Value::Enum(value), VariableType::Enum(enum_id)) => value.enum_id == *enum_id,
+            (Value::Ref(reference), VariableType::Ref(inner_type)) => reference.inner_type == **inner_type,
+            (Value::Object(object), VariableType::Object(inner_type)) => object.inner_type == **inner_type,
+            (Value::Function(function), VariableType::Function(signature)) => function.signature == **signature,
+            _ => false,
+        }
+    }
+    /// Whether every struct within the value has the fields its definition in `structs` lists, and every enum a
+    /// variant of its definition in `enums` with the payload that variant takes.
+    pub fn conforms_to(&self, structs: &[StructDefinition], enums: &[EnumDefinition]) -> bool {
+        match self {
+            Value::Struct(value) => value.conforms_to(structs, enums),
+            Value::Array(array) => array.conforms_to(structs, enums),
+            Value::Map(map) => map.conforms_to(structs, enums),
+            Value::Option(option) => option.value().is_none_or(|value| value.conforms_to(structs, enums)),
+            Value::Enum(value) => value.conforms_to(structs, enums),
+            Value::Function(function) => function.captures.iter().all(|capture| capture.conforms_to(structs, enums)),
+            _ => true,
+        }
+    }
+    pub fn is_same_type(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => a.has_inner_type(&b.get_inner_type()),
+            (Value::Struct(a), Value::Struct(b)) => a.struct_id == b.struct_id,
+            (Value::Map(a), Value::Map(b)) => a.has_types(&b.key_type, &b.value_type),
+            (Value::Option(a), Value::Option(b)) => a.inner_type == b.inner_type,
+            (Value::Enum(a), Value::Enum(b)) => a.enum_id == b.enum_id,
+            (Value::Ref(a), Value::Ref(b)) => a.inner_type == b.inner_type,
+            (Value::Object(a), Value::Object(b)) => a.inner_type == b.inner_type,
+            (Value::Function(a), Value::Function(b)) => a.signature == b.signature,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+    /// Approximate number of bytes owned by the value on the heap, not counting the `Value` itself.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Value::String(string) => string.len(),
+            Value::Bytes(bytes) => bytes.len(),
+            Value::Array(array) => array.heap_size(),
+            Value::Struct(value) => value.heap_size(),
+            Value::Map(map) => map.heap_size(),
+            Value::Option(option) => option.heap_size(),
+            Value::Enum(value) => value.heap_size(),
+            Value::Function(function) => function.heap_size(),
+            _ => 0,
+        }
+    }
+    /// Calls `visit` with where every reference and object handle within the value points, not following them.
+    /// Null references and handles are skipped.
+    pub fn visit_targets(&self, visit: &mut impl FnMut(RefTarget)) {
+        match self {
+            Value::Ref(reference) => reference.target.into_iter().for_each(visit),
+            Value::Object(object) => object.target().into_iter().for_each(visit),
+            Value::Array(array) => array.visit_targets(visit),
+            Value::Struct(value) => value.fields.iter().for_each(|field| field.visit_targets(visit)),
+            Value::Map(map) => map.entries.values().for_each(|value| value.visit_targets(visit)),
+            Value::Option(option) => option.value().into_iter().for_each(|value| value.visit_targets(visit)),
+            Value::Enum(value) => value.payload().into_iter().for_each(|payload| payload.visit_targets(visit)),
+            Value::Function(function) => function.captures.iter().for_each(|capture| capture.visit_targets(visit)),
+            _ => (),
         }
     }
     pub fn is_number(&self) -> bool {
@@ -62,7 +255,7 @@ impl Value {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ArrayValue {
     BoolArray(Vec<bool>), // could use packed bits
     U8Array(Vec<u8>),
@@ -71,6 +264,15 @@ pub enum ArrayValue {
     U64Array(Vec<u64>),
     StringArray(Vec<String>),
     ArrayArray(VariableType, Vec<ArrayValue>),
+    StructArray(StructIdType, Vec<StructValue>),
+    MapArray(VariableType, VariableType, Vec<MapValue>),
+    OptionArray(VariableType, Vec<OptionValue>),
+    EnumArray(EnumIdType, Vec<EnumValue>),
+    CharArray(Vec<char>),
+    BytesArray(Vec<Vec<u8>>),
+    RefArray(VariableType, Vec<RefValue>),
+    ObjectArray(VariableType, Vec<ObjectValue>),
+    FunctionArray(Signature, Vec<FunctionValue>),
 }
 
 impl ArrayValue {
@@ -83,6 +285,15 @@ impl ArrayValue {
             VariableType::String => ArrayValue::StringArray(Vec::new()),
             VariableType::Array(sub_array_type) => ArrayValue::ArrayArray(*sub_array_type, Vec::new()),
             VariableType::Bool => ArrayValue::BoolArray(Vec::new()),
+            VariableType::Struct(struct_id) => ArrayValue::StructArray(struct_id, Vec::new()),
+            VariableType::Map(key_type, value_type) => ArrayValue::MapArray(*key_type, *value_type, Vec::new()),
+            VariableType::Option(inner_type) => ArrayValue::OptionArray(*inner_type, Vec::new()),
+            VariableType::Enum(enum_id) => ArrayValue::EnumArray(enum_id, Vec::new()),
+            VariableType::Char => ArrayValue::CharArray(Vec::new()),
+            VariableType::Bytes => ArrayValue::BytesArray(Vec::new()),
+            VariableType::Ref(inner_type) => ArrayValue::RefArray(*inner_type, Vec::new()),
+            VariableType::Object(inner_type) => ArrayValue::ObjectArray(*inner_type, Vec::new()),
+            VariableType::Function(signature) => ArrayValue::FunctionArray(*signature, Vec::new()),
         }
     }
     pub fn get_inner_type(&self) -> VariableType {
@@ -94,23 +305,71 @@ impl ArrayValue {
             ArrayValue::U64Array(_) => VariableType::U64,
             ArrayValue::StringArray(_) => VariableType::String,
             ArrayValue::ArrayArray(a, _) => VariableType::Array(Box::from(a.clone())),
+            ArrayValue::StructArray(struct_id, _) => VariableType::Struct(*struct_id),
+            ArrayValue::MapArray(key_type, value_type, _) => VariableType::Map(Box::new(key_type.clone()), Box::new(value_type.clone())),
+            ArrayValue::OptionArray(inner_type, _) => VariableType::Option(Box::new(inner_type.clone())),
+            ArrayValue::EnumArray(enum_id, _) => VariableType::Enum(*enum_id),
+            ArrayValue::CharArray(_) => VariableType::Char,
+            ArrayValue::BytesArray(_) => VariableType::Bytes,
+            ArrayValue::RefArray(inner_type, _) => VariableType::Ref(Box::new(inner_type.clone())),
+            ArrayValue::ObjectArray(inner_type, _) => VariableType::Object(Box::new(inner_type.clone())),
+            ArrayValue::FunctionArray(signature, _) => VariableType::Function(Box::new(signature.clone())),
         }
     }
     pub fn get_type(&self) -> VariableType {
         VariableType::Array(Box::from(self.get_inner_type()))
     }
-    pub fn set_index(&mut self, index: usize, value: Value) -> Result<(), InterpreterError> {
-        match (self, value) {
-            (ArrayValue::U8Array(a), Value::U8(v)) => *a.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))? = v,
-            (ArrayValue::U16Array(a), Value::U16(v)) => *a.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))? = v,
-            (ArrayValue::U32Array(a), Value::U32(v)) => *a.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))? = v,
-            (ArrayValue::U64Array(a), Value::U64(v)) => *a.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))? = v,
-            (ArrayValue::BoolArray(a), Value::Bool(v)) => *a.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))? = v,
-            (ArrayValue::StringArray(a), Value::String(v)) => *a.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))? = v,
-            (s, v) => return Err(InterpreterError::ArraySetValueWithIncompatibleType(s.get_type(), v.get_type())),
+    pub fn has_inner_type(&self, inner_type: &VariableType) -> bool {
+        match (self, inner_type) {
+            (ArrayValue::BoolArray(_), VariableType::Bool)
+            | (ArrayValue::U8Array(_), VariableType::U8)
+            | (ArrayValue::U16Array(_), VariableType::U16)
+            | (ArrayValue::U32Array(_), VariableType::U32)
+            | (ArrayValue::U64Array(_), VariableType::U64)
+            | (ArrayValue::StringArray(_), VariableType::String)
+            | (ArrayValue::CharArray(_), VariableType::Char)
+            | (ArrayValue::BytesArray(_), VariableType::Bytes) => true,
+            (ArrayValue::ArrayArray(a, _), VariableType::Array(b)) => a == b.as_ref(),
+            (ArrayValue::StructArray(a, _), VariableType::Struct(b)) => a == b,
+            (ArrayValue::MapArray(k, v, _), VariableType::Map(key_type, value_type)) => k == key_type.as_ref() && v == value_type.as_ref(),
+            (ArrayValue::OptionArray(a, _), VariableType::Option(b)) => a == b.as_ref(),
+            (ArrayValue::EnumArray(a, _), VariableType::Enum(b)) => a == b,
+            (ArrayValue::RefArray(a, _), VariableType::Ref(b)) => a == b.as_ref(),
+            (ArrayValue::ObjectArray(a, _), VariableType::Object(b)) => a == b.as_ref(),
+            (ArrayValue::FunctionArray(a, _), VariableType::Function(b)) => a == b.as_ref(),
+            _ => false,
         }
+    }
+    pub fn set_index(&mut self, index: usize, value: Value) -> Result<(), InterpreterError> {
+        self.replace_index(index, value)?;
         Ok(())
     }
+    /// Sets the element at `index`, returning the value it replaced.
+    pub fn replace_index(&mut self, index: usize, value: Value) -> Result<Value, InterpreterError> {
+        fn replace<T>(array: &mut [T], index: usize, value: T) -> Result<T, InterpreterError> {
+            let element = array.get_mut(index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))?;
+            Ok(std::mem::replace(element, value))
+        }
+        Ok(match (self, value) {
+            (ArrayValue::U8Array(a), Value::U8(v)) => Value::U8(replace(a, index, v)?),
+            (ArrayValue::U16Array(a), Value::U16(v)) => Value::U16(replace(a, index, v)?),
+            (ArrayValue::U32Array(a), Value::U32(v)) => Value::U32(replace(a, index, v)?),
+            (ArrayValue::U64Array(a), Value::U64(v)) => Value::U64(replace(a, index, v)?),
+            (ArrayValue::BoolArray(a), Value::Bool(v)) => Value::Bool(replace(a, index, v)?),
+            (ArrayValue::StringArray(a), Value::String(v)) => Value::String(replace(a, index, v)?),
+            (ArrayValue::CharArray(a), Value::Char(v)) => Value::Char(replace(a, index, v)?),
+            (ArrayValue::BytesArray(a), Value::Bytes(v)) => Value::Bytes(replace(a, index, v)?),
+            (ArrayValue::ArrayArray(t, a), Value::Array(v)) if v.has_inner_type(t) => Value::Array(replace(a, index, v)?),
+            (ArrayValue::StructArray(t, a), Value::Struct(v)) if v.struct_id == *t => Value::Struct(replace(a, index, v)?),
+            (ArrayValue::MapArray(k, t, a), Value::Map(v)) if v.has_types(k, t) => Value::Map(replace(a, index, v)?),
+            (ArrayValue::OptionArray(t, a), Value::Option(v)) if v.inner_type == *t => Value::Option(replace(a, index, v)?),
+            (ArrayValue::EnumArray(t, a), Value::Enum(v)) if v.enum_id == *t => Value::Enum(replace(a, index, v)?),
+            (ArrayValue::RefArray(t, a), Value::Ref(v)) if v.inner_type == *t => Value::Ref(replace(a, index, v)?),
+            (ArrayValue::ObjectArray(t, a), Value::Object(v)) if v.inner_type == *t => Value::Object(replace(a, index, v)?),
+            (ArrayValue::FunctionArray(t, a), Value::Function(v)) if v.signature == *t => Value::Function(replace(a, index, v)?),
+            (s, v) => return Err(InterpreterError::ArraySetValueWithIncompatibleType(s.get_type(), v.get_type())),
+        })
+    }
 
     pub fn push(&mut self, value: Value) -> Result<(), InterpreterError> {
         match (self, value) {
@@ -120,6 +379,16 @@ impl ArrayValue {
             (ArrayValue::U64Array(a), Value::U64(v)) => a.push(v),
             (ArrayValue::BoolArray(a), Value::Bool(v)) => a.push(v),
             (ArrayValue::StringArray(a), Value::String(v)) => a.push(v),
+            (ArrayValue::CharArray(a), Value::Char(v)) => a.push(v),
+            (ArrayValue::BytesArray(a), Value::Bytes(v)) => a.push(v),
+            (ArrayValue::ArrayArray(t, a), Value::Array(v)) if v.has_inner_type(t) => a.push(v),
+            (ArrayValue::StructArray(t, a), Value::Struct(v)) if v.struct_id == *t => a.push(v),
+            (ArrayValue::MapArray(k, t, a), Value::Map(v)) if v.has_types(k, t) => a.push(v),
+            (ArrayValue::OptionArray(t, a), Value::Option(v)) if v.inner_type == *t => a.push(v),
+            (ArrayValue::EnumArray(t, a), Value::Enum(v)) if v.enum_id == *t => a.push(v),
+            (ArrayValue::RefArray(t, a), Value::Ref(v)) if v.inner_type == *t => a.push(v),
+            (ArrayValue::ObjectArray(t, a), Value::Object(v)) if v.inner_type == *t => a.push(v),
+            (ArrayValue::FunctionArray(t, a), Value::Function(v)) if v.signature == *t => a.push(v),
             (s, v) => return Err(InterpreterError::ArrayTypeIncompatibleWithPushValue(s.get_type(), v.get_type())),
         }
         Ok(())
@@ -133,7 +402,16 @@ impl ArrayValue {
                 ArrayValue::U32Array(v) => Value::U32(*v.get(index)?),
                 ArrayValue::U64Array(v) => Value::U64(*v.get(index)?),
                 ArrayValue::StringArray(v) => Value::String(v.get(index)?.clone()),
+                ArrayValue::CharArray(v) => Value::Char(*v.get(index)?),
+                ArrayValue::BytesArray(v) => Value::Bytes(v.get(index)?.clone()),
+                ArrayValue::RefArray(_, v) => Value::Ref(v.get(index)?.clone()),
+                ArrayValue::ObjectArray(_, v) => Value::Object(v.get(index)?.clone()),
+                ArrayValue::FunctionArray(_, v) => Value::Function(v.get(index)?.clone()),
                 ArrayValue::ArrayArray(_, v) => Value::Array(v.get(index)?.clone()),
+                ArrayValue::StructArray(_, v) => Value::Struct(v.get(index)?.clone()),
+                ArrayValue::MapArray(_, _, v) => Value::Map(v.get(index)?.clone()),
+                ArrayValue::OptionArray(_, v) => Value::Option(v.get(index)?.clone()),
+                ArrayValue::EnumArray(_, v) => Value::Enum(v.get(index)?.clone()),
             })
         }
         get_index_internal(self, index).ok_or(InterpreterError::ArrayIndexBeyondBounds(index))
@@ -148,10 +426,600 @@ impl ArrayValue {
             ArrayValue::U64Array(a) => a.len(),
             ArrayValue::StringArray(a) => a.len(),
             ArrayValue::ArrayArray(_, a) => a.len(),
+            ArrayValue::StructArray(_, a) => a.len(),
+            ArrayValue::MapArray(_, _, a) => a.len(),
+            ArrayValue::OptionArray(_, a) => a.len(),
+            ArrayValue::EnumArray(_, a) => a.len(),
+            ArrayValue::CharArray(a) => a.len(),
+            ArrayValue::BytesArray(a) => a.len(),
+            ArrayValue::RefArray(_, a) => a.len(),
+            ArrayValue::ObjectArray(_, a) => a.len(),
+            ArrayValue::FunctionArray(_, a) => a.len(),
         }
     }
 
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Number of bytes a single element occupies in the array's buffer.
+    pub fn element_size(&self) -> usize {
+        match self {
+            ArrayValue::BoolArray(_) => size_of::<bool>(),
+            ArrayValue::U8Array(_) => size_of::<u8>(),
+            ArrayValue::U16Array(_) => size_of::<u16>(),
+            ArrayValue::U32Array(_) => size_of::<u32>(),
+            ArrayValue::U64Array(_) => size_of::<u64>(),
+            ArrayValue::StringArray(_) => size_of::<String>(),
+            ArrayValue::ArrayArray(_, _) => size_of::<ArrayValue>(),
+            ArrayValue::StructArray(_, _) => size_of::<StructValue>(),
+            ArrayValue::MapArray(_, _, _) => size_of::<MapValue>(),
+            ArrayValue::OptionArray(_, _) => size_of::<OptionValue>(),
+            ArrayValue::EnumArray(_, _) => size_of::<EnumValue>(),
+            ArrayValue::CharArray(_) => size_of::<char>(),
+            ArrayValue::BytesArray(_) => size_of::<Vec<u8>>(),
+            ArrayValue::RefArray(_, _) => size_of::<RefValue>(),
+            ArrayValue::ObjectArray(_, _) => size_of::<ObjectValue>(),
+            ArrayValue::FunctionArray(_, _) => size_of::<FunctionValue>(),
+        }
+    }
+
+    fn visit_targets(&self, visit: &mut impl FnMut(RefTarget)) {
+        match self {
+            ArrayValue::RefArray(_, references) => references.iter().filter_map(|reference| reference.target).for_each(visit),
+            ArrayValue::ObjectArray(_, objects) => objects.iter().filter_map(ObjectValue::target).for_each(visit),
+            ArrayValue::ArrayArray(_, arrays) => arrays.iter().for_each(|array| array.visit_targets(visit)),
+            ArrayValue::StructArray(_, values) => values
+                .iter()
+                .for_each(|value| value.fields.iter().for_each(|field| field.visit_targets(visit))),
+            ArrayValue::MapArray(_, _, maps) => maps
+                .iter()
+                .for_each(|map| map.entries.values().for_each(|value| value.visit_targets(visit))),
+            ArrayValue::OptionArray(_, options) => options
+                .iter()
+                .for_each(|option| option.value().into_iter().for_each(|value| value.visit_targets(visit))),
+            ArrayValue::EnumArray(_, values) => values
+                .iter()
+                .for_each(|value| value.payload().into_iter().for_each(|payload| payload.visit_targets(visit))),
+            ArrayValue::FunctionArray(_, values) => values
+                .iter()
+                .for_each(|value| value.captures.iter().for_each(|capture| capture.visit_targets(visit))),
+            _ => (),
+        }
+    }
+
+    fn conforms_to(&self, structs: &[StructDefinition], enums: &[EnumDefinition]) -> bool {
+        match self {
+            ArrayValue::ArrayArray(_, arrays) => arrays.iter().all(|array| array.conforms_to(structs, enums)),
+            ArrayValue::StructArray(_, values) => values.iter().all(|value| value.conforms_to(structs, enums)),
+            ArrayValue::MapArray(_, _, maps) => maps.iter().all(|map| map.conforms_to(structs, enums)),
+            ArrayValue::OptionArray(_, options) => options
+                .iter()
+                .all(|option| option.value().is_none_or(|value| value.conforms_to(structs, enums))),
+            ArrayValue::EnumArray(_, values) => values.iter().all(|value| value.conforms_to(structs, enums)),
+            ArrayValue::FunctionArray(_, values) => values
+                .iter()
+                .all(|value| value.captures.iter().all(|capture| capture.conforms_to(structs, enums))),
+            _ => true,
+        }
+    }
+
+    /// Approximate number of bytes owned by the array's elements, including nested strings, arrays and other values.
+    pub fn heap_size(&self) -> usize {
+        let nested = match self {
+            ArrayValue::StringArray(a) => a.iter().map(String::len).sum(),
+            ArrayValue::BytesArray(a) => a.iter().map(Vec::len).sum(),
+            ArrayValue::ArrayArray(_, a) => a.iter().map(ArrayValue::heap_size).sum(),
+            ArrayValue::StructArray(_, a) => a.iter().map(StructValue::heap_size).sum(),
+            ArrayValue::MapArray(_, _, a) => a.iter().map(MapValue::heap_size).sum(),
+            ArrayValue::OptionArray(_, a) => a.iter().map(OptionValue::heap_size).sum(),
+            ArrayValue::EnumArray(_, a) => a.iter().map(EnumValue::heap_size).sum(),
+            ArrayValue::FunctionArray(_, a) => a.iter().map(FunctionValue::heap_size).sum(),
+            _ => 0,
+        };
+        self.len() * self.element_size() + nested
+    }
+}
+
+/// A user defined record type: an optional name and the type of each field, addressed by index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructDefinition {
+    name: Option<String>,
+    fields: Vec<VariableType>,
+}
+
+impl StructDefinition {
+    pub fn new(fields: &[VariableType]) -> Self {
+        StructDefinition {
+            name: None,
+            fields: fields.to_vec(),
+        }
+    }
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    pub fn fields(&self) -> &[VariableType] {
+        &self.fields
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructValue {
+    struct_id: StructIdType,
+    fields: Vec<Value>,
+}
+
+impl StructValue {
+    pub fn new(struct_id: StructIdType, fields: Vec<Value>) -> Self {
+        StructValue { struct_id, fields }
+    }
+    pub fn struct_id(&self) -> StructIdType {
+        self.struct_id
+    }
+    pub fn fields(&self) -> &[Value] {
+        &self.fields
+    }
+    pub fn get_field(&self, field_id: FieldIdType) -> Result<&Value, InterpreterError> {
+        self.fields
+            .get(field_id as usize)
+            .ok_or(InterpreterError::StructFieldDoesNotExist(self.struct_id, field_id))
+    }
+    /// Sets a field to a value of the same type, returning the value it replaced.
+    pub fn replace_field(&mut self, field_id: FieldIdType, value: Value) -> Result<Value, InterpreterError> {
+        let struct_id = self.struct_id;
+        let field = self
+            .fields
+            .get_mut(field_id as usize)
+            .ok_or(InterpreterError::StructFieldDoesNotExist(struct_id, field_id))?;
+        if !field.is_same_type(&value) {
+            return Err(InterpreterError::StructFieldTypeMismatch(field.get_type(), value.get_type()));
+        }
+        Ok(std::mem::replace(field, value))
+    }
+    fn conforms_to(&self, structs: &[StructDefinition], enums: &[EnumDefinition]) -> bool {
+        let Some(definition) = structs.get(self.struct_id as usize) else {
+            return false;
+        };
+        definition.fields.len() == self.fields.len()
+            && definition
+                .fields
+                .iter()
+                .zip(self.fields.iter())
+                .all(|(field_type, field)| field.is_type(field_type) && field.conforms_to(structs, enums))
+    }
+    /// Approximate number of bytes owned by the fields on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.fields.len() * size_of::<Value>() + self.fields.iter().map(Value::heap_size).sum::<usize>()
+    }
+}
+
+/// A value usable as a map key. Ordered so maps iterate, compare and print the same way every run.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MapKey {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    String(String),
+    Char(char),
+    Bytes(Vec<u8>),
+}
+
+impl MapKey {
+    pub fn from_value(value: Value) -> Result<MapKey, InterpreterError> {
+        Ok(match value {
+            Value::Bool(v) => MapKey::Bool(v),
+            Value::U8(v) => MapKey::U8(v),
+            Value::U16(v) => MapKey::U16(v),
+            Value::U32(v) => MapKey::U32(v),
+            Value::U64(v) => MapKey::U64(v),
+            Value::String(v) => MapKey::String(v),
+            Value::Char(v) => MapKey::Char(v),
+            Value::Bytes(v) => MapKey::Bytes(v),
+            v => return Err(InterpreterError::MapKeyNotHashable(v.get_type())),
+        })
+    }
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Bool(v) => Value::Bool(*v),
+            MapKey::U8(v) => Value::U8(*v),
+            MapKey::U16(v) => Value::U16(*v),
+            MapKey::U32(v) => Value::U32(*v),
+            MapKey::U64(v) => Value::U64(*v),
+            MapKey::String(v) => Value::String(v.clone()),
+            MapKey::Char(v) => Value::Char(*v),
+            MapKey::Bytes(v) => Value::Bytes(v.clone()),
+        }
+    }
+    pub fn heap_size(&self) -> usize {
+        match self {
+            MapKey::String(v) => v.len(),
+            MapKey::Bytes(v) => v.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Entries of a map, ordered by key. Keys and values are checked against the map's types on insert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapValue {
+    key_type: VariableType,
+    value_type: VariableType,
+    entries: BTreeMap<MapKey, Value>,
+}
+
+impl MapValue {
+    pub fn new(key_type: VariableType, value_type: VariableType) -> Self {
+        MapValue {
+            key_type,
+            value_type,
+            entries: BTreeMap::new(),
+        }
+    }
+    pub fn key_type(&self) -> &VariableType {
+        &self.key_type
+    }
+    pub fn value_type(&self) -> &VariableType {
+        &self.value_type
+    }
+    pub fn get_type(&self) -> VariableType {
+        VariableType::Map(Box::new(self.key_type.clone()), Box::new(self.value_type.clone()))
+    }
+    pub fn has_types(&self, key_type: &VariableType, value_type: &VariableType) -> bool {
+        self.key_type == *key_type && self.value_type == *value_type
+    }
+    pub fn entries(&self) -> impl Iterator<Item = (&MapKey, &Value)> {
+        self.entries.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn key(&self, key: Value) -> Result<MapKey, InterpreterError> {
+        if !key.is_type(&self.key_type) {
+            return Err(InterpreterError::MapKeyTypeMismatch(self.key_type.clone(), key.get_type()));
+        }
+        MapKey::from_value(key)
+    }
+    /// Inserts or replaces the entry for `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<Option<Value>, InterpreterError> {
+        let key = self.key(key)?;
+        if !value.is_type(&self.value_type) {
+            return Err(InterpreterError::MapValueTypeMismatch(self.value_type.clone(), value.get_type()));
+        }
+        Ok(self.entries.insert(key, value))
+    }
+    pub fn get(&self, key: Value) -> Result<Option<&Value>, InterpreterError> {
+        let key = self.key(key)?;
+        Ok(self.entries.get(&key))
+    }
+    pub fn remove(&mut self, key: Value) -> Result<Option<(MapKey, Value)>, InterpreterError> {
+        let key = self.key(key)?;
+        Ok(self.entries.remove_entry(&key))
+    }
+    pub fn contains(&self, key: Value) -> Result<bool, InterpreterError> {
+        let key = self.key(key)?;
+        Ok(self.entries.contains_key(&key))
+    }
+    /// The keys in order, as an array of the key type.
+    pub fn keys(&self) -> Result<ArrayValue, InterpreterError> {
+        let mut keys = ArrayValue::new(self.key_type.clone());
+        for key in self.entries.keys() {
+            keys.push(key.to_value())?;
+        }
+        Ok(keys)
+    }
+    /// The values in key order, as an array of the value type.
+    pub fn values(&self) -> Result<ArrayValue, InterpreterError> {
+        let mut values = ArrayValue::new(self.value_type.clone());
+        for value in self.entries.values() {
+            values.push(value.clone())?;
+        }
+        Ok(values)
+    }
+
+    fn conforms_to(&self, structs: &[StructDefinition], enums: &[EnumDefinition]) -> bool {
+        self.entries.values().all(|value| value.conforms_to(structs, enums))
+    }
+    /// Approximate number of bytes owned by the entries on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.entries.len() * Self::entry_size()
+            + self
+                .entries
+                .iter()
+                .map(|(key, value)| key.heap_size() + value.heap_size())
+                .sum::<usize>()
+    }
+    /// Number of bytes a single entry occupies, not counting what its key and value own.
+    pub fn entry_size() -> usize {
+        size_of::<MapKey>() + size_of::<Value>()
+    }
+}
+
+/// A value that may be missing. Remembers its inner type while empty, so a none still has a full type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionValue {
+    inner_type: VariableType,
+    value: Option<Box<Value>>,
+}
+
+impl OptionValue {
+    pub fn none(inner_type: VariableType) -> Self {
+        OptionValue { inner_type, value: None }
+    }
+    pub fn some(value: Value) -> Self {
+        OptionValue {
+            inner_type: value.get_type(),
+            value: Some(Box::new(value)),
+        }
+    }
+    pub fn inner_type(&self) -> &VariableType {
+        &self.inner_type
+    }
+    pub fn get_type(&self) -> VariableType {
+        VariableType::Option(Box::new(self.inner_type.clone()))
+    }
+    pub fn is_some(&self) -> bool {
+        self.value.is_some()
+    }
+    pub fn value(&self) -> Option<&Value> {
+        self.value.as_deref()
+    }
+    /// Sets the option to `value` of the inner type, or to none, returning the value it held.
+    pub fn replace(&mut self, value: Option<Value>) -> Result<Option<Value>, InterpreterError> {
+        if let Some(value) = &value {
+            if !value.is_type(&self.inner_type) {
+                return Err(InterpreterError::OptionValueTypeMismatch(self.inner_type.clone(), value.get_type()));
+            }
+        }
+        Ok(std::mem::replace(&mut self.value, value.map(Box::new)).map(|value| *value))
+    }
+    /// Approximate number of bytes owned by the held value on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.value().map_or(0, |value| size_of::<Value>() + value.heap_size())
+    }
+}
+
+/// A user defined sum type: an optional name and, for each variant in tag order, the type of its payload if it
+/// carries one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumDefinition {
+    name: Option<String>,
+    variants: Vec<Option<VariableType>>,
+}
+
+impl EnumDefinition {
+    pub fn new(variants: &[Option<VariableType>]) -> Self {
+        EnumDefinition {
+            name: None,
+            variants: variants.to_vec(),
+        }
+    }
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    pub fn variants(&self) -> &[Option<VariableType>] {
+        &self.variants
+    }
+    /// The payload type of a variant, `None` inside if it carries no payload.
+    pub fn variant(&self, enum_id: EnumIdType, tag: VariantIdType) -> Result<Option<&VariableType>, InterpreterError> {
+        self.variants
+            .get(tag as usize)
+            .map(Option::as_ref)
+            .ok_or(InterpreterError::EnumVariantDoesNotExist(enum_id, tag))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue {
+    enum_id: EnumIdType,
+    tag: VariantIdType,
+    payload: Option<Box<Value>>,
+}
+
+impl EnumValue {
+    pub fn new(enum_id: EnumIdType, tag: VariantIdType, payload: Option<Value>) -> Self {
+        EnumValue {
+            enum_id,
+            tag,
+            payload: payload.map(Box::new),
+        }
+    }
+    pub fn enum_id(&self) -> EnumIdType {
+        self.enum_id
+    }
+    pub fn tag(&self) -> VariantIdType {
+        self.tag
+    }
+    pub fn payload(&self) -> Option<&Value> {
+        self.payload.as_deref()
+    }
+    /// The payload, if the value is the variant `tag`.
+    pub fn get_payload(&self, tag: VariantIdType) -> Result<&Value, InterpreterError> {
+        if self.tag != tag {
+            return Err(InterpreterError::EnumTagMismatch(tag, self.tag));
+        }
+        self.payload().ok_or(InterpreterError::EnumVariantHasNoPayload(self.enum_id, tag))
+    }
+    fn conforms_to(&self, structs: &[StructDefinition], enums: &[EnumDefinition]) -> bool {
+        let Some(Ok(payload_type)) = enums
+            .get(self.enum_id as usize)
+            .map(|definition| definition.variant(self.enum_id, self.tag))
+        else {
+            return false;
+        };
+        match (payload_type, self.payload()) {
+            (Some(payload_type), Some(payload)) => payload.is_type(payload_type) && payload.conforms_to(structs, enums),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+    /// Approximate number of bytes owned by the payload on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.payload().map_or(0, |payload| size_of::<Value>() + payload.heap_size())
+    }
+}
+
+/// Where a reference points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefTarget {
+    /// A variable of the frame at this depth of the callstack, the entry function's frame being depth 0.
+    Variable { frame: usize, var_id: VariableIdType },
+    /// An object on the interpreter's heap.
+    Heap(HeapIdType),
+}
+
+/// A reference to a value of `inner_type`, or a null reference until one is assigned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefValue {
+    inner_type: VariableType,
+    target: Option<RefTarget>,
+}
+
+impl RefValue {
+    pub fn null(inner_type: VariableType) -> Self {
+        RefValue { inner_type, target: None }
+    }
+    pub fn new(inner_type: VariableType, target: RefTarget) -> Self {
+        RefValue {
+            inner_type,
+            target: Some(target),
+        }
+    }
+    pub fn inner_type(&self) -> &VariableType {
+        &self.inner_type
+    }
+    pub fn get_type(&self) -> VariableType {
+        VariableType::Ref(Box::new(self.inner_type.clone()))
+    }
+    pub fn target(&self) -> Option<RefTarget> {
+        self.target
+    }
+}
+
+/// A handle to a heap object holding a value of `inner_type`, or a null handle until an object is assigned. Copies
+/// of a handle share the object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectValue {
+    inner_type: VariableType,
+    handle: Option<HeapIdType>,
+}
+
+impl ObjectValue {
+    pub fn null(inner_type: VariableType) -> Self {
+        ObjectValue { inner_type, handle: None }
+    }
+    pub fn new(inner_type: VariableType, handle: HeapIdType) -> Self {
+        ObjectValue {
+            inner_type,
+            handle: Some(handle),
+        }
+    }
+    pub fn inner_type(&self) -> &VariableType {
+        &self.inner_type
+    }
+    pub fn get_type(&self) -> VariableType {
+        VariableType::Object(Box::new(self.inner_type.clone()))
+    }
+    pub fn handle(&self) -> Option<HeapIdType> {
+        self.handle
+    }
+    fn target(&self) -> Option<RefTarget> {
+        self.handle.map(RefTarget::Heap)
+    }
+}
+
+/// The parameter types a function takes and the type it returns, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    parameters: Vec<VariableType>,
+    return_type: Option<VariableType>,
+}
+
+impl Signature {
+    pub fn new(parameters: &[VariableType], return_type: Option<VariableType>) -> Self {
+        Signature {
+            parameters: parameters.to_vec(),
+            return_type,
+        }
+    }
+    pub fn parameters(&self) -> &[VariableType] {
+        &self.parameters
+    }
+    pub fn return_type(&self) -> Option<&VariableType> {
+        self.return_type.as_ref()
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fn(")?;
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{parameter}")?;
+        }
+        write!(f, ")")?;
+        match &self.return_type {
+            Some(return_type) => write!(f, " -> {return_type}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A function of the program with `signature`, or a null function until one is assigned. Calling it checks the
+/// function really has that signature. A closure also carries captured values, which the function receives in the
+/// variables following its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionValue {
+    signature: Signature,
+    function_id: Option<FunctionIdType>,
+    captures: Vec<Value>,
+}
+
+impl FunctionValue {
+    pub fn null(signature: Signature) -> Self {
+        FunctionValue {
+            signature,
+            function_id: None,
+            captures: Vec::new(),
+        }
+    }
+    pub fn new(signature: Signature, function_id: FunctionIdType) -> Self {
+        FunctionValue::closure(signature, function_id, Vec::new())
+    }
+    pub fn closure(signature: Signature, function_id: FunctionIdType, captures: Vec<Value>) -> Self {
+        FunctionValue {
+            signature,
+            function_id: Some(function_id),
+            captures,
+        }
+    }
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+    pub fn get_type(&self) -> VariableType {
+        VariableType::Function(Box::new(self.signature.clone()))
+    }
+    pub fn function_id(&self) -> Option<FunctionIdType> {
+        self.function_id
+    }
+    pub fn captures(&self) -> &[Value] {
+        &self.captures
+    }
+    /// Approximate number of bytes owned by the captures on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.captures.len() * size_of::<Value>() + self.captures.iter().map(Value::heap_size).sum::<usize>()
+    }
 }